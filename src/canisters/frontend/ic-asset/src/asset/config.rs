@@ -1,7 +1,9 @@
+use crate::asset::content_encoder::ContentEncoder;
 use crate::error::AssetLoadConfigError;
 use crate::error::AssetLoadConfigError::{LoadRuleFailed, MalformedAssetConfigFile};
 use crate::error::GetAssetConfigError;
 use crate::error::GetAssetConfigError::{AssetConfigNotFound, InvalidPath};
+use candid::CandidType;
 use derivative::Derivative;
 use globset::GlobMatcher;
 use serde::{Deserialize, Serialize};
@@ -25,10 +27,22 @@ pub struct AssetConfig {
     pub(crate) enable_aliasing: Option<bool>,
     #[derivative(Default(value = "Some(true)"))]
     pub(crate) allow_raw_access: Option<bool>,
+    pub(crate) encodings: Option<Vec<ContentEncoder>>,
+    pub(crate) redirect: Option<RedirectConfig>,
+    pub(crate) rewrite_from: Option<Vec<String>>,
 }
 
 pub(crate) type HeadersConfig = BTreeMap<String, String>;
 
+/// Makes the canister answer requests for the asset with a redirect instead of its content.
+#[derive(CandidType, Deserialize, Serialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct RedirectConfig {
+    pub(crate) location: String,
+    pub(crate) status_code: u16,
+}
+
+const REDIRECT_STATUS_CODES: [u16; 4] = [301, 302, 307, 308];
+
 #[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq, Eq)]
 pub(crate) struct CacheConfig {
     pub(crate) max_age: Option<u64>,
@@ -64,6 +78,15 @@ pub struct AssetConfigRule {
     /// Redirects the traffic from .raw.icp0.io domain to .icp0.io
     #[serde(skip_serializing_if = "Option::is_none")]
     allow_raw_access: Option<bool>,
+    /// Content encodings to try in addition to identity, replaces the defaults for the media type
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encodings: Option<Vec<ContentEncoder>>,
+    /// Serves a redirect to another location instead of the asset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redirect: Option<RedirectConfig>,
+    /// Path prefixes, e.g. `/app`, for which the asset is served if no other asset matches
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rewrite_from: Option<Vec<String>>,
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -260,6 +283,18 @@ impl AssetConfig {
         if other.allow_raw_access.is_some() {
             self.allow_raw_access = other.allow_raw_access;
         }
+
+        if let Some(encodings) = &other.encodings {
+            self.encodings = Some(encodings.to_owned());
+        }
+
+        if let Some(redirect) = &other.redirect {
+            self.redirect = Some(redirect.to_owned());
+        }
+
+        if let Some(rewrite_from) = &other.rewrite_from {
+            self.rewrite_from = Some(rewrite_from.to_owned());
+        }
         self
     }
 }
@@ -267,7 +302,10 @@ impl AssetConfig {
 /// This module contains various utilities needed for serialization/deserialization
 /// and pretty-printing of the `AssetConfigRule` data structure.
 mod rule_utils {
-    use super::{AssetConfig, AssetConfigRule, CacheConfig, HeadersConfig, Maybe};
+    use super::{
+        AssetConfig, AssetConfigRule, CacheConfig, ContentEncoder, HeadersConfig, Maybe,
+        RedirectConfig, REDIRECT_STATUS_CODES,
+    };
     use crate::error::LoadRuleError;
     use globset::{Glob, GlobMatcher};
     use serde::{Deserialize, Serializer};
@@ -350,6 +388,9 @@ mod rule_utils {
         enable_aliasing: Option<bool>,
         #[serde(default = "super::default_raw_access")]
         allow_raw_access: Option<bool>,
+        encodings: Option<Vec<ContentEncoder>>,
+        redirect: Option<RedirectConfig>,
+        rewrite_from: Option<Vec<String>>,
     }
 
     impl AssetConfigRule {
@@ -361,9 +402,21 @@ mod rule_utils {
                 ignore,
                 enable_aliasing,
                 allow_raw_access,
+                encodings,
+                redirect,
+                rewrite_from,
             }: InterimAssetConfigRule,
             config_file_parent_dir: &Path,
         ) -> Result<Self, LoadRuleError> {
+            if let Some(redirect) = &redirect {
+                if !REDIRECT_STATUS_CODES.contains(&redirect.status_code) {
+                    return Err(LoadRuleError::InvalidRedirectStatusCode(
+                        r#match,
+                        redirect.status_code,
+                    ));
+                }
+            }
+
             let glob = config_file_parent_dir.join(&r#match);
             let glob = glob.to_str().ok_or_else(|| {
                 LoadRuleError::FormGlobPatternFailed(
@@ -383,6 +436,9 @@ mod rule_utils {
                 used: false,
                 enable_aliasing,
                 allow_raw_access,
+                encodings,
+                redirect,
+                rewrite_from,
             })
         }
     }
@@ -442,6 +498,24 @@ mod rule_utils {
                     ));
                 }
             }
+            if let Some(ref encodings) = self.encodings {
+                s.push_str(&format!(
+                    "  - Content encodings: {}\n",
+                    std::iter::once("identity".to_string())
+                        .chain(encodings.iter().map(|e| e.to_string()))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+            if let Some(ref redirect) = self.redirect {
+                s.push_str(&format!(
+                    "  - Redirect: {} {}\n",
+                    redirect.status_code, redirect.location
+                ));
+            }
+            if let Some(ref rewrite_from) = self.rewrite_from {
+                s.push_str(&format!("  - Rewrite from: {}\n", rewrite_from.join(", ")));
+            }
 
             write!(f, "{}", s)
         }
@@ -936,4 +1010,121 @@ mod with_tempdir {
             },
         );
     }
+
+    #[test]
+    fn encodings_rule() {
+        let cfg = Some(HashMap::from([(
+            "".to_string(),
+            r#"[
+  {
+    "match": "**/*",
+    "encodings": ["gzip"]
+  },
+  {
+    "match": "**/*.js",
+    "encodings": ["gzip", "br", "zstd"]
+  },
+  {
+    "match": "nested/**/*",
+    "encodings": []
+  }
+]"#
+            .to_string(),
+        )]));
+        let assets_temp_dir = create_temporary_assets_directory(cfg, 7);
+        let assets_dir = assets_temp_dir.path().canonicalize().unwrap();
+        let mut assets_config = AssetSourceDirectoryConfiguration::load(&assets_dir).unwrap();
+        for (file, encodings) in [
+            ("index.html", vec![ContentEncoder::Gzip]),
+            (
+                "js/index.js",
+                vec![
+                    ContentEncoder::Gzip,
+                    ContentEncoder::Brotli,
+                    ContentEncoder::Zstd,
+                ],
+            ),
+            ("nested/the-thing.txt", vec![]),
+        ] {
+            assert_eq!(
+                assets_config
+                    .get_asset_config(assets_dir.join(file).as_path())
+                    .unwrap(),
+                AssetConfig {
+                    encodings: Some(encodings),
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_encoding() {
+        let cfg = Some(HashMap::from([(
+            "".to_string(),
+            r#"[{"match": "*", "encodings": ["lzma"]}]"#.to_string(),
+        )]));
+        let assets_temp_dir = create_temporary_assets_directory(cfg, 0);
+        let assets_dir = assets_temp_dir.path().canonicalize().unwrap();
+        assert!(matches!(
+            AssetSourceDirectoryConfiguration::load(&assets_dir),
+            Err(AssetLoadConfigError::MalformedAssetConfigFile(..))
+        ));
+    }
+
+    #[test]
+    fn redirect_and_rewrite_rules() {
+        let cfg = Some(HashMap::from([(
+            "".to_string(),
+            r#"[
+  {
+    "match": "index.html",
+    "redirect": {"location": "/js/index.js", "status_code": 302}
+  },
+  {
+    "match": "nested/**/*",
+    "rewrite_from": ["/nested"]
+  }
+]"#
+            .to_string(),
+        )]));
+        let assets_temp_dir = create_temporary_assets_directory(cfg, 7);
+        let assets_dir = assets_temp_dir.path().canonicalize().unwrap();
+        let mut assets_config = AssetSourceDirectoryConfiguration::load(&assets_dir).unwrap();
+        assert_eq!(
+            assets_config
+                .get_asset_config(assets_dir.join("index.html").as_path())
+                .unwrap(),
+            AssetConfig {
+                redirect: Some(RedirectConfig {
+                    location: "/js/index.js".to_string(),
+                    status_code: 302,
+                }),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            assets_config
+                .get_asset_config(assets_dir.join("nested/the-thing.txt").as_path())
+                .unwrap(),
+            AssetConfig {
+                rewrite_from: Some(vec!["/nested".to_string()]),
+                ..Default::default()
+            },
+        );
+    }
+
+    #[test]
+    fn invalid_redirect_status_code() {
+        let cfg = Some(HashMap::from([(
+            "".to_string(),
+            r#"[{"match": "*", "redirect": {"location": "/", "status_code": 200}}]"#.to_string(),
+        )]));
+        let assets_temp_dir = create_temporary_assets_directory(cfg, 0);
+        let assets_dir = assets_temp_dir.path().canonicalize().unwrap();
+        assert!(matches!(
+            AssetSourceDirectoryConfiguration::load(&assets_dir),
+            Err(AssetLoadConfigError::LoadRuleFailed(..))
+        ));
+    }
 }
@@ -7,6 +7,12 @@ use sha2::{Digest, Sha256};
 use std::io::Write;
 use std::path::Path;
 
+// Assets are compressed once at upload time, so use the best compression available
+const BROTLI_QUALITY: u32 = 11;
+const BROTLI_WINDOW_SIZE: u32 = 22;
+const BROTLI_BUFFER_SIZE: usize = 4096;
+const ZSTD_LEVEL: i32 = 19;
+
 pub(crate) struct Content {
     pub data: Vec<u8>,
     pub media_type: Mime,
@@ -27,6 +33,8 @@ impl Content {
     pub fn encode(&self, encoder: &ContentEncoder) -> Result<Content, std::io::Error> {
         match encoder {
             ContentEncoder::Gzip => self.to_gzip(),
+            ContentEncoder::Brotli => self.to_brotli(),
+            ContentEncoder::Zstd => self.to_zstd(),
         }
     }
 
@@ -40,6 +48,29 @@ impl Content {
         })
     }
 
+    pub fn to_brotli(&self) -> Result<Content, std::io::Error> {
+        let mut e = brotli::CompressorWriter::new(
+            Vec::new(),
+            BROTLI_BUFFER_SIZE,
+            BROTLI_QUALITY,
+            BROTLI_WINDOW_SIZE,
+        );
+        e.write_all(&self.data)?;
+        let data = e.into_inner();
+        Ok(Content {
+            data,
+            media_type: self.media_type.clone(),
+        })
+    }
+
+    pub fn to_zstd(&self) -> Result<Content, std::io::Error> {
+        let data = zstd::encode_all(self.data.as_slice(), ZSTD_LEVEL)?;
+        Ok(Content {
+            data,
+            media_type: self.media_type.clone(),
+        })
+    }
+
     pub fn sha256(&self) -> Vec<u8> {
         Sha256::digest(&self.data).to_vec()
     }
@@ -1,12 +1,22 @@
-#[derive(Clone, Debug)]
+use serde::{Deserialize, Serialize};
+
+/// Content encodings the asset canister can serve, ordered from the most to the least widely supported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum ContentEncoder {
+    #[serde(rename = "gzip")]
     Gzip,
+    #[serde(rename = "br")]
+    Brotli,
+    #[serde(rename = "zstd")]
+    Zstd,
 }
 
 impl std::fmt::Display for ContentEncoder {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self {
             ContentEncoder::Gzip => f.write_str("gzip"),
+            ContentEncoder::Brotli => f.write_str("br"),
+            ContentEncoder::Zstd => f.write_str("zstd"),
         }
     }
 }
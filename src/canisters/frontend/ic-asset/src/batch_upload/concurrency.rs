@@ -0,0 +1,162 @@
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+// A response slower than this multiple of the fastest one observed counts as congestion
+const CONGESTED_LATENCY_FACTOR: u32 = 2;
+
+/// Limits the number of requests in flight, and adapts the limit to how the
+/// replica responds: it grows by one for every limit's worth of timely responses,
+/// shrinks by one for every limit's worth of slow responses or failed requests, and
+/// halves whenever a request is rejected because the replica or boundary node is
+/// overloaded.
+pub(crate) struct AdaptiveConcurrency {
+    state: Mutex<State>,
+    available: Notify,
+    min: usize,
+    max: usize,
+}
+
+struct State {
+    limit: f64,
+    in_flight: usize,
+    min_latency: Option<Duration>,
+}
+
+pub(crate) struct Permit<'a> {
+    concurrency: &'a AdaptiveConcurrency,
+}
+
+impl AdaptiveConcurrency {
+    pub(crate) fn new(initial: usize, min: usize, max: usize) -> Self {
+        Self {
+            state: Mutex::new(State {
+                limit: initial as f64,
+                in_flight: 0,
+                min_latency: None,
+            }),
+            available: Notify::new(),
+            min,
+            max,
+        }
+    }
+
+    pub(crate) async fn acquire(&self) -> Permit<'_> {
+        loop {
+            // Registered before checking, so a release in between is not missed
+            let available = self.available.notified();
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.in_flight < state.limit as usize {
+                    state.in_flight += 1;
+                    return Permit { concurrency: self };
+                }
+            }
+            available.await;
+        }
+    }
+
+    pub(crate) fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit as usize
+    }
+
+    pub(crate) fn succeeded(&self, latency: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let min_latency = *state.min_latency.get_or_insert(latency);
+        state.min_latency = Some(min_latency.min(latency));
+
+        let step = 1.0 / state.limit.floor();
+        state.limit = if latency > min_latency * CONGESTED_LATENCY_FACTOR {
+            (state.limit - step).max(self.min as f64)
+        } else {
+            (state.limit + step).min(self.max as f64)
+        };
+        drop(state);
+        self.available.notify_waiters();
+    }
+
+    /// A request that failed for another reason than overload, such as a timeout,
+    /// counts as a slow response.
+    pub(crate) fn failed(&self) {
+        let mut state = self.state.lock().unwrap();
+        let step = 1.0 / state.limit.floor();
+        state.limit = (state.limit - step).max(self.min as f64);
+    }
+
+    pub(crate) fn rejected(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.limit = (state.limit / 2.0).max(self.min as f64);
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.concurrency.state.lock().unwrap().in_flight -= 1;
+        self.concurrency.available.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod test_adaptive_concurrency {
+    use super::AdaptiveConcurrency;
+    use std::time::Duration;
+
+    const FAST: Duration = Duration::from_millis(100);
+    const SLOW: Duration = Duration::from_millis(500);
+
+    #[test]
+    fn grows_by_one_per_limit_of_timely_responses() {
+        let concurrency = AdaptiveConcurrency::new(4, 1, 6);
+        for _ in 0..4 {
+            concurrency.succeeded(FAST);
+        }
+        assert_eq!(concurrency.limit(), 5);
+        for _ in 0..20 {
+            concurrency.succeeded(FAST);
+        }
+        assert_eq!(concurrency.limit(), 6);
+    }
+
+    #[test]
+    fn shrinks_on_slow_responses_and_rejections() {
+        let concurrency = AdaptiveConcurrency::new(8, 2, 16);
+        concurrency.succeeded(FAST);
+        concurrency.succeeded(SLOW);
+        assert_eq!(concurrency.limit(), 8);
+        concurrency.succeeded(SLOW);
+        assert_eq!(concurrency.limit(), 7);
+
+        concurrency.rejected();
+        assert_eq!(concurrency.limit(), 3);
+        concurrency.rejected();
+        assert_eq!(concurrency.limit(), 2);
+    }
+
+    #[test]
+    fn counts_failed_requests_as_slow_responses() {
+        let concurrency = AdaptiveConcurrency::new(8, 2, 16);
+        concurrency.succeeded(FAST);
+        concurrency.failed();
+        assert_eq!(concurrency.limit(), 8);
+        concurrency.failed();
+        assert_eq!(concurrency.limit(), 7);
+
+        for _ in 0..100 {
+            concurrency.failed();
+        }
+        assert_eq!(concurrency.limit(), 2);
+    }
+
+    #[test]
+    fn waits_for_a_permit_within_the_limit() {
+        let concurrency = AdaptiveConcurrency::new(1, 1, 1);
+        futures::executor::block_on(async {
+            let permit = concurrency.acquire().await;
+            let waiting = concurrency.acquire();
+            futures::pin_mut!(waiting);
+            assert!(futures::poll!(&mut waiting).is_pending());
+            drop(permit);
+            waiting.await;
+        });
+    }
+}
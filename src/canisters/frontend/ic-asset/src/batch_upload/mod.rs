@@ -1,4 +1,6 @@
+pub(crate) mod concurrency;
 pub(crate) mod operations;
 pub(crate) mod plumbing;
+pub(crate) mod progress;
 pub(crate) mod retryable;
 pub(crate) mod semaphores;
@@ -115,6 +115,8 @@ pub(crate) fn create_new_assets(
             let headers = project_asset.asset_descriptor.config.clone().headers;
             let enable_aliasing = project_asset.asset_descriptor.config.enable_aliasing;
             let allow_raw_access = project_asset.asset_descriptor.config.allow_raw_access;
+            let redirect = project_asset.asset_descriptor.config.redirect.clone();
+            let rewrite_from = project_asset.asset_descriptor.config.rewrite_from.clone();
 
             operations.push(BatchOperationKind::CreateAsset(CreateAssetArguments {
                 key: key.clone(),
@@ -123,6 +125,8 @@ pub(crate) fn create_new_assets(
                 headers,
                 enable_aliasing,
                 allow_raw_access,
+                redirect,
+                rewrite_from,
             }));
         }
     }
@@ -235,12 +239,30 @@ pub(crate) fn update_properties(
                         None
                     }
                 },
+                redirect: {
+                    if project_asset_properties.redirect != canister_asset_properties.redirect {
+                        Some(project_asset_properties.redirect.clone())
+                    } else {
+                        None
+                    }
+                },
+                rewrite_from: {
+                    if project_asset_properties.rewrite_from
+                        != canister_asset_properties.rewrite_from
+                    {
+                        Some(project_asset_properties.rewrite_from.clone())
+                    } else {
+                        None
+                    }
+                },
             };
             // check if the properties are the same and skip if they are to save saves cycles
             if set_asset_props.allow_raw_access.is_some()
                 || set_asset_props.max_age.is_some()
                 || set_asset_props.headers.is_some()
                 || set_asset_props.is_aliased.is_some()
+                || set_asset_props.redirect.is_some()
+                || set_asset_props.rewrite_from.is_some()
             {
                 operations.push(BatchOperationKind::SetAssetProperties(set_asset_props));
             }
@@ -307,6 +329,8 @@ mod test_update_properties {
                 headers: Some(HashMap::new()),
                 is_aliased: Some(true),
                 allow_raw_access: Some(true),
+                redirect: None,
+                rewrite_from: None,
             },
         );
         let mut operations = vec![];
@@ -320,6 +344,8 @@ mod test_update_properties {
                 headers: Some(Some(vec![("key".to_string(), "value".to_string())])),
                 is_aliased: Some(Some(false)),
                 allow_raw_access: Some(Some(false)),
+                redirect: None,
+                rewrite_from: None,
             })
         );
     }
@@ -361,6 +387,8 @@ mod test_update_properties {
                 headers: Some(HashMap::new()),
                 is_aliased: Some(true),
                 allow_raw_access: Some(true),
+                redirect: None,
+                rewrite_from: None,
             },
         );
         canister_asset_properties.insert(
@@ -370,6 +398,8 @@ mod test_update_properties {
                 headers: Some(HashMap::new()),
                 is_aliased: Some(true),
                 allow_raw_access: Some(true),
+                redirect: None,
+                rewrite_from: None,
             },
         );
         let mut operations = vec![];
@@ -401,6 +431,8 @@ mod test_update_properties {
                 headers: Some(HashMap::from([("key".to_string(), "value".to_string())])),
                 is_aliased: Some(true),
                 allow_raw_access: Some(true),
+                redirect: None,
+                rewrite_from: None,
             },
         );
         let mut operations = vec![];
@@ -414,6 +446,8 @@ mod test_update_properties {
                 headers: Some(None),
                 is_aliased: Some(None),
                 allow_raw_access: Some(None),
+                redirect: None,
+                rewrite_from: None,
             })
         );
     }
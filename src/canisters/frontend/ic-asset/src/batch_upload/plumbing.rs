@@ -1,6 +1,7 @@
 use crate::asset::config::AssetConfig;
 use crate::asset::content::Content;
 use crate::asset::content_encoder::ContentEncoder;
+use crate::batch_upload::progress::ProgressTracker;
 use crate::batch_upload::semaphores::Semaphores;
 use crate::canister_api::methods::chunk::create_chunk;
 use crate::canister_api::types::asset::AssetDetails;
@@ -8,6 +9,7 @@ use crate::error::CreateChunkError;
 use crate::error::CreateEncodingError;
 use crate::error::CreateEncodingError::EncodeContentFailed;
 use crate::error::CreateProjectAssetError;
+use crate::progress::UploadProgressReporter;
 use candid::Nat;
 use futures::future::try_join_all;
 use futures::TryFutureExt;
@@ -51,14 +53,20 @@ pub(crate) struct ChunkUploader<'agent> {
     batch_id: Nat,
     chunks: Arc<AtomicUsize>,
     bytes: Arc<AtomicUsize>,
+    progress: ProgressTracker<'agent>,
 }
 impl<'agent> ChunkUploader<'agent> {
-    pub(crate) fn new(canister: Canister<'agent>, batch_id: Nat) -> Self {
+    pub(crate) fn new(
+        canister: Canister<'agent>,
+        batch_id: Nat,
+        progress: Option<&'agent dyn UploadProgressReporter>,
+    ) -> Self {
         Self {
             canister,
             batch_id,
             chunks: Arc::new(AtomicUsize::new(0)),
             bytes: Arc::new(AtomicUsize::new(0)),
+            progress: ProgressTracker::new(progress),
         }
     }
 
@@ -69,7 +77,13 @@ impl<'agent> ChunkUploader<'agent> {
     ) -> Result<Nat, CreateChunkError> {
         self.chunks.fetch_add(1, Ordering::SeqCst);
         self.bytes.fetch_add(contents.len(), Ordering::SeqCst);
-        create_chunk(&self.canister, &self.batch_id, contents, semaphores).await
+        let chunk_id = create_chunk(&self.canister, &self.batch_id, contents, semaphores).await?;
+        self.progress.chunk_uploaded(contents.len());
+        Ok(chunk_id)
+    }
+
+    pub(crate) fn progress(&self) -> &ProgressTracker<'agent> {
+        &self.progress
     }
 
     pub(crate) fn bytes(&self) -> usize {
@@ -150,57 +164,29 @@ async fn make_project_asset_encoding(
     })
 }
 
-#[allow(clippy::too_many_arguments)]
-async fn make_encoding(
-    chunk_upload_target: Option<&ChunkUploader<'_>>,
+// Compresses the content with every applicable encoder. An encoding is kept only if it is
+// smaller than identity and than every kept encoding with wider client support, otherwise
+// clients accepting it are better served by one of the others.
+fn encode_content(
     asset_descriptor: &AssetDescriptor,
-    canister_assets: &HashMap<String, AssetDetails>,
     content: &Content,
-    encoder: &Option<ContentEncoder>,
-    semaphores: &Semaphores,
-    logger: &Logger,
-) -> Result<Option<(String, ProjectAssetEncoding)>, CreateEncodingError> {
-    match encoder {
-        None => {
-            let identity_asset_encoding = make_project_asset_encoding(
-                chunk_upload_target,
-                asset_descriptor,
-                canister_assets,
-                content,
-                CONTENT_ENCODING_IDENTITY,
-                semaphores,
-                logger,
-            )
-            .await
-            .map_err(CreateEncodingError::CreateChunkFailed)?;
-            Ok(Some((
-                CONTENT_ENCODING_IDENTITY.to_string(),
-                identity_asset_encoding,
-            )))
-        }
-        Some(encoder) => {
-            let encoded = content.encode(encoder).map_err(|e| {
-                EncodeContentFailed(asset_descriptor.key.clone(), encoder.clone(), e)
-            })?;
-            if encoded.data.len() < content.data.len() {
-                let content_encoding = format!("{}", encoder);
-                let project_asset_encoding = make_project_asset_encoding(
-                    chunk_upload_target,
-                    asset_descriptor,
-                    canister_assets,
-                    &encoded,
-                    &content_encoding,
-                    semaphores,
-                    logger,
-                )
-                .await
-                .map_err(CreateEncodingError::CreateChunkFailed)?;
-                Ok(Some((content_encoding, project_asset_encoding)))
-            } else {
-                Ok(None)
-            }
+) -> Result<Vec<(String, Content)>, CreateEncodingError> {
+    let mut encoders = applicable_encoders(&asset_descriptor.config, &content.media_type);
+    encoders.sort();
+    encoders.dedup();
+
+    let mut smallest = content.data.len();
+    let mut encoded_contents = vec![];
+    for encoder in encoders {
+        let encoded = content
+            .encode(&encoder)
+            .map_err(|e| EncodeContentFailed(asset_descriptor.key.clone(), encoder, e))?;
+        if encoded.data.len() < smallest {
+            smallest = encoded.data.len();
+            encoded_contents.push((encoder.to_string(), encoded));
         }
     }
+    Ok(encoded_contents)
 }
 
 async fn make_encodings(
@@ -211,34 +197,34 @@ async fn make_encodings(
     semaphores: &Semaphores,
     logger: &Logger,
 ) -> Result<HashMap<String, ProjectAssetEncoding>, CreateEncodingError> {
-    let mut encoders = vec![None];
-    for encoder in applicable_encoders(&content.media_type) {
-        encoders.push(Some(encoder));
+    let encoded_contents = encode_content(asset_descriptor, content)?;
+
+    let mut contents = vec![(CONTENT_ENCODING_IDENTITY, content)];
+    for (content_encoding, encoded) in encoded_contents.iter() {
+        contents.push((content_encoding.as_str(), encoded));
     }
 
-    let encoding_futures: Vec<_> = encoders
-        .iter()
-        .map(|maybe_encoder| {
-            make_encoding(
+    let encoding_futures: Vec<_> = contents
+        .into_iter()
+        .map(|(content_encoding, content)| {
+            make_project_asset_encoding(
                 chunk_upload_target,
                 asset_descriptor,
                 canister_assets,
                 content,
-                maybe_encoder,
+                content_encoding,
                 semaphores,
                 logger,
             )
+            .map_ok(move |encoding| (content_encoding.to_string(), encoding))
         })
         .collect();
 
-    let encodings = try_join_all(encoding_futures).await?;
-
-    let mut result: HashMap<String, ProjectAssetEncoding> = HashMap::new();
+    let encodings = try_join_all(encoding_futures)
+        .await
+        .map_err(CreateEncodingError::CreateChunkFailed)?;
 
-    for (key, value) in encodings.into_iter().flatten() {
-        result.insert(key, value);
-    }
-    Ok(result)
+    Ok(encodings.into_iter().collect())
 }
 
 async fn make_project_asset(
@@ -271,6 +257,9 @@ async fn make_project_asset(
         logger,
     )
     .await?;
+    if let Some(target) = chunk_upload_target {
+        target.progress().file_processed();
+    }
 
     Ok(ProjectAsset {
         asset_descriptor,
@@ -286,6 +275,9 @@ pub(crate) async fn make_project_assets(
     logger: &Logger,
 ) -> Result<HashMap<String, ProjectAsset>, CreateProjectAssetError> {
     let semaphores = Semaphores::new();
+    if let Some(target) = chunk_upload_target {
+        target.progress().add_total_files(asset_descriptors.len());
+    }
 
     let project_asset_futures: Vec<_> = asset_descriptors
         .iter()
@@ -300,6 +292,11 @@ pub(crate) async fn make_project_assets(
         })
         .collect();
     let project_assets = try_join_all(project_asset_futures).await?;
+    debug!(
+        logger,
+        "Concurrent create_chunk requests adapted to {}",
+        semaphores.create_chunk_request.limit()
+    );
 
     let mut hm = HashMap::new();
     for project_asset in project_assets {
@@ -317,6 +314,9 @@ async fn upload_content_chunks(
     semaphores: &Semaphores,
     logger: &Logger,
 ) -> Result<Vec<Nat>, CreateChunkError> {
+    chunk_uploader
+        .progress()
+        .add_total_bytes(content.data.len());
     if content.data.is_empty() {
         let empty = vec![];
         let chunk_id = chunk_uploader.create_chunk(&empty, semaphores).await?;
@@ -367,10 +367,15 @@ fn content_encoding_descriptive_suffix(content_encoding: &str) -> String {
     }
 }
 
-// todo: make this configurable https://github.com/dfinity/dx-triage/issues/152
-fn applicable_encoders(media_type: &Mime) -> Vec<ContentEncoder> {
+// Encodings configured in .ic-assets.json take precedence over the defaults for the media type
+fn applicable_encoders(config: &AssetConfig, media_type: &Mime) -> Vec<ContentEncoder> {
+    if let Some(encodings) = &config.encodings {
+        return encodings.clone();
+    }
     match (media_type.type_(), media_type.subtype()) {
-        (mime::TEXT, _) | (_, mime::JAVASCRIPT) | (_, mime::HTML) => vec![ContentEncoder::Gzip],
+        (mime::TEXT, _) | (_, mime::JAVASCRIPT) | (_, mime::HTML) => {
+            vec![ContentEncoder::Gzip, ContentEncoder::Brotli]
+        }
         _ => vec![],
     }
 }
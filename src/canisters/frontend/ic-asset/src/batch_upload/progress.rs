@@ -0,0 +1,84 @@
+use crate::progress::{UploadProgress, UploadProgressReporter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+pub(crate) struct ProgressTracker<'a> {
+    reporter: Option<&'a dyn UploadProgressReporter>,
+    started: Instant,
+    total_files: AtomicUsize,
+    processed_files: AtomicUsize,
+    total_bytes: AtomicUsize,
+    uploaded_bytes: AtomicUsize,
+}
+
+impl<'a> ProgressTracker<'a> {
+    pub(crate) fn new(reporter: Option<&'a dyn UploadProgressReporter>) -> Self {
+        Self {
+            reporter,
+            started: Instant::now(),
+            total_files: AtomicUsize::new(0),
+            processed_files: AtomicUsize::new(0),
+            total_bytes: AtomicUsize::new(0),
+            uploaded_bytes: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn add_total_files(&self, files: usize) {
+        self.total_files.fetch_add(files, Ordering::SeqCst);
+        self.report();
+    }
+
+    pub(crate) fn file_processed(&self) {
+        self.processed_files.fetch_add(1, Ordering::SeqCst);
+        self.report();
+    }
+
+    pub(crate) fn add_total_bytes(&self, bytes: usize) {
+        self.total_bytes.fetch_add(bytes, Ordering::SeqCst);
+    }
+
+    pub(crate) fn chunk_uploaded(&self, bytes: usize) {
+        self.uploaded_bytes.fetch_add(bytes, Ordering::SeqCst);
+        self.report();
+    }
+
+    fn report(&self) {
+        let Some(reporter) = self.reporter else {
+            return;
+        };
+        let total_bytes = self.total_bytes.load(Ordering::SeqCst);
+        let uploaded_bytes = self.uploaded_bytes.load(Ordering::SeqCst);
+        reporter.report(&UploadProgress {
+            total_files: self.total_files.load(Ordering::SeqCst),
+            processed_files: self.processed_files.load(Ordering::SeqCst),
+            total_bytes,
+            uploaded_bytes,
+            eta: eta(total_bytes, uploaded_bytes, self.started.elapsed()),
+        });
+    }
+}
+
+fn eta(total_bytes: usize, uploaded_bytes: usize, elapsed: Duration) -> Option<Duration> {
+    if uploaded_bytes == 0 {
+        return None;
+    }
+    let remaining_bytes = total_bytes.saturating_sub(uploaded_bytes);
+    Some(elapsed.mul_f64(remaining_bytes as f64 / uploaded_bytes as f64))
+}
+
+#[cfg(test)]
+mod test_eta {
+    use super::eta;
+    use std::time::Duration;
+
+    #[test]
+    fn extrapolates_the_observed_rate() {
+        assert_eq!(eta(100, 0, Duration::from_secs(3)), None);
+        assert_eq!(
+            eta(100, 25, Duration::from_secs(3)),
+            Some(Duration::from_secs(9))
+        );
+        assert_eq!(eta(100, 100, Duration::from_secs(3)), Some(Duration::ZERO));
+        assert_eq!(eta(50, 100, Duration::from_secs(3)), Some(Duration::ZERO));
+    }
+}
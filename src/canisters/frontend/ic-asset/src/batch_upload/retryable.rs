@@ -1,3 +1,4 @@
+use ic_agent::agent::{RejectCode, RejectResponse};
 use ic_agent::AgentError;
 
 pub(crate) fn retryable(agent_error: &AgentError) -> bool {
@@ -6,3 +7,14 @@ pub(crate) fn retryable(agent_error: &AgentError) -> bool {
         AgentError::TimeoutWaitingForResponse() | AgentError::TransportError(_)
     )
 }
+
+// The replica or a boundary node is too busy to take the request right now
+pub(crate) fn overloaded(agent_error: &AgentError) -> bool {
+    match agent_error {
+        AgentError::ReplicaError(RejectResponse { reject_code, .. }) => {
+            *reject_code == RejectCode::SysTransient
+        }
+        AgentError::HttpError(payload) => payload.status == 429 || payload.status == 503,
+        _ => false,
+    }
+}
@@ -1,16 +1,16 @@
+use crate::batch_upload::concurrency::AdaptiveConcurrency;
 use futures_intrusive::sync::SharedSemaphore;
 
 // Maximum MB of file data to load at once.  More memory may be used, due to encodings.
 const MAX_SIMULTANEOUS_LOADED_MB: usize = 50;
 
 // How many simultaneous chunks being created at once
-const MAX_SIMULTANEOUS_CREATE_CHUNK: usize = 12;
+const MAX_SIMULTANEOUS_CREATE_CHUNK: usize = 32;
 
-// How many simultaneous Agent.call() to create_chunk
-const MAX_SIMULTANEOUS_CREATE_CHUNK_CALLS: usize = 4;
-
-// How many simultaneous Agent.wait() on create_chunk result
-const MAX_SIMULTANEOUS_CREATE_CHUNK_WAITS: usize = 4;
+// How many create_chunk requests to have in flight at first, and the bounds for adapting that
+const INITIAL_CREATE_CHUNK_REQUESTS: usize = 8;
+const MIN_CREATE_CHUNK_REQUESTS: usize = 1;
+const MAX_CREATE_CHUNK_REQUESTS: usize = MAX_SIMULTANEOUS_CREATE_CHUNK;
 
 pub(crate) struct Semaphores {
     // The "file" semaphore limits how much file data to load at once.  A given loaded file's data
@@ -24,13 +24,10 @@ pub(crate) struct Semaphores {
     // any chunk made its second attempt.
     pub create_chunk: SharedSemaphore,
 
-    // The create_chunk_call semaphore limits the number of simultaneous
-    // agent.call()s to create_chunk.
-    pub create_chunk_call: SharedSemaphore,
-
-    // The create_chunk_wait semaphore limits the number of simultaneous
-    // agent.wait() calls for outstanding create_chunk requests.
-    pub create_chunk_wait: SharedSemaphore,
+    // The create_chunk_request limit covers the agent.call() to create_chunk and the
+    // agent.wait() for its result.  It adapts to the latency and rejections observed,
+    // so that uploads go as fast as the replica and boundary nodes allow.
+    pub create_chunk_request: AdaptiveConcurrency,
 }
 
 impl Semaphores {
@@ -39,15 +36,16 @@ impl Semaphores {
 
         let create_chunk = SharedSemaphore::new(true, MAX_SIMULTANEOUS_CREATE_CHUNK);
 
-        let create_chunk_call = SharedSemaphore::new(true, MAX_SIMULTANEOUS_CREATE_CHUNK_CALLS);
-
-        let create_chunk_wait = SharedSemaphore::new(true, MAX_SIMULTANEOUS_CREATE_CHUNK_WAITS);
+        let create_chunk_request = AdaptiveConcurrency::new(
+            INITIAL_CREATE_CHUNK_REQUESTS,
+            MIN_CREATE_CHUNK_REQUESTS,
+            MAX_CREATE_CHUNK_REQUESTS,
+        );
 
         Semaphores {
             file,
             create_chunk,
-            create_chunk_call,
-            create_chunk_wait,
+            create_chunk_request,
         }
     }
 }
@@ -1,9 +1,9 @@
 use crate::batch_upload::retryable::retryable;
 use crate::canister_api::methods::method_names::{
-    COMMIT_BATCH, COMPUTE_EVIDENCE, CREATE_BATCH, PROPOSE_COMMIT_BATCH,
+    COMMIT_BATCH, COMPUTE_EVIDENCE, CREATE_BATCH, DELETE_BATCH, PROPOSE_COMMIT_BATCH,
 };
 use crate::canister_api::types::batch_upload::common::{
-    ComputeEvidenceArguments, CreateBatchRequest, CreateBatchResponse,
+    ComputeEvidenceArguments, CreateBatchRequest, CreateBatchResponse, DeleteBatchArguments,
 };
 use backoff::backoff::Backoff;
 use backoff::ExponentialBackoffBuilder;
@@ -121,3 +121,10 @@ pub(crate) async fn compute_evidence(
         }
     }
 }
+
+pub(crate) async fn delete_batch(
+    canister: &Canister<'_>,
+    arg: DeleteBatchArguments,
+) -> Result<(), AgentError> {
+    submit_commit_batch(canister, DELETE_BATCH, arg).await
+}
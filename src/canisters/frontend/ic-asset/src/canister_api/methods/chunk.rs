@@ -1,4 +1,4 @@
-use crate::batch_upload::retryable::retryable;
+use crate::batch_upload::retryable::{overloaded, retryable};
 use crate::batch_upload::semaphores::Semaphores;
 use crate::canister_api::methods::method_names::CREATE_CHUNK;
 use crate::canister_api::types::batch_upload::common::{CreateChunkRequest, CreateChunkResponse};
@@ -7,7 +7,7 @@ use backoff::backoff::Backoff;
 use backoff::ExponentialBackoffBuilder;
 use candid::{Decode, Nat};
 use ic_utils::Canister;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 pub(crate) async fn create_chunk(
     canister: &Canister<'_>,
@@ -28,37 +28,43 @@ pub(crate) async fn create_chunk(
     loop {
         let builder = canister.update(CREATE_CHUNK);
         let builder = builder.with_arg(&args);
-        let request_id_result = {
-            let _releaser = semaphores.create_chunk_call.acquire(1).await;
-            builder
+        let (wait_result, latency) = {
+            let _releaser = semaphores.create_chunk_request.acquire().await;
+            let started = Instant::now();
+            let request_id_result = builder
                 .build()
                 .map(|result: (CreateChunkResponse,)| (result.0.chunk_id,))
                 .call()
-                .await
-        };
-
-        let wait_result = match request_id_result {
-            Ok(request_id) => {
-                let _releaser = semaphores.create_chunk_wait.acquire(1).await;
-                canister.wait(request_id).await
-            }
-            Err(agent_err) => Err(agent_err),
+                .await;
+            let wait_result = match request_id_result {
+                Ok(request_id) => canister.wait(request_id).await,
+                Err(agent_err) => Err(agent_err),
+            };
+            (wait_result, started.elapsed())
         };
 
         match wait_result {
             Ok(response) => {
+                semaphores.create_chunk_request.succeeded(latency);
                 // failure to decode the response is not retryable
                 let response = Decode!(&response, CreateChunkResponse)
                     .map_err(CreateChunkError::DecodeCreateChunkResponse)?;
                 return Ok(response.chunk_id);
             }
-            Err(agent_err) if !retryable(&agent_err) => {
+            Err(agent_err) if !retryable(&agent_err) && !overloaded(&agent_err) => {
                 return Err(CreateChunkError::CreateChunk(agent_err));
             }
-            Err(agent_err) => match retry_policy.next_backoff() {
-                Some(duration) => tokio::time::sleep(duration).await,
-                None => return Err(CreateChunkError::CreateChunk(agent_err)),
-            },
+            Err(agent_err) => {
+                if overloaded(&agent_err) {
+                    semaphores.create_chunk_request.rejected();
+                } else {
+                    semaphores.create_chunk_request.failed();
+                }
+                match retry_policy.next_backoff() {
+                    Some(duration) => tokio::time::sleep(duration).await,
+                    None => return Err(CreateChunkError::CreateChunk(agent_err)),
+                }
+            }
         }
     }
 }
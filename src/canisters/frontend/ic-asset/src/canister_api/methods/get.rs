@@ -0,0 +1,26 @@
+use crate::canister_api::methods::method_names::{GET, GET_CHUNK};
+use crate::canister_api::types::get::{GetChunkRequest, GetChunkResponse, GetRequest, GetResponse};
+use ic_agent::AgentError;
+use ic_utils::call::SyncCall;
+use ic_utils::Canister;
+
+pub(crate) async fn get(
+    canister: &Canister<'_>,
+    arg: &GetRequest,
+) -> Result<GetResponse, AgentError> {
+    let (response,): (GetResponse,) = canister.query(GET).with_arg(arg).build().call().await?;
+    Ok(response)
+}
+
+pub(crate) async fn get_chunk(
+    canister: &Canister<'_>,
+    arg: &GetChunkRequest,
+) -> Result<GetChunkResponse, AgentError> {
+    let (response,): (GetChunkResponse,) = canister
+        .query(GET_CHUNK)
+        .with_arg(arg)
+        .build()
+        .call()
+        .await?;
+    Ok(response)
+}
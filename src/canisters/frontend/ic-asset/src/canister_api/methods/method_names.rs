@@ -3,6 +3,9 @@ pub(crate) const COMMIT_BATCH: &str = "commit_batch";
 pub(crate) const COMPUTE_EVIDENCE: &str = "compute_evidence";
 pub(crate) const CREATE_BATCH: &str = "create_batch";
 pub(crate) const CREATE_CHUNK: &str = "create_chunk";
+pub(crate) const DELETE_BATCH: &str = "delete_batch";
+pub(crate) const GET: &str = "get";
 pub(crate) const GET_ASSET_PROPERTIES: &str = "get_asset_properties";
+pub(crate) const GET_CHUNK: &str = "get_chunk";
 pub(crate) const LIST: &str = "list";
 pub(crate) const PROPOSE_COMMIT_BATCH: &str = "propose_commit_batch";
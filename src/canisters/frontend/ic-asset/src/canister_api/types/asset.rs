@@ -1,9 +1,10 @@
+use crate::asset::config::RedirectConfig;
 use candid::CandidType;
 use serde::Deserialize;
 use std::collections::HashMap;
 
 /// Information about a content encoding stored for an asset.
-#[derive(CandidType, Debug, Deserialize)]
+#[derive(CandidType, Clone, Debug, Deserialize)]
 pub struct AssetEncodingDetails {
     /// A content encoding, such as "gzip".
     pub content_encoding: String,
@@ -14,7 +15,7 @@ pub struct AssetEncodingDetails {
 }
 
 /// Information about an asset stored in the canister.
-#[derive(CandidType, Debug, Deserialize)]
+#[derive(CandidType, Clone, Debug, Deserialize)]
 pub struct AssetDetails {
     /// The key identifies the asset.
     pub key: String,
@@ -35,6 +36,10 @@ pub struct AssetProperties {
     pub allow_raw_access: Option<bool>,
     /// Asset's toggle for whether to serve the .html asset both as /route and /route.html
     pub is_aliased: Option<bool>,
+    /// Asset's redirect, served instead of its content
+    pub redirect: Option<RedirectConfig>,
+    /// Asset's path prefixes for which it is served if no other asset matches
+    pub rewrite_from: Option<Vec<String>>,
 }
 
 /// Sets the asset with the given properties.
@@ -45,6 +50,8 @@ pub struct SetAssetPropertiesArguments {
     pub headers: Option<Option<Vec<(String, String)>>>,
     pub allow_raw_access: Option<Option<bool>>,
     pub is_aliased: Option<Option<bool>>,
+    pub redirect: Option<Option<RedirectConfig>>,
+    pub rewrite_from: Option<Option<Vec<String>>>,
 }
 
 /// The arguments to the `get_asset_properties` method.
@@ -1,4 +1,4 @@
-use crate::asset::config::HeadersConfig;
+use crate::asset::config::{HeadersConfig, RedirectConfig};
 use candid::{CandidType, Nat};
 use serde::Deserialize;
 
@@ -50,6 +50,10 @@ pub struct CreateAssetArguments {
     pub enable_aliasing: Option<bool>,
     /// When set to true, don't redirect from raw to certified
     pub allow_raw_access: Option<bool>,
+    /// Serve a redirect instead of the content
+    pub redirect: Option<RedirectConfig>,
+    /// Path prefixes for which this asset is served if no other asset matches
+    pub rewrite_from: Option<Vec<String>>,
 }
 
 /// Set the data for a particular content encoding for the given asset.
@@ -85,6 +89,13 @@ pub struct DeleteAssetArguments {
 #[derive(CandidType, Clone, Debug, PartialOrd, PartialEq, Eq, Ord)]
 pub struct ClearArguments {}
 
+/// Delete a batch and its chunks, unless it has been committed.
+#[derive(CandidType, Debug)]
+pub struct DeleteBatchArguments {
+    /// The batch to delete.
+    pub batch_id: Nat,
+}
+
 /// Compute a hash over the proposed CommitBatchArguments.  This may take more than one call.
 #[derive(CandidType, Debug)]
 pub struct ComputeEvidenceArguments {
@@ -0,0 +1,44 @@
+use candid::{CandidType, Nat};
+use serde::Deserialize;
+use serde_bytes::ByteBuf;
+
+/// Get an asset in the first of the given encodings the canister has.
+#[derive(CandidType, Debug)]
+pub struct GetRequest {
+    /// The key of the asset.
+    pub key: String,
+    /// The acceptable encodings, in order of preference.
+    pub accept_encodings: Vec<String>,
+}
+
+/// The first chunk of an asset encoding.
+#[derive(CandidType, Debug, Deserialize)]
+pub struct GetResponse {
+    /// Chunk index 0, or the entire content if it has a single chunk.
+    pub content: ByteBuf,
+    /// The sha256 of the entire asset encoding.
+    pub sha256: Option<ByteBuf>,
+    /// The length of the entire asset encoding.  All chunks except the last
+    /// have the length of the first chunk.
+    pub total_length: Nat,
+}
+
+/// Get a chunk of an asset encoding.
+#[derive(CandidType, Debug)]
+pub struct GetChunkRequest {
+    /// The key of the asset.
+    pub key: String,
+    /// The encoding of the chunk.
+    pub content_encoding: String,
+    /// The index of the chunk.
+    pub index: Nat,
+    /// The sha256 of the entire asset encoding, to fail if it changed in the meantime.
+    pub sha256: Option<ByteBuf>,
+}
+
+/// A chunk of an asset encoding.
+#[derive(CandidType, Debug, Deserialize)]
+pub struct GetChunkResponse {
+    /// The content of the chunk.
+    pub content: ByteBuf,
+}
@@ -0,0 +1,490 @@
+use crate::batch_upload::operations::assemble_batch_operations;
+use crate::batch_upload::operations::AssetDeletionReason::Obsolete;
+use crate::batch_upload::plumbing::make_project_assets;
+use crate::canister_api::methods::asset_properties::get_assets_properties;
+use crate::canister_api::methods::list::list_assets;
+use crate::canister_api::types::asset::{AssetDetails, SetAssetPropertiesArguments};
+use crate::canister_api::types::batch_upload::v1::BatchOperationKind;
+use crate::error::ComputeSyncDiffError;
+use crate::sync::gather_asset_descriptors;
+use ic_utils::Canister;
+use serde::Serialize;
+use slog::{info, Logger};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+/// The changes a sync would make to an asset canister, each list sorted by key.
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+pub struct SyncDiff {
+    /// Assets not in the canister yet.
+    pub new: Vec<AssetChange>,
+    /// Assets with content uploaded or removed for at least one encoding.
+    pub changed: Vec<AssetChange>,
+    /// Assets with unchanged content whose properties change.
+    pub properties_changed: Vec<PropertiesChange>,
+    /// Keys of assets deleted from the canister.
+    pub deleted: Vec<String>,
+}
+
+/// A new asset, or an asset whose content changes.
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+pub struct AssetChange {
+    /// The key of the asset.
+    pub key: String,
+    /// The MIME type of the asset after the sync.
+    pub content_type: String,
+    /// Set when the content type changes, which recreates the asset.
+    pub previous_content_type: Option<String>,
+    /// Encodings whose content is uploaded.
+    pub encodings: Vec<EncodingChange>,
+    /// Encodings removed from the asset.
+    pub removed_encodings: Vec<String>,
+    /// Names of the properties changing along with the content.
+    pub properties: Vec<String>,
+}
+
+/// Content uploaded for one encoding of an asset.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct EncodingChange {
+    /// A content encoding, such as "gzip".
+    pub content_encoding: String,
+    /// Hex-encoded SHA-256 of the uploaded content.
+    pub sha256: String,
+    /// Hex-encoded SHA-256 of the content it replaces, if the canister has this encoding.
+    pub previous_sha256: Option<String>,
+}
+
+/// An asset whose properties change while its content stays the same.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct PropertiesChange {
+    /// The key of the asset.
+    pub key: String,
+    /// Names of the changing properties, such as "headers".
+    pub properties: Vec<String>,
+}
+
+impl SyncDiff {
+    /// Whether the sync would leave the canister as it is.
+    pub fn is_empty(&self) -> bool {
+        self.new.is_empty()
+            && self.changed.is_empty()
+            && self.properties_changed.is_empty()
+            && self.deleted.is_empty()
+    }
+}
+
+/// Compute the changes a sync of the directories would make, without uploading or committing anything.
+pub async fn compute_sync_diff(
+    canister: &Canister<'_>,
+    dirs: &[&Path],
+    logger: &Logger,
+) -> Result<SyncDiff, ComputeSyncDiffError> {
+    let asset_descriptors = gather_asset_descriptors(dirs, logger)?;
+
+    let canister_assets = list_assets(canister)
+        .await
+        .map_err(ComputeSyncDiffError::ListAssets)?;
+    info!(
+        logger,
+        "Fetching properties for all assets in the canister."
+    );
+    let canister_asset_properties = get_assets_properties(canister, &canister_assets).await?;
+
+    info!(logger, "Comparing assets in the project to the canister.");
+    let project_assets =
+        make_project_assets(None, asset_descriptors, &canister_assets, logger).await?;
+
+    let operations = assemble_batch_operations(
+        &project_assets,
+        canister_assets.clone(),
+        Obsolete,
+        canister_asset_properties,
+    );
+
+    Ok(diff_operations(&operations, &canister_assets))
+}
+
+#[derive(Default)]
+struct KeyOperations {
+    created: Option<String>,
+    deleted: bool,
+    encodings: Vec<(String, Option<Vec<u8>>)>,
+    removed_encodings: Vec<String>,
+    properties: Vec<String>,
+}
+
+fn diff_operations(
+    operations: &[BatchOperationKind],
+    canister_assets: &HashMap<String, AssetDetails>,
+) -> SyncDiff {
+    let mut by_key: BTreeMap<&str, KeyOperations> = BTreeMap::new();
+    for operation in operations {
+        match operation {
+            BatchOperationKind::CreateAsset(args) => {
+                by_key.entry(&args.key).or_default().created = Some(args.content_type.clone())
+            }
+            BatchOperationKind::DeleteAsset(args) => {
+                by_key.entry(&args.key).or_default().deleted = true
+            }
+            BatchOperationKind::SetAssetContent(args) => by_key
+                .entry(&args.key)
+                .or_default()
+                .encodings
+                .push((args.content_encoding.clone(), args.sha256.clone())),
+            BatchOperationKind::UnsetAssetContent(args) => by_key
+                .entry(&args.key)
+                .or_default()
+                .removed_encodings
+                .push(args.content_encoding.clone()),
+            BatchOperationKind::SetAssetProperties(args) => {
+                by_key.entry(&args.key).or_default().properties = changed_properties(args)
+            }
+            // Not generated by a sync
+            BatchOperationKind::Clear(_) => {}
+        }
+    }
+
+    let mut diff = SyncDiff::default();
+    for (key, mut operations) in by_key {
+        let canister_asset = canister_assets.get(key);
+        let previous_sha256 = |content_encoding: &str| {
+            canister_asset
+                .and_then(|asset| {
+                    asset
+                        .encodings
+                        .iter()
+                        .find(|details| details.content_encoding == content_encoding)
+                })
+                .and_then(|details| details.sha256.as_ref())
+                .map(hex::encode)
+        };
+
+        operations.encodings.sort();
+        operations.removed_encodings.sort();
+        let mut change = AssetChange {
+            key: key.to_string(),
+            content_type: operations
+                .created
+                .clone()
+                .or_else(|| canister_asset.map(|asset| asset.content_type.clone()))
+                .unwrap_or_default(),
+            previous_content_type: None,
+            encodings: operations
+                .encodings
+                .iter()
+                .map(|(content_encoding, sha256)| EncodingChange {
+                    content_encoding: content_encoding.clone(),
+                    sha256: sha256.as_ref().map(hex::encode).unwrap_or_default(),
+                    previous_sha256: previous_sha256(content_encoding),
+                })
+                .collect(),
+            removed_encodings: operations.removed_encodings,
+            properties: operations.properties,
+        };
+
+        match (operations.created, operations.deleted) {
+            // An asset is recreated when its content type changes
+            (Some(_), true) => {
+                change.previous_content_type =
+                    canister_asset.map(|asset| asset.content_type.clone());
+                diff.changed.push(change);
+            }
+            (Some(_), false) => diff.new.push(change),
+            (None, true) => diff.deleted.push(key.to_string()),
+            (None, false)
+                if !change.encodings.is_empty() || !change.removed_encodings.is_empty() =>
+            {
+                diff.changed.push(change)
+            }
+            (None, false) if !change.properties.is_empty() => {
+                diff.properties_changed.push(PropertiesChange {
+                    key: key.to_string(),
+                    properties: change.properties,
+                })
+            }
+            (None, false) => {}
+        }
+    }
+
+    diff
+}
+
+fn changed_properties(args: &SetAssetPropertiesArguments) -> Vec<String> {
+    [
+        ("max_age", args.max_age.is_some()),
+        ("headers", args.headers.is_some()),
+        ("allow_raw_access", args.allow_raw_access.is_some()),
+        ("is_aliased", args.is_aliased.is_some()),
+        ("redirect", args.redirect.is_some()),
+        ("rewrite_from", args.rewrite_from.is_some()),
+    ]
+    .into_iter()
+    .filter(|(_, changed)| *changed)
+    .map(|(name, _)| name.to_string())
+    .collect()
+}
+
+// One line per asset, prefixed with + (new), ~ (changed), * (properties only) or - (deleted)
+impl fmt::Display for SyncDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.new {
+            writeln!(
+                f,
+                "+ {} ({}){}",
+                change.key,
+                change.content_type,
+                change.details()
+            )?;
+        }
+        for change in &self.changed {
+            match &change.previous_content_type {
+                Some(previous) => writeln!(
+                    f,
+                    "~ {} ({} -> {}){}",
+                    change.key,
+                    previous,
+                    change.content_type,
+                    change.details()
+                )?,
+                None => writeln!(f, "~ {}{}", change.key, change.details())?,
+            }
+        }
+        for change in &self.properties_changed {
+            writeln!(
+                f,
+                "* {}: properties {}",
+                change.key,
+                change.properties.join(", ")
+            )?;
+        }
+        for key in &self.deleted {
+            writeln!(f, "- {}", key)?;
+        }
+
+        write!(
+            f,
+            "{} new, {} changed, {} with changed properties, {} deleted",
+            self.new.len(),
+            self.changed.len(),
+            self.properties_changed.len(),
+            self.deleted.len()
+        )
+    }
+}
+
+impl AssetChange {
+    fn details(&self) -> String {
+        let mut details: Vec<String> = self
+            .encodings
+            .iter()
+            .map(|encoding| match &encoding.previous_sha256 {
+                Some(previous) => format!(
+                    "{} {} -> {}",
+                    encoding.content_encoding,
+                    short_sha256(previous),
+                    short_sha256(&encoding.sha256)
+                ),
+                None => format!(
+                    "{} {}",
+                    encoding.content_encoding,
+                    short_sha256(&encoding.sha256)
+                ),
+            })
+            .collect();
+        details.extend(
+            self.removed_encodings
+                .iter()
+                .map(|content_encoding| format!("{} removed", content_encoding)),
+        );
+        if !self.properties.is_empty() {
+            details.push(format!("properties {}", self.properties.join(", ")));
+        }
+
+        if details.is_empty() {
+            String::new()
+        } else {
+            format!(": {}", details.join(", "))
+        }
+    }
+}
+
+fn short_sha256(sha256: &str) -> &str {
+    &sha256[..sha256.len().min(12)]
+}
+
+#[cfg(test)]
+mod test_diff_operations {
+    use super::{diff_operations, AssetChange, EncodingChange, PropertiesChange, SyncDiff};
+    use crate::canister_api::types::asset::{
+        AssetDetails, AssetEncodingDetails, SetAssetPropertiesArguments,
+    };
+    use crate::canister_api::types::batch_upload::common::{
+        CreateAssetArguments, DeleteAssetArguments, SetAssetContentArguments,
+        UnsetAssetContentArguments,
+    };
+    use crate::canister_api::types::batch_upload::v1::BatchOperationKind;
+    use std::collections::HashMap;
+
+    fn canister_asset(key: &str, content_type: &str, encodings: &[(&str, u8)]) -> AssetDetails {
+        AssetDetails {
+            key: key.to_string(),
+            content_type: content_type.to_string(),
+            encodings: encodings
+                .iter()
+                .map(|(content_encoding, sha256)| AssetEncodingDetails {
+                    content_encoding: content_encoding.to_string(),
+                    sha256: Some(vec![*sha256; 32]),
+                })
+                .collect(),
+        }
+    }
+
+    fn create(key: &str, content_type: &str) -> BatchOperationKind {
+        BatchOperationKind::CreateAsset(CreateAssetArguments {
+            key: key.to_string(),
+            content_type: content_type.to_string(),
+            max_age: None,
+            headers: None,
+            enable_aliasing: None,
+            allow_raw_access: None,
+            redirect: None,
+            rewrite_from: None,
+        })
+    }
+
+    fn set_content(key: &str, content_encoding: &str, sha256: u8) -> BatchOperationKind {
+        BatchOperationKind::SetAssetContent(SetAssetContentArguments {
+            key: key.to_string(),
+            content_encoding: content_encoding.to_string(),
+            chunk_ids: vec![],
+            sha256: Some(vec![sha256; 32]),
+        })
+    }
+
+    fn sha256(byte: u8) -> String {
+        hex::encode([byte; 32])
+    }
+
+    #[test]
+    fn classifies_operations_by_key() {
+        let canister_assets = HashMap::from([
+            (
+                "/index.html".to_string(),
+                canister_asset("/index.html", "text/html", &[("identity", 1), ("gzip", 2)]),
+            ),
+            (
+                "/app.js".to_string(),
+                canister_asset("/app.js", "text/javascript", &[("identity", 3)]),
+            ),
+            (
+                "/logo".to_string(),
+                canister_asset("/logo", "image/png", &[("identity", 4)]),
+            ),
+            (
+                "/old.txt".to_string(),
+                canister_asset("/old.txt", "text/plain", &[("identity", 5)]),
+            ),
+        ]);
+        let operations = vec![
+            BatchOperationKind::DeleteAsset(DeleteAssetArguments {
+                key: "/old.txt".to_string(),
+            }),
+            BatchOperationKind::DeleteAsset(DeleteAssetArguments {
+                key: "/logo".to_string(),
+            }),
+            create("/logo", "image/svg+xml"),
+            create("/about.html", "text/html"),
+            BatchOperationKind::UnsetAssetContent(UnsetAssetContentArguments {
+                key: "/index.html".to_string(),
+                content_encoding: "gzip".to_string(),
+            }),
+            set_content("/logo", "identity", 6),
+            set_content("/about.html", "identity", 7),
+            set_content("/index.html", "identity", 8),
+            BatchOperationKind::SetAssetProperties(SetAssetPropertiesArguments {
+                key: "/app.js".to_string(),
+                max_age: Some(Some(60)),
+                headers: Some(None),
+                allow_raw_access: None,
+                is_aliased: None,
+                redirect: None,
+                rewrite_from: None,
+            }),
+        ];
+
+        let diff = diff_operations(&operations, &canister_assets);
+
+        assert_eq!(
+            diff,
+            SyncDiff {
+                new: vec![AssetChange {
+                    key: "/about.html".to_string(),
+                    content_type: "text/html".to_string(),
+                    encodings: vec![EncodingChange {
+                        content_encoding: "identity".to_string(),
+                        sha256: sha256(7),
+                        previous_sha256: None,
+                    }],
+                    ..Default::default()
+                }],
+                changed: vec![
+                    AssetChange {
+                        key: "/index.html".to_string(),
+                        content_type: "text/html".to_string(),
+                        encodings: vec![EncodingChange {
+                            content_encoding: "identity".to_string(),
+                            sha256: sha256(8),
+                            previous_sha256: Some(sha256(1)),
+                        }],
+                        removed_encodings: vec!["gzip".to_string()],
+                        ..Default::default()
+                    },
+                    AssetChange {
+                        key: "/logo".to_string(),
+                        content_type: "image/svg+xml".to_string(),
+                        previous_content_type: Some("image/png".to_string()),
+                        encodings: vec![EncodingChange {
+                            content_encoding: "identity".to_string(),
+                            sha256: sha256(6),
+                            previous_sha256: Some(sha256(4)),
+                        }],
+                        ..Default::default()
+                    },
+                ],
+                properties_changed: vec![PropertiesChange {
+                    key: "/app.js".to_string(),
+                    properties: vec!["max_age".to_string(), "headers".to_string()],
+                }],
+                deleted: vec!["/old.txt".to_string()],
+            }
+        );
+        assert_eq!(
+            diff.to_string(),
+            format!(
+                "+ /about.html (text/html): identity {}\n\
+                 ~ /index.html: identity {} -> {}, gzip removed\n\
+                 ~ /logo (image/png -> image/svg+xml): identity {} -> {}\n\
+                 * /app.js: properties max_age, headers\n\
+                 - /old.txt\n\
+                 1 new, 2 changed, 1 with changed properties, 1 deleted",
+                &sha256(7)[..12],
+                &sha256(1)[..12],
+                &sha256(8)[..12],
+                &sha256(4)[..12],
+                &sha256(6)[..12],
+            )
+        );
+    }
+
+    #[test]
+    fn empty_without_operations() {
+        let diff = diff_operations(&[], &HashMap::new());
+
+        assert!(diff.is_empty());
+        assert_eq!(
+            diff.to_string(),
+            "0 new, 0 changed, 0 with changed properties, 0 deleted"
+        );
+    }
+}
@@ -0,0 +1,394 @@
+use crate::asset::config::{
+    CacheConfig, HeadersConfig, RedirectConfig, ASSETS_CONFIG_FILENAME_JSON,
+};
+use crate::asset::content_encoder::ContentEncoder;
+use crate::canister_api::methods::asset_properties::get_assets_properties;
+use crate::canister_api::methods::get::{get, get_chunk};
+use crate::canister_api::methods::list::list_assets;
+use crate::canister_api::types::asset::{AssetDetails, AssetEncodingDetails, AssetProperties};
+use crate::canister_api::types::get::{GetChunkRequest, GetRequest};
+use crate::error::DownloadError;
+use crate::error::DownloadError::{
+    ContentMismatch, DecodeContentFailed, GetAssetFailed, InvalidKey, ListAssets, MissingSha256,
+    NotFound, SerializeConfigFailed,
+};
+use candid::Nat;
+use ic_utils::Canister;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use slog::{info, warn, Logger};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+const IDENTITY: &str = "identity";
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+/// A rule of an exported `.ic-assets.json`, matching a single asset.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+struct ExportedRule {
+    r#match: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache: Option<CacheConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    headers: Option<HeadersConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ignore: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    enable_aliasing: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allow_raw_access: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encodings: Option<Vec<ContentEncoder>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redirect: Option<RedirectConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rewrite_from: Option<Vec<String>>,
+}
+
+/// Download assets into a directory, each to the path of its key below it.
+/// Every encoding is verified against the sha256 the canister lists for it.
+pub async fn download(
+    canister: &Canister<'_>,
+    keys: &[String],
+    dir: &Path,
+    logger: &Logger,
+) -> Result<(), DownloadError> {
+    let canister_assets = list_assets(canister).await.map_err(ListAssets)?;
+
+    for key in keys {
+        let asset = canister_assets
+            .get(key)
+            .ok_or_else(|| NotFound(key.clone()))?;
+        download_asset(canister, asset, dir, logger).await?;
+    }
+
+    Ok(())
+}
+
+/// Download all assets into a directory, along with an `.ic-assets.json` that
+/// restores their properties and encodings when the directory is synced again.
+pub async fn export(
+    canister: &Canister<'_>,
+    dir: &Path,
+    logger: &Logger,
+) -> Result<(), DownloadError> {
+    let canister_assets = list_assets(canister).await.map_err(ListAssets)?;
+    info!(
+        logger,
+        "Fetching properties for all assets in the canister."
+    );
+    let canister_asset_properties = get_assets_properties(canister, &canister_assets).await?;
+
+    let mut assets: Vec<&AssetDetails> = canister_assets.values().collect();
+    assets.sort_by(|a, b| a.key.cmp(&b.key));
+
+    let mut rules = vec![];
+    for asset in assets {
+        download_asset(canister, asset, dir, logger).await?;
+        rules.push(exported_rule(
+            asset,
+            canister_asset_properties.get(&asset.key),
+        ));
+    }
+
+    let config = serde_json::to_string_pretty(&rules).map_err(SerializeConfigFailed)?;
+    dfx_core::fs::create_dir_all(dir)?;
+    dfx_core::fs::write(dir.join(ASSETS_CONFIG_FILENAME_JSON), config)?;
+    info!(
+        logger,
+        "Exported {} assets to {}.",
+        rules.len(),
+        dir.display()
+    );
+
+    Ok(())
+}
+
+async fn download_asset(
+    canister: &Canister<'_>,
+    asset: &AssetDetails,
+    dir: &Path,
+    logger: &Logger,
+) -> Result<(), DownloadError> {
+    let path = dir.join(key_path(&asset.key)?);
+
+    let mut identity = None;
+    let mut decodable = None;
+    for encoding in &asset.encodings {
+        let content = download_encoding(canister, &asset.key, encoding).await?;
+        if encoding.content_encoding == IDENTITY {
+            identity = Some(content);
+        } else if decodable.is_none() {
+            if let Some(encoder) = content_encoder(&encoding.content_encoding) {
+                decodable = Some((encoder, content));
+            }
+        }
+    }
+
+    let content = match (identity, decodable) {
+        (Some(content), _) => content,
+        (None, Some((encoder, content))) => decode(&content, encoder)
+            .map_err(|e| DecodeContentFailed(asset.key.clone(), encoder.to_string(), e))?,
+        (None, None) => {
+            warn!(
+                logger,
+                "Asset {} has no content that can be written, writing an empty file.", asset.key
+            );
+            vec![]
+        }
+    };
+
+    if let Some(parent) = path.parent() {
+        dfx_core::fs::create_dir_all(parent)?;
+    }
+    dfx_core::fs::write(&path, content)?;
+    info!(
+        logger,
+        "  {} ({})",
+        asset.key,
+        asset
+            .encodings
+            .iter()
+            .map(|encoding| encoding.content_encoding.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    Ok(())
+}
+
+async fn download_encoding(
+    canister: &Canister<'_>,
+    key: &str,
+    encoding: &AssetEncodingDetails,
+) -> Result<Vec<u8>, DownloadError> {
+    let content_encoding = &encoding.content_encoding;
+    let expected_sha256 = encoding
+        .sha256
+        .as_ref()
+        .ok_or_else(|| MissingSha256(key.to_string(), content_encoding.clone()))?;
+    let get_failed = |e| GetAssetFailed(key.to_string(), content_encoding.clone(), e);
+
+    let first = get(
+        canister,
+        &GetRequest {
+            key: key.to_string(),
+            accept_encodings: vec![content_encoding.clone()],
+        },
+    )
+    .await
+    .map_err(get_failed)?;
+
+    // Lengths beyond usize cannot be held in memory, reading stops at the first empty chunk
+    let total_length = usize::try_from(&first.total_length.0).unwrap_or(usize::MAX);
+    let mut content = first.content.into_vec();
+    let mut index = 1_usize;
+    while content.len() < total_length {
+        let chunk = get_chunk(
+            canister,
+            &GetChunkRequest {
+                key: key.to_string(),
+                content_encoding: content_encoding.clone(),
+                index: Nat::from(index),
+                sha256: first.sha256.clone(),
+            },
+        )
+        .await
+        .map_err(get_failed)?;
+        if chunk.content.is_empty() {
+            break;
+        }
+        content.extend_from_slice(&chunk.content);
+        index += 1;
+    }
+
+    if Sha256::digest(&content).as_slice() != expected_sha256.as_slice() {
+        return Err(ContentMismatch(key.to_string(), content_encoding.clone()));
+    }
+
+    Ok(content)
+}
+
+// "/css/main.css" is written to "css/main.css" below the target directory
+fn key_path(key: &str) -> Result<PathBuf, DownloadError> {
+    let components: Vec<&str> = key.strip_prefix('/').unwrap_or(key).split('/').collect();
+    let writable = components.iter().all(|component| {
+        !component.is_empty()
+            && *component != "."
+            && *component != ".."
+            && !component.contains('\\')
+    });
+    if !writable {
+        return Err(InvalidKey(key.to_string()));
+    }
+
+    Ok(components.iter().collect())
+}
+
+fn exported_rule(asset: &AssetDetails, properties: Option<&AssetProperties>) -> ExportedRule {
+    let relative = asset.key.strip_prefix('/').unwrap_or(&asset.key);
+
+    // Dot files are only synced when a rule includes them
+    let is_hidden = relative
+        .split('/')
+        .any(|component| component.starts_with('.'));
+
+    let mut encodings: Vec<ContentEncoder> = asset
+        .encodings
+        .iter()
+        .filter_map(|encoding| content_encoder(&encoding.content_encoding))
+        .collect();
+    encodings.sort();
+
+    let default_properties = AssetProperties::default();
+    let properties = properties.unwrap_or(&default_properties);
+
+    ExportedRule {
+        r#match: escape_glob(relative),
+        cache: properties.max_age.map(|max_age| CacheConfig {
+            max_age: Some(max_age),
+        }),
+        headers: properties
+            .headers
+            .as_ref()
+            .map(|headers| headers.clone().into_iter().collect()),
+        ignore: is_hidden.then_some(false),
+        enable_aliasing: properties.is_aliased,
+        allow_raw_access: properties.allow_raw_access,
+        encodings: Some(encodings),
+        redirect: properties.redirect.clone(),
+        rewrite_from: properties.rewrite_from.clone(),
+    }
+}
+
+fn content_encoder(content_encoding: &str) -> Option<ContentEncoder> {
+    match content_encoding {
+        "gzip" => Some(ContentEncoder::Gzip),
+        "br" => Some(ContentEncoder::Brotli),
+        "zstd" => Some(ContentEncoder::Zstd),
+        _ => None,
+    }
+}
+
+fn decode(content: &[u8], encoder: ContentEncoder) -> Result<Vec<u8>, std::io::Error> {
+    let mut decoded = vec![];
+    match encoder {
+        ContentEncoder::Gzip => {
+            flate2::read::GzDecoder::new(content).read_to_end(&mut decoded)?;
+        }
+        ContentEncoder::Brotli => {
+            brotli::Decompressor::new(content, BROTLI_BUFFER_SIZE).read_to_end(&mut decoded)?;
+        }
+        ContentEncoder::Zstd => {
+            decoded = zstd::stream::decode_all(content)?;
+        }
+    }
+    Ok(decoded)
+}
+
+// Match file names with glob metacharacters literally
+fn escape_glob(path: &str) -> String {
+    let mut escaped = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            '*' | '?' | '[' | ']' | '{' | '}' => {
+                escaped.push('[');
+                escaped.push(c);
+                escaped.push(']');
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test_exported_rules {
+    use super::{escape_glob, exported_rule, key_path, ExportedRule};
+    use crate::asset::config::CacheConfig;
+    use crate::asset::content_encoder::ContentEncoder;
+    use crate::canister_api::types::asset::{AssetDetails, AssetEncodingDetails, AssetProperties};
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    fn canister_asset(key: &str, encodings: &[&str]) -> AssetDetails {
+        AssetDetails {
+            key: key.to_string(),
+            content_type: "text/html".to_string(),
+            encodings: encodings
+                .iter()
+                .map(|content_encoding| AssetEncodingDetails {
+                    content_encoding: content_encoding.to_string(),
+                    sha256: Some(vec![0; 32]),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn maps_keys_to_paths_below_the_directory() {
+        assert_eq!(
+            key_path("/css/main.css").unwrap(),
+            ["css", "main.css"].iter().collect::<PathBuf>()
+        );
+        assert_eq!(
+            key_path("/index.html").unwrap(),
+            PathBuf::from("index.html")
+        );
+        assert!(key_path("/").is_err());
+        assert!(key_path("/a//b").is_err());
+        assert!(key_path("/../outside").is_err());
+        assert!(key_path("/a/./b").is_err());
+    }
+
+    #[test]
+    fn escapes_glob_metacharacters() {
+        assert_eq!(escape_glob("css/main.css"), "css/main.css");
+        assert_eq!(escape_glob("a*b?[c]{d}"), "a[*]b[?][[]c[]][{]d[}]");
+    }
+
+    #[test]
+    fn captures_properties_and_encodings() {
+        let asset = canister_asset("/.well-known/index.html", &["identity", "br", "gzip"]);
+        let properties = AssetProperties {
+            max_age: Some(600),
+            headers: Some(HashMap::from([(
+                "x-header".to_string(),
+                "value".to_string(),
+            )])),
+            allow_raw_access: Some(false),
+            is_aliased: Some(true),
+            redirect: None,
+            rewrite_from: None,
+        };
+
+        assert_eq!(
+            exported_rule(&asset, Some(&properties)),
+            ExportedRule {
+                r#match: ".well-known/index.html".to_string(),
+                cache: Some(CacheConfig { max_age: Some(600) }),
+                headers: Some([("x-header".to_string(), "value".to_string())].into()),
+                ignore: Some(false),
+                enable_aliasing: Some(true),
+                allow_raw_access: Some(false),
+                encodings: Some(vec![ContentEncoder::Gzip, ContentEncoder::Brotli]),
+                redirect: None,
+                rewrite_from: None,
+            }
+        );
+    }
+
+    #[test]
+    fn disables_compression_for_identity_only_assets() {
+        let asset = canister_asset("/image.png", &["identity"]);
+        let rule = exported_rule(&asset, None);
+
+        assert_eq!(rule.encodings, Some(vec![]));
+        assert_eq!(rule.ignore, None);
+        assert_eq!(rule.cache, None);
+        assert_eq!(
+            serde_json::to_string(&rule).unwrap(),
+            r#"{"match":"image.png","encodings":[]}"#
+        );
+    }
+}
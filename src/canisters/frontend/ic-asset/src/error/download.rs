@@ -0,0 +1,48 @@
+use crate::error::get_asset_properties::GetAssetPropertiesError;
+use dfx_core::error::fs::FsError;
+use ic_agent::AgentError;
+use thiserror::Error;
+
+/// Errors related to downloading assets from the asset canister.
+#[derive(Error, Debug)]
+pub enum DownloadError {
+    /// The content of an encoding does not match the sha256 the canister lists for it.
+    #[error("Content of asset '{0}' with {1} encoding does not match its sha256")]
+    ContentMismatch(String, String),
+
+    /// Failed to decode an encoding of an asset without identity encoding.
+    #[error("Failed to decode content of asset '{0}' with {1} encoding: {2}")]
+    DecodeContentFailed(String, String, std::io::Error),
+
+    /// Failed to get an asset from the asset canister.
+    #[error("Failed to get asset '{0}' with {1} encoding: {2}")]
+    GetAssetFailed(String, String, AgentError),
+
+    /// Failed when reading assets properties from the asset canister.
+    #[error(transparent)]
+    GetAssetProperties(#[from] GetAssetPropertiesError),
+
+    /// The key of an asset cannot be mapped to a file below the target directory.
+    #[error("Asset key '{0}' cannot be written to a file")]
+    InvalidKey(String),
+
+    /// Failed to list assets in the asset canister.
+    #[error("Failed to list assets: {0}")]
+    ListAssets(AgentError),
+
+    /// The canister does not list a sha256 for an encoding, so it cannot be verified.
+    #[error("No sha256 listed for asset '{0}' with {1} encoding")]
+    MissingSha256(String, String),
+
+    /// An asset to download does not exist in the asset canister.
+    #[error("Asset '{0}' not found")]
+    NotFound(String),
+
+    /// Failed to serialize the asset configuration.
+    #[error("Failed to serialize asset configuration: {0}")]
+    SerializeConfigFailed(serde_json::Error),
+
+    /// Failed to write a file.
+    #[error(transparent)]
+    WriteFailed(#[from] FsError),
+}
@@ -11,4 +11,8 @@ pub enum LoadRuleError {
     /// The glob pattern was not valid.
     #[error("{0} is not a valid glob pattern: {1}")]
     InvalidGlobPattern(String, globset::Error),
+
+    /// A redirect used a status code that is not a redirect the canister can serve.
+    #[error("Redirect for {0} has status code {1}, expected 301, 302, 307 or 308")]
+    InvalidRedirectStatusCode(String, u16),
 }
@@ -2,10 +2,12 @@
 
 mod compatibility;
 mod compute_evidence;
+mod compute_sync_diff;
 mod create_chunk;
 mod create_encoding;
 mod create_project_asset;
 mod downgrade_commit_batch_arguments;
+mod download;
 mod gather_asset_descriptors;
 mod get_asset_config;
 mod get_asset_properties;
@@ -16,13 +18,16 @@ mod prepare_sync_for_proposal;
 mod sync;
 mod upload;
 mod upload_content;
+mod watch;
 
 pub use compatibility::CompatibilityError;
 pub use compute_evidence::ComputeEvidenceError;
+pub use compute_sync_diff::ComputeSyncDiffError;
 pub use create_chunk::CreateChunkError;
 pub use create_encoding::CreateEncodingError;
 pub use create_project_asset::CreateProjectAssetError;
 pub use downgrade_commit_batch_arguments::DowngradeCommitBatchArgumentsV1ToV0Error;
+pub use download::DownloadError;
 pub use gather_asset_descriptors::GatherAssetDescriptorsError;
 pub use get_asset_config::GetAssetConfigError;
 pub use get_asset_properties::GetAssetPropertiesError;
@@ -33,3 +38,4 @@ pub use prepare_sync_for_proposal::PrepareSyncForProposalError;
 pub use sync::SyncError;
 pub use upload::UploadError;
 pub use upload_content::UploadContentError;
+pub use watch::WatchError;
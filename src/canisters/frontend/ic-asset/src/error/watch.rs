@@ -0,0 +1,19 @@
+use crate::error::sync::SyncError;
+use dfx_core::error::fs::FsError;
+use thiserror::Error;
+
+/// Errors related to watching directories and syncing their changes.
+#[derive(Error, Debug)]
+pub enum WatchError {
+    /// A source directory does not exist or cannot be accessed.
+    #[error("Invalid source directory: {0}")]
+    InvalidSourceDirectory(FsError),
+
+    /// The initial sync failed.
+    #[error(transparent)]
+    Sync(#[from] SyncError),
+
+    /// Failed to watch a source directory for changes.
+    #[error("Failed to watch for changes: {0}")]
+    WatchFailed(notify::Error),
+}
@@ -20,7 +20,7 @@
 //!     .with_agent(&agent)
 //!     .build()?;
 //! let logger = slog::Logger::root(slog::Discard, slog::o!());
-//! ic_asset::sync(&canister, &[concat!(env!("CARGO_MANIFEST_DIR"), "assets/").as_ref()], &logger).await?;
+//! ic_asset::sync(&canister, &[concat!(env!("CARGO_MANIFEST_DIR"), "assets/").as_ref()], None, &logger).await?;
 //! # Ok(())
 //! # }
 
@@ -34,12 +34,20 @@
 mod asset;
 mod batch_upload;
 mod canister_api;
+mod diff;
+mod download;
 pub mod error;
 mod evidence;
+mod progress;
 mod sync;
 mod upload;
+mod watch;
 
+pub use diff::{compute_sync_diff, AssetChange, EncodingChange, PropertiesChange, SyncDiff};
+pub use download::{download, export};
 pub use evidence::compute_evidence;
+pub use progress::{UploadProgress, UploadProgressReporter};
 pub use sync::prepare_sync_for_proposal;
 pub use sync::sync;
 pub use upload::upload;
+pub use watch::watch;
@@ -0,0 +1,39 @@
+use std::time::Duration;
+
+/// Progress of uploading the contents of assets to an asset canister.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UploadProgress {
+    /// Number of files to upload or to find already in place.
+    pub total_files: usize,
+
+    /// Number of files whose encodings are all uploaded or already in place.
+    pub processed_files: usize,
+
+    /// Bytes of all encodings that need to be uploaded, as far as known.
+    /// Grows while files are being loaded and encoded.
+    pub total_bytes: usize,
+
+    /// Bytes of encodings uploaded so far.
+    pub uploaded_bytes: usize,
+
+    /// Estimated time until `total_bytes` are uploaded, at the rate observed so far.
+    /// `None` until the first chunk is uploaded.
+    pub eta: Option<Duration>,
+}
+
+/// Receives progress updates while the contents of assets are uploaded.
+///
+/// Implemented for closures taking an [`UploadProgress`].
+pub trait UploadProgressReporter: Send + Sync {
+    /// Called after every uploaded chunk and every processed file.
+    fn report(&self, progress: &UploadProgress);
+}
+
+impl<F> UploadProgressReporter for F
+where
+    F: Fn(&UploadProgress) + Send + Sync,
+{
+    fn report(&self, progress: &UploadProgress) {
+        self(progress)
+    }
+}
@@ -15,6 +15,7 @@ use crate::canister_api::methods::{
     batch::{commit_batch, create_batch},
     list::list_assets,
 };
+use crate::canister_api::types::asset::AssetDetails;
 use crate::canister_api::types::batch_upload::v0;
 use crate::canister_api::types::batch_upload::v1::BatchOperationKind;
 use crate::canister_api::types::batch_upload::{
@@ -30,23 +31,42 @@ use crate::error::SyncError;
 use crate::error::SyncError::CommitBatchFailed;
 use crate::error::UploadContentError;
 use crate::error::UploadContentError::{CreateBatchFailed, ListAssetsFailed};
+use crate::progress::UploadProgressReporter;
 use candid::Nat;
 use ic_agent::AgentError;
 use ic_utils::Canister;
 use slog::{debug, info, trace, warn, Logger};
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
 /// Sets the contents of the asset canister to the contents of a directory, including deleting old assets.
 pub async fn upload_content_and_assemble_sync_operations(
     canister: &Canister<'_>,
     dirs: &[&Path],
+    progress: Option<&dyn UploadProgressReporter>,
     logger: &Logger,
 ) -> Result<CommitBatchArguments, UploadContentError> {
-    let asset_descriptors = gather_asset_descriptors(dirs, logger)?;
+    upload_changed_content_and_assemble_sync_operations(canister, dirs, None, progress, logger)
+        .await
+}
 
-    let canister_assets = list_assets(canister).await.map_err(ListAssetsFailed)?;
+/// Like `upload_content_and_assemble_sync_operations`, but if `changed` is set only
+/// assets with a source at or below one of the changed paths are compared, along
+/// with assets whose source no longer exists.
+pub(crate) async fn upload_changed_content_and_assemble_sync_operations(
+    canister: &Canister<'_>,
+    dirs: &[&Path],
+    changed: Option<&[PathBuf]>,
+    progress: Option<&dyn UploadProgressReporter>,
+    logger: &Logger,
+) -> Result<CommitBatchArguments, UploadContentError> {
+    let mut asset_descriptors = gather_asset_descriptors(dirs, logger)?;
+
+    let mut canister_assets = list_assets(canister).await.map_err(ListAssetsFailed)?;
+    if let Some(changed) = changed {
+        retain_changed(&mut asset_descriptors, &mut canister_assets, changed);
+    }
     info!(
         logger,
         "Fetching properties for all assets in the canister."
@@ -62,7 +82,7 @@ pub async fn upload_content_and_assemble_sync_operations(
         "Staging contents of new and changed assets in batch {}:", batch_id
     );
 
-    let chunk_uploader = ChunkUploader::new(canister.clone(), batch_id.clone());
+    let chunk_uploader = ChunkUploader::new(canister.clone(), batch_id.clone(), progress);
 
     let project_assets = make_project_assets(
         Some(&chunk_uploader),
@@ -103,10 +123,19 @@ pub async fn upload_content_and_assemble_sync_operations(
 pub async fn sync(
     canister: &Canister<'_>,
     dirs: &[&Path],
+    progress: Option<&dyn UploadProgressReporter>,
     logger: &Logger,
 ) -> Result<(), SyncError> {
     let commit_batch_args =
-        upload_content_and_assemble_sync_operations(canister, dirs, logger).await?;
+        upload_content_and_assemble_sync_operations(canister, dirs, progress, logger).await?;
+    commit_sync_operations(canister, commit_batch_args, logger).await
+}
+
+pub(crate) async fn commit_sync_operations(
+    canister: &Canister<'_>,
+    commit_batch_args: CommitBatchArguments,
+    logger: &Logger,
+) -> Result<(), SyncError> {
     let canister_api_version = api_version(canister).await;
     debug!(logger, "Canister API version: {canister_api_version}. ic-asset API version: {BATCH_UPLOAD_API_VERSION}");
     info!(logger, "Committing batch.");
@@ -178,9 +207,10 @@ async fn commit_in_stages(
 pub async fn prepare_sync_for_proposal(
     canister: &Canister<'_>,
     dirs: &[&Path],
+    progress: Option<&dyn UploadProgressReporter>,
     logger: &Logger,
 ) -> Result<(), PrepareSyncForProposalError> {
-    let arg = upload_content_and_assemble_sync_operations(canister, dirs, logger).await?;
+    let arg = upload_content_and_assemble_sync_operations(canister, dirs, progress, logger).await?;
     let arg = sort_batch_operations(arg);
     let batch_id = arg.batch_id.clone();
 
@@ -208,6 +238,19 @@ pub async fn prepare_sync_for_proposal(
     Ok(())
 }
 
+fn retain_changed(
+    asset_descriptors: &mut Vec<AssetDescriptor>,
+    canister_assets: &mut HashMap<String, AssetDetails>,
+    changed: &[PathBuf],
+) {
+    let existing: HashSet<String> = asset_descriptors.iter().map(|d| d.key.clone()).collect();
+    asset_descriptors.retain(|d| changed.iter().any(|path| d.source.starts_with(path)));
+    let retained: HashSet<&str> = asset_descriptors.iter().map(|d| d.key.as_str()).collect();
+
+    // Assets of unchanged sources must not be deleted as obsolete
+    canister_assets.retain(|key, _| !existing.contains(key) || retained.contains(key.as_str()));
+}
+
 fn sort_batch_operations(mut args: CommitBatchArguments) -> CommitBatchArguments {
     args.operations.sort();
     args
@@ -857,3 +900,62 @@ mod test_gathering_asset_descriptors_with_tempdir {
         assert_eq!(dbg!(asset_descriptors), expected_asset_descriptors);
     }
 }
+
+#[cfg(test)]
+mod test_retain_changed {
+    use super::{retain_changed, AssetDescriptor};
+    use crate::canister_api::types::asset::AssetDetails;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    fn descriptor(key: &str) -> AssetDescriptor {
+        AssetDescriptor {
+            source: PathBuf::from("/assets").join(&key[1..]),
+            key: key.to_string(),
+            config: Default::default(),
+        }
+    }
+
+    fn canister_assets(keys: &[&str]) -> HashMap<String, AssetDetails> {
+        keys.iter()
+            .map(|key| {
+                let asset = AssetDetails {
+                    key: key.to_string(),
+                    encodings: vec![],
+                    content_type: "text/plain".to_string(),
+                };
+                (key.to_string(), asset)
+            })
+            .collect()
+    }
+
+    fn sorted<'a>(keys: impl Iterator<Item = &'a String>) -> Vec<&'a str> {
+        let mut keys: Vec<&str> = keys.map(|key| key.as_str()).collect();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn keeps_changed_and_removed_assets() {
+        let mut asset_descriptors = vec![
+            descriptor("/index.html"),
+            descriptor("/css/main.css"),
+            descriptor("/css/print.css"),
+            descriptor("/js/app.js"),
+        ];
+        let mut assets =
+            canister_assets(&["/index.html", "/css/main.css", "/js/app.js", "/old.js"]);
+        let changed = [
+            PathBuf::from("/assets/css"),
+            PathBuf::from("/assets/old.js"),
+        ];
+
+        retain_changed(&mut asset_descriptors, &mut assets, &changed);
+
+        assert_eq!(
+            sorted(asset_descriptors.iter().map(|d| &d.key)),
+            ["/css/main.css", "/css/print.css"]
+        );
+        assert_eq!(sorted(assets.keys()), ["/css/main.css", "/old.js"]);
+    }
+}
@@ -14,6 +14,7 @@ use crate::canister_api::types::batch_upload::v0;
 use crate::error::CompatibilityError::DowngradeV1TOV0Failed;
 use crate::error::UploadError;
 use crate::error::UploadError::{CommitBatchFailed, CreateBatchFailed, ListAssetsFailed};
+use crate::progress::UploadProgressReporter;
 use ic_utils::Canister;
 use slog::{info, Logger};
 use std::collections::HashMap;
@@ -23,6 +24,7 @@ use std::path::PathBuf;
 pub async fn upload(
     canister: &Canister<'_>,
     files: HashMap<String, PathBuf>,
+    progress: Option<&dyn UploadProgressReporter>,
     logger: &Logger,
 ) -> Result<(), UploadError> {
     let asset_descriptors: Vec<AssetDescriptor> = files
@@ -42,7 +44,7 @@ pub async fn upload(
 
     info!(logger, "Staging contents of new and changed assets:");
 
-    let chunk_upload_target = ChunkUploader::new(canister.clone(), batch_id.clone());
+    let chunk_upload_target = ChunkUploader::new(canister.clone(), batch_id.clone(), progress);
 
     let project_assets = make_project_assets(
         Some(&chunk_upload_target),
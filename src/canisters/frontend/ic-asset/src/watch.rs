@@ -0,0 +1,127 @@
+use crate::asset::config::{ASSETS_CONFIG_FILENAME_JSON, ASSETS_CONFIG_FILENAME_JSON5};
+use crate::canister_api::methods::batch::delete_batch;
+use crate::canister_api::types::batch_upload::common::DeleteBatchArguments;
+use crate::error::SyncError;
+use crate::error::WatchError;
+use crate::error::WatchError::{InvalidSourceDirectory, WatchFailed};
+use crate::progress::UploadProgressReporter;
+use crate::sync::{
+    commit_sync_operations, sync, upload_changed_content_and_assemble_sync_operations,
+};
+use ic_utils::Canister;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use slog::{info, warn, Logger};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+// Editors and build tools touch several files per save
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Syncs the directories to the asset canister, then keeps watching them and syncs
+/// the assets of changed files as they change, until the future is dropped.
+///
+/// Changes to `.ic-assets.json` or `.ic-assets.json5` trigger a full sync, since they
+/// can affect any asset below them.
+pub async fn watch(
+    canister: &Canister<'_>,
+    dirs: &[&Path],
+    progress: Option<&dyn UploadProgressReporter>,
+    logger: &Logger,
+) -> Result<(), WatchError> {
+    sync(canister, dirs, progress, logger).await?;
+
+    let (sender, mut receiver) = unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = sender.send(event);
+    })
+    .map_err(WatchFailed)?;
+    for dir in dirs {
+        let dir = dfx_core::fs::canonicalize(dir).map_err(InvalidSourceDirectory)?;
+        watcher
+            .watch(&dir, RecursiveMode::Recursive)
+            .map_err(WatchFailed)?;
+    }
+
+    info!(logger, "Watching for changes.");
+    while let Some(changed) = next_changes(&mut receiver, logger).await {
+        if changed.is_empty() {
+            continue;
+        }
+        let changed: Vec<PathBuf> = changed.into_iter().collect();
+
+        let result = if changed.iter().any(|path| is_assets_config(path)) {
+            info!(logger, "Asset configuration changed, syncing all assets.");
+            sync(canister, dirs, progress, logger).await
+        } else {
+            info!(
+                logger,
+                "{} paths changed, syncing their assets.",
+                changed.len()
+            );
+            sync_changes(canister, dirs, &changed, progress, logger).await
+        };
+        if let Err(e) = result {
+            warn!(logger, "Failed to sync changes: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+async fn sync_changes(
+    canister: &Canister<'_>,
+    dirs: &[&Path],
+    changed: &[PathBuf],
+    progress: Option<&dyn UploadProgressReporter>,
+    logger: &Logger,
+) -> Result<(), SyncError> {
+    let commit_batch_args = upload_changed_content_and_assemble_sync_operations(
+        canister,
+        dirs,
+        Some(changed),
+        progress,
+        logger,
+    )
+    .await?;
+
+    if commit_batch_args.operations.is_empty() {
+        info!(logger, "No assets changed.");
+        let batch_id = commit_batch_args.batch_id;
+        let _ = delete_batch(canister, DeleteBatchArguments { batch_id }).await;
+        return Ok(());
+    }
+
+    commit_sync_operations(canister, commit_batch_args, logger).await
+}
+
+/// Waits for the next change, then collects the paths of all changes until none
+/// arrive for `DEBOUNCE`. Returns `None` once the watcher is gone.
+async fn next_changes(
+    receiver: &mut UnboundedReceiver<notify::Result<Event>>,
+    logger: &Logger,
+) -> Option<BTreeSet<PathBuf>> {
+    let mut changed = BTreeSet::new();
+    let mut event = receiver.recv().await?;
+    loop {
+        match event {
+            Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+                changed.extend(event.paths);
+            }
+            Ok(_) => {}
+            Err(e) => warn!(logger, "Error while watching for changes: {}", e),
+        }
+
+        event = match tokio::time::timeout(DEBOUNCE, receiver.recv()).await {
+            Ok(Some(event)) => event,
+            Ok(None) | Err(_) => return Some(changed),
+        };
+    }
+}
+
+fn is_assets_config(path: &Path) -> bool {
+    path.file_name().map_or(false, |name| {
+        name == ASSETS_CONFIG_FILENAME_JSON || name == ASSETS_CONFIG_FILENAME_JSON5
+    })
+}
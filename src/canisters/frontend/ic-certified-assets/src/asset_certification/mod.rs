@@ -150,6 +150,11 @@ impl CertifiedResponses {
         self.delete(HashTreePath::not_found_base_path_v2().as_vec());
     }
 
+    /// Removes all certified responses for requests below a rewrite prefix for certification v2
+    pub fn remove_rewrite_responses(&mut self, prefix: &str) {
+        self.delete(HashTreePath::rewrite_base_path_v2(prefix).as_vec());
+    }
+
     /// Removes the certified fallback response for certification v1
     pub fn remove_fallback_responses_v1(&mut self) {
         self.delete(HashTreePath::not_found_base_path_v1().as_vec());
@@ -173,8 +178,8 @@ impl CertifiedResponses {
     /// `(found, tree)`
     /// * `found`:
     ///   * WitnessResult::Found if `path` has a certified response.
-    ///   * `WitnessResult::FallbackFound` if the path has no certified response, but the fallback path has.
-    ///   * `WitnessResult::NoneFound` if both `path` and the fallback path have no certified response.
+    ///   * `WitnessResult::FallbackFound` if the path has no certified response, but the fallback path or a rewrite prefix of the path has.
+    ///   * `WitnessResult::NoneFound` if both `path` and the fallback paths have no certified response.
     /// * `tree`: The `HashTree` as described above.
     pub fn witness_path(&self, path: &str) -> (HashTree, WitnessResult) {
         let path = AssetPath::from(path);
@@ -196,8 +201,10 @@ impl CertifiedResponses {
                         merge_hash_trees(accumulator, new_proof)
                     });
 
-            let fallback_path = HashTreePath::not_found_base_path_v2();
-            if self.contains_path(fallback_path.as_vec()) {
+            if self
+                .most_specific_fallback_path(&hash_tree_path_root)
+                .is_some()
+            {
                 (combined_proof, WitnessResult::FallbackFound)
             } else {
                 (combined_proof, WitnessResult::NoneFound)
@@ -211,10 +218,24 @@ impl CertifiedResponses {
         if self.contains_path(hash_tree_path_root.as_vec()) {
             path.asset_hash_path_root_v2().expr_path()
         } else {
-            HashTreePath::not_found_base_path_v2().expr_path()
+            self.most_specific_fallback_path(&hash_tree_path_root)
+                .unwrap_or_else(HashTreePath::not_found_base_path_v2)
+                .expr_path()
         }
     }
 
+    // A rewrite prefix takes precedence over the fallback paths above it, up to the root `<*>`
+    fn most_specific_fallback_path(
+        &self,
+        hash_tree_path_root: &HashTreePath,
+    ) -> Option<HashTreePath> {
+        hash_tree_path_root
+            .fallback_paths_v2()
+            .into_iter()
+            .rev()
+            .find(|path| self.contains_path(path.as_vec()))
+    }
+
     /// If the path has certified responses this function creates a hash tree that proves...
     /// * The path is part of the CertifiedResponses hash tree
     /// The hash tree then includes certification the valid certification v1 response for this path.
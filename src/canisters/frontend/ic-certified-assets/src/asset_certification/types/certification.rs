@@ -85,9 +85,15 @@ impl AssetPath {
     ) -> HashTreePath {
         let mut hash_path: Vec<NestedTreeKey> = vec![];
         if matches!(self.0.last(), Some(segment) if segment == "<*>") {
-            // it's a v2 fallback path
+            // it's a v2 fallback path, either for the root or for a rewrite prefix
             hash_path.push("http_expr".into());
-            hash_path.push("<*>".into());
+            let segments = match self.0.first() {
+                Some(first) if first == "http_expr" => &self.0[1..],
+                _ => &self.0[..],
+            };
+            for segment in segments {
+                hash_path.push(segment.as_str().into());
+            }
         } else {
             hash_path.push("http_expr".into());
             hash_path = self.0.iter().fold(hash_path, |mut path, s| {
@@ -110,6 +116,13 @@ impl AssetPath {
         Self(vec!["http_expr".into(), "<*>".into()])
     }
 
+    /// Wildcard path that serves all requests below `prefix`, e.g. `["app", "<*>"]` for `/app`.
+    pub fn rewrite_path(prefix: &str) -> Self {
+        let mut path = Self::from(prefix);
+        path.0.push("<*>".into());
+        path
+    }
+
     pub fn fallback_path_v1() -> Self {
         Self::from(FALLBACK_FILE)
     }
@@ -204,6 +217,14 @@ impl HashTreePath {
         ]))
     }
 
+    pub fn rewrite_base_path_v2(prefix: &str) -> Self {
+        let mut path = vec![NestedTreeKey::String("http_expr".into())];
+        for segment in AssetPath::rewrite_path(prefix).0 {
+            path.push(segment.into());
+        }
+        HashTreePath::from(path)
+    }
+
     pub fn not_found_base_path_v1() -> Self {
         let not_found_path = AssetPath::from(FALLBACK_FILE);
         not_found_path.asset_hash_path_v1()
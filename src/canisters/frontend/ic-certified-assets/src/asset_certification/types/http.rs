@@ -161,6 +161,7 @@ impl HttpResponse {
         callback: &CallbackFunc,
         etags: &[Hash],
         cert_version: u16,
+        content_chunk: &dyn Fn(&AssetEncoding, usize) -> RcBytes,
     ) -> HttpResponse {
         let mut headers = asset.get_headers_for_asset(enc_name, cert_version);
         if let Some(head) = certificate_header {
@@ -169,7 +170,7 @@ impl HttpResponse {
 
         let streaming_strategy = StreamingCallbackToken::create_token(
             enc_name,
-            enc.content_chunks.len(),
+            enc.chunk_count(),
             enc.sha256,
             key,
             chunk_index,
@@ -191,7 +192,7 @@ impl HttpResponse {
                     format!("\"{}\"", hex::encode(enc.sha256)),
                 );
             }
-            (200, enc.content_chunks[chunk_index].clone())
+            (200, content_chunk(enc, chunk_index))
         };
 
         HttpResponse {
@@ -213,6 +214,7 @@ impl HttpResponse {
         callback: &CallbackFunc,
         etags: &[Hash],
         cert_version: u16,
+        content_chunk: &dyn Fn(&AssetEncoding, usize) -> RcBytes,
     ) -> Option<HttpResponse> {
         let most_important_v1 = asset.most_important_encoding_v1();
 
@@ -236,6 +238,7 @@ impl HttpResponse {
                         callback,
                         etags,
                         cert_version,
+                        content_chunk,
                     ));
                 }
             }
@@ -258,6 +261,7 @@ impl HttpResponse {
                         callback,
                         etags,
                         cert_version,
+                        content_chunk,
                     ));
                 }
             }
@@ -281,6 +285,7 @@ impl HttpResponse {
                         callback,
                         etags,
                         cert_version,
+                        content_chunk,
                     ));
                 }
             }
@@ -0,0 +1,536 @@
+//! Storage of the assets served by the state machine.
+//!
+//! Assets are kept on the heap by default. A state backed by stable memory keeps them in
+//! stable structures instead, so they survive upgrades without being serialized.
+//!
+//! Either way the content of encodings is addressed by its SHA-256: encodings with identical
+//! content share their chunks, which are dropped once the last encoding referencing them is.
+//! Content can also be referenced from outside of any asset, so that a canister keeping
+//! files of its own stores content it also serves as an asset only once.
+use crate::{
+    asset_certification::types::{certification::AssetKey, rc_bytes::RcBytes},
+    state_machine::{Asset, AssetEncoding},
+};
+use candid::{CandidType, Decode, Deserialize, Encode};
+use ic_stable_structures::{
+    memory_manager::VirtualMemory, storable::Bound, BTreeMap as StableBTreeMap, DefaultMemoryImpl,
+    Storable,
+};
+use serde_bytes::ByteBuf;
+use std::{borrow::Cow, collections::HashMap};
+
+pub type Memory = VirtualMemory<DefaultMemoryImpl>;
+
+/// The stable memories holding the assets of a state.
+/// They must not be used for anything else.
+pub struct AssetMemories {
+    /// Properties and encodings of all assets, without their content.
+    pub assets: Memory,
+    /// Chunk layout and reference count of each distinct content.
+    pub contents: Memory,
+    /// The content itself, one entry per chunk.
+    pub chunks: Memory,
+}
+
+pub enum AssetStore {
+    Heap(HeapAssets),
+    Stable(StableAssets),
+}
+
+impl Default for AssetStore {
+    fn default() -> Self {
+        Self::Heap(HeapAssets::default())
+    }
+}
+
+impl AssetStore {
+    /// Returns the asset including the content of all its encodings.
+    /// This reads the whole content from stable memory, serving content does not need it.
+    pub fn get(&self, key: &str) -> Option<Cow<'_, Asset>> {
+        match self {
+            Self::Heap(assets) => assets.assets.get(key).map(Cow::Borrowed),
+            Self::Stable(assets) => assets.get(key).map(Cow::Owned),
+        }
+    }
+
+    /// Returns the asset, but the content chunks of its encodings may be left empty.
+    /// Their content can be read one chunk at a time with `content_chunk`.
+    pub fn get_without_content(&self, key: &str) -> Option<Cow<'_, Asset>> {
+        match self {
+            Self::Heap(assets) => assets.assets.get(key).map(Cow::Borrowed),
+            Self::Stable(assets) => assets.get_without_content(key).map(Cow::Owned),
+        }
+    }
+
+    /// Returns a content chunk of an encoding, without reading any other chunk.
+    pub fn content_chunk(&self, enc: &AssetEncoding, index: usize) -> Option<RcBytes> {
+        if let Some(chunk) = enc.content_chunks.get(index) {
+            return Some(chunk.clone());
+        }
+        match self {
+            Self::Heap(_) => None,
+            Self::Stable(assets) => assets.content_chunk(&enc.sha256, index),
+        }
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        match self {
+            Self::Heap(assets) => assets.assets.contains_key(key),
+            Self::Stable(assets) => assets.contains_key(key),
+        }
+    }
+
+    /// Makes the encodings of `asset` use the chunks already stored for the same content.
+    /// Must be called before response hashes are computed, since the chunk boundaries of
+    /// the stored content are the ones that will be served.
+    pub fn share_content(&self, asset: &mut Asset) {
+        match self {
+            Self::Heap(assets) => assets.share_content(asset),
+            Self::Stable(assets) => assets.share_content(asset),
+        }
+    }
+
+    pub fn insert(&mut self, key: AssetKey, asset: Asset) {
+        match self {
+            Self::Heap(assets) => assets.insert(key, asset),
+            Self::Stable(assets) => assets.insert(key, asset),
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        match self {
+            Self::Heap(assets) => assets.remove(key),
+            Self::Stable(assets) => assets.remove(key),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        match self {
+            Self::Heap(assets) => *assets = HeapAssets::default(),
+            Self::Stable(assets) => assets.clear(),
+        }
+    }
+
+    pub fn keys(&self) -> Vec<AssetKey> {
+        match self {
+            Self::Heap(assets) => assets.assets.keys().cloned().collect(),
+            Self::Stable(assets) => assets.keys(),
+        }
+    }
+
+    /// Iterates over all assets like `get_without_content`.
+    pub fn iter_without_content(
+        &self,
+    ) -> Box<dyn Iterator<Item = (AssetKey, Cow<'_, Asset>)> + '_> {
+        match self {
+            Self::Heap(assets) => Box::new(
+                assets
+                    .assets
+                    .iter()
+                    .map(|(key, asset)| (key.clone(), Cow::Borrowed(asset))),
+            ),
+            Self::Stable(assets) => Box::new(
+                assets
+                    .assets
+                    .iter()
+                    .map(|(key, stored)| (key, Cow::Owned(stored.0))),
+            ),
+        }
+    }
+
+    /// Adds a reference to content from outside of the assets. The chunks are only written
+    /// if the content is not stored yet, otherwise the stored chunks are kept as they are.
+    /// Every call must be matched by a `release_content`.
+    pub fn insert_content(&mut self, sha256: [u8; 32], chunks: Vec<RcBytes>) {
+        match self {
+            Self::Heap(assets) => assets.insert_content(sha256, chunks),
+            Self::Stable(assets) => assets.insert_content(sha256, chunks),
+        }
+    }
+
+    /// Adds a reference to content that is stored already, returns false if it is not.
+    pub fn retain_content(&mut self, sha256: &[u8; 32]) -> bool {
+        match self {
+            Self::Heap(assets) => assets.retain_content(sha256),
+            Self::Stable(assets) => assets.retain_content(sha256),
+        }
+    }
+
+    pub fn release_content(&mut self, sha256: &[u8; 32]) {
+        match self {
+            Self::Heap(assets) => assets.release_content(sha256),
+            Self::Stable(assets) => assets.release_content(sha256),
+        }
+    }
+
+    /// Length of each chunk of the content with the given hash, if it is stored.
+    pub fn stored_chunk_lengths(&self, sha256: &[u8; 32]) -> Option<Vec<usize>> {
+        match self {
+            Self::Heap(assets) => assets
+                .contents
+                .get(sha256)
+                .map(|content| content.chunks.iter().map(|chunk| chunk.len()).collect()),
+            Self::Stable(assets) => assets
+                .contents
+                .get(sha256)
+                .map(|content| content.chunk_lengths),
+        }
+    }
+
+    pub fn stored_chunk(&self, sha256: &[u8; 32], index: usize) -> Option<RcBytes> {
+        match self {
+            Self::Heap(assets) => assets
+                .contents
+                .get(sha256)
+                .and_then(|content| content.chunks.get(index).cloned()),
+            Self::Stable(assets) => assets.content_chunk(sha256, index),
+        }
+    }
+
+    /// Total length of all distinct contents, the bytes actually stored.
+    pub fn physical_size(&self) -> usize {
+        match self {
+            Self::Heap(assets) => assets
+                .contents
+                .values()
+                .map(|content| content_length(&content.chunks))
+                .sum(),
+            Self::Stable(assets) => assets
+                .contents
+                .iter()
+                .map(|(_, content)| content.chunk_lengths.iter().sum::<usize>())
+                .sum(),
+        }
+    }
+
+    /// The assets kept on the heap. Assets in stable memory are left out, they are
+    /// persisted already.
+    pub fn into_heap_assets(self) -> HashMap<AssetKey, Asset> {
+        match self {
+            Self::Heap(assets) => assets.assets,
+            Self::Stable(_) => HashMap::new(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct HeapAssets {
+    assets: HashMap<AssetKey, Asset>,
+    contents: HashMap<[u8; 32], SharedContent>,
+}
+
+struct SharedContent {
+    chunks: Vec<RcBytes>,
+    references: usize,
+}
+
+impl HeapAssets {
+    fn share_content(&self, asset: &mut Asset) {
+        for enc in asset.encodings.values_mut() {
+            if let Some(content) = self.contents.get(&enc.sha256) {
+                if content_length(&content.chunks) == enc.total_length {
+                    enc.content_chunks = content.chunks.clone();
+                }
+            }
+        }
+    }
+
+    fn insert(&mut self, key: AssetKey, asset: Asset) {
+        // Referencing the new content first keeps content that stays in use
+        for enc in asset.encodings.values() {
+            self.insert_content(enc.sha256, enc.content_chunks.clone());
+        }
+        if let Some(previous) = self.assets.insert(key, asset) {
+            self.release(&previous);
+        }
+    }
+
+    fn insert_content(&mut self, sha256: [u8; 32], chunks: Vec<RcBytes>) {
+        self.contents
+            .entry(sha256)
+            .or_insert_with(|| SharedContent {
+                chunks,
+                references: 0,
+            })
+            .references += 1;
+    }
+
+    fn retain_content(&mut self, sha256: &[u8; 32]) -> bool {
+        match self.contents.get_mut(sha256) {
+            Some(content) => {
+                content.references += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(asset) = self.assets.remove(key) {
+            self.release(&asset);
+        }
+    }
+
+    fn release(&mut self, asset: &Asset) {
+        for enc in asset.encodings.values() {
+            self.release_content(&enc.sha256);
+        }
+    }
+
+    fn release_content(&mut self, sha256: &[u8; 32]) {
+        if let Some(content) = self.contents.get_mut(sha256) {
+            content.references -= 1;
+            if content.references == 0 {
+                self.contents.remove(sha256);
+            }
+        }
+    }
+}
+
+pub struct StableAssets {
+    assets: StableBTreeMap<AssetKey, StoredAsset, Memory>,
+    contents: StableBTreeMap<[u8; 32], StoredContent, Memory>,
+    chunks: StableBTreeMap<StoredChunkKey, Vec<u8>, Memory>,
+}
+
+impl StableAssets {
+    /// Opens the assets stored in `memories`, if any.
+    pub fn init(memories: AssetMemories) -> Self {
+        Self {
+            assets: StableBTreeMap::init(memories.assets),
+            contents: StableBTreeMap::init(memories.contents),
+            chunks: StableBTreeMap::init(memories.chunks),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<Asset> {
+        let StoredAsset(mut asset) = self.assets.get(&key.to_string())?;
+
+        for enc in asset.encodings.values_mut() {
+            let chunk_count = self
+                .contents
+                .get(&enc.sha256)
+                .map_or(0, |content| content.chunk_lengths.len());
+            enc.content_chunks = (0..chunk_count)
+                .map(|index| {
+                    let content = self
+                        .chunks
+                        .get(&StoredChunkKey::new(enc.sha256, index))
+                        .expect("asset chunk missing from stable memory");
+                    RcBytes::from(ByteBuf::from(content))
+                })
+                .collect();
+        }
+
+        Some(asset)
+    }
+
+    fn get_without_content(&self, key: &str) -> Option<Asset> {
+        self.assets.get(&key.to_string()).map(|stored| stored.0)
+    }
+
+    fn content_chunk(&self, sha256: &[u8; 32], index: usize) -> Option<RcBytes> {
+        self.chunks
+            .get(&StoredChunkKey::new(*sha256, index))
+            .map(|content| RcBytes::from(ByteBuf::from(content)))
+    }
+
+    fn contains_key(&self, key: &str) -> bool {
+        self.assets.contains_key(&key.to_string())
+    }
+
+    // Content that is stored already only needs its chunk boundaries, not its bytes.
+    // Encodings read without their content are left as they are.
+    fn share_content(&self, asset: &mut Asset) {
+        for enc in asset
+            .encodings
+            .values_mut()
+            .filter(|enc| !enc.content_chunks.is_empty())
+        {
+            if let Some(content) = self.contents.get(&enc.sha256) {
+                let lengths: Vec<usize> = enc.content_chunks.iter().map(|c| c.len()).collect();
+                if content.chunk_lengths != lengths
+                    && content.chunk_lengths.iter().sum::<usize>() == enc.total_length
+                {
+                    rechunk(enc, &content.chunk_lengths);
+                }
+            }
+        }
+    }
+
+    // Only content that is not stored yet is written, so that updating the properties
+    // of a large asset stays cheap.
+    fn insert(&mut self, key: AssetKey, mut asset: Asset) {
+        let previous = self.assets.get(&key);
+
+        for enc in asset.encodings.values_mut() {
+            let content_chunks = std::mem::take(&mut enc.content_chunks);
+            self.insert_content(enc.sha256, content_chunks);
+        }
+
+        self.assets.insert(key, StoredAsset(asset));
+        if let Some(StoredAsset(previous)) = previous {
+            self.release(&previous);
+        }
+    }
+
+    fn insert_content(&mut self, sha256: [u8; 32], chunks: Vec<RcBytes>) {
+        let mut content = self.contents.get(&sha256).unwrap_or_else(|| {
+            for (index, chunk) in chunks.iter().enumerate() {
+                self.chunks
+                    .insert(StoredChunkKey::new(sha256, index), chunk.to_vec());
+            }
+            StoredContent {
+                chunk_lengths: chunks.iter().map(|chunk| chunk.len()).collect(),
+                references: 0,
+            }
+        });
+        content.references += 1;
+        self.contents.insert(sha256, content);
+    }
+
+    fn retain_content(&mut self, sha256: &[u8; 32]) -> bool {
+        let Some(mut content) = self.contents.get(sha256) else {
+            return false;
+        };
+        content.references += 1;
+        self.contents.insert(*sha256, content);
+        true
+    }
+
+    // Releasing content only needs the hashes of the encodings
+    fn remove(&mut self, key: &str) {
+        if let Some(StoredAsset(asset)) = self.assets.remove(&key.to_string()) {
+            self.release(&asset);
+        }
+    }
+
+    fn clear(&mut self) {
+        for key in self.keys() {
+            if let Some(StoredAsset(asset)) = self.assets.remove(&key) {
+                self.release(&asset);
+            }
+        }
+    }
+
+    fn keys(&self) -> Vec<AssetKey> {
+        self.assets.iter().map(|(key, _)| key).collect()
+    }
+
+    fn release(&mut self, asset: &Asset) {
+        for enc in asset.encodings.values() {
+            self.release_content(&enc.sha256);
+        }
+    }
+
+    fn release_content(&mut self, sha256: &[u8; 32]) {
+        let Some(mut content) = self.contents.get(sha256) else {
+            return;
+        };
+        content.references -= 1;
+        if content.references > 0 {
+            self.contents.insert(*sha256, content);
+            return;
+        }
+
+        self.contents.remove(sha256);
+        for index in 0..content.chunk_lengths.len() {
+            self.chunks.remove(&StoredChunkKey::new(*sha256, index));
+        }
+    }
+}
+
+fn content_length(chunks: &[RcBytes]) -> usize {
+    chunks.iter().map(|chunk| chunk.len()).sum()
+}
+
+// Splits the content of `enc` at the given chunk boundaries
+fn rechunk(enc: &mut AssetEncoding, chunk_lengths: &[usize]) {
+    let content: Vec<u8> = enc
+        .content_chunks
+        .iter()
+        .flat_map(|chunk| chunk.iter().copied())
+        .collect();
+
+    let mut offset = 0;
+    enc.content_chunks = chunk_lengths
+        .iter()
+        .map(|length| {
+            let chunk = content[offset..offset + length].to_vec();
+            offset += length;
+            RcBytes::from(ByteBuf::from(chunk))
+        })
+        .collect();
+}
+
+/// Encodings are stored without their content chunks
+#[derive(CandidType, Deserialize)]
+struct StoredAsset(Asset);
+
+impl Storable for StoredAsset {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode StoredAsset"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(&bytes, Self).expect("Failed to decode StoredAsset")
+    }
+}
+
+#[derive(CandidType, Deserialize)]
+struct StoredContent {
+    /// Length of each content chunk
+    chunk_lengths: Vec<usize>,
+    /// Number of encodings and outside references with this content
+    references: u64,
+}
+
+impl Storable for StoredContent {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode StoredContent"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(&bytes, Self).expect("Failed to decode StoredContent")
+    }
+}
+
+/// Chunks of the same content are adjacent in the tree.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct StoredChunkKey {
+    sha256: [u8; 32],
+    index: u64,
+}
+
+impl StoredChunkKey {
+    fn new(sha256: [u8; 32], index: usize) -> Self {
+        Self {
+            sha256,
+            index: index as u64,
+        }
+    }
+}
+
+impl Storable for StoredChunkKey {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 40,
+        is_fixed_size: true,
+    };
+
+    fn to_bytes(&self) -> Cow<[u8]> {
+        let mut bytes = self.sha256.to_vec();
+        bytes.extend_from_slice(&self.index.to_be_bytes());
+        Cow::Owned(bytes)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Self {
+            sha256: bytes[..32].try_into().unwrap(),
+            index: u64::from_be_bytes(bytes[32..].try_into().unwrap()),
+        }
+    }
+}
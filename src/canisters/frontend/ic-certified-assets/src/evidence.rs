@@ -4,8 +4,9 @@ use crate::types::BatchOperation::{
     Clear, CreateAsset, DeleteAsset, SetAssetContent, SetAssetProperties, UnsetAssetContent,
 };
 use crate::types::{
-    ChunkId, ClearArguments, CommitBatchArguments, CreateAssetArguments, DeleteAssetArguments,
-    SetAssetContentArguments, SetAssetPropertiesArguments, UnsetAssetContentArguments,
+    AssetRedirect, ChunkId, ClearArguments, CommitBatchArguments, CreateAssetArguments,
+    DeleteAssetArguments, SetAssetContentArguments, SetAssetPropertiesArguments,
+    UnsetAssetContentArguments,
 };
 use itertools::Itertools;
 use serde_bytes::ByteBuf;
@@ -24,6 +25,8 @@ const TAG_UNSET_ASSET_CONTENT: [u8; 1] = [6];
 const TAG_DELETE_ASSET: [u8; 1] = [7];
 const TAG_CLEAR: [u8; 1] = [8];
 const TAG_SET_ASSET_PROPERTIES: [u8; 1] = [9];
+const TAG_REDIRECT: [u8; 1] = [10];
+const TAG_REWRITE_FROM: [u8; 1] = [11];
 
 pub enum EvidenceComputation {
     NextOperation {
@@ -170,6 +173,13 @@ fn hash_create_asset(hasher: &mut Sha256, args: &CreateAssetArguments) {
     hash_headers(hasher, args.headers.as_ref());
     hash_opt_bool(hasher, args.allow_raw_access);
     hash_opt_bool(hasher, args.enable_aliasing);
+    // only hashed if set, so evidence for batches without them is unchanged
+    if let Some(redirect) = args.redirect.as_ref() {
+        hash_redirect(hasher, Some(redirect));
+    }
+    if let Some(rewrite_from) = args.rewrite_from.as_ref() {
+        hash_rewrite_from(hasher, Some(rewrite_from));
+    }
 }
 
 fn hash_set_asset_content(hasher: &mut Sha256, args: &SetAssetContentArguments) {
@@ -226,6 +236,37 @@ fn hash_set_asset_properties(hasher: &mut Sha256, args: &SetAssetPropertiesArgum
     } else {
         hasher.update(TAG_NONE);
     }
+    // only hashed if set, so evidence for batches without them is unchanged
+    if let Some(redirect) = args.redirect.as_ref() {
+        hash_redirect(hasher, redirect.as_ref());
+    }
+    if let Some(rewrite_from) = args.rewrite_from.as_ref() {
+        hash_rewrite_from(hasher, rewrite_from.as_ref());
+    }
+}
+
+fn hash_redirect(hasher: &mut Sha256, redirect: Option<&AssetRedirect>) {
+    hasher.update(TAG_REDIRECT);
+    if let Some(redirect) = redirect {
+        hasher.update(TAG_SOME);
+        hasher.update(&redirect.location);
+        hasher.update(redirect.status_code.to_be_bytes());
+    } else {
+        hasher.update(TAG_NONE);
+    }
+}
+
+fn hash_rewrite_from(hasher: &mut Sha256, rewrite_from: Option<&Vec<String>>) {
+    hasher.update(TAG_REWRITE_FROM);
+    if let Some(rewrite_from) = rewrite_from {
+        hasher.update(TAG_SOME);
+        for prefix in rewrite_from {
+            hasher.update(prefix);
+            hasher.update([0]);
+        }
+    } else {
+        hasher.update(TAG_NONE);
+    }
 }
 
 fn hash_opt_bool(hasher: &mut Sha256, b: Option<bool>) {
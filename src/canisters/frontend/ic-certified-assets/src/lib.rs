@@ -1,6 +1,7 @@
 mod web3disk;
 
 pub mod asset_certification;
+pub mod asset_store;
 pub mod evidence;
 pub mod state_machine;
 pub mod types;
@@ -16,13 +17,18 @@ mod tests;
 //         CallbackFunc, HttpRequest, HttpResponse, StreamingCallbackHttpResponse,
 //         StreamingCallbackToken,
 //     },
+//     asset_store::AssetMemories,
 //     state_machine::{AssetDetails, CertifiedTree, EncodedAsset, State},
 //     types::*,
 // };
 // use asset_certification::types::{certification::AssetKey, rc_bytes::RcBytes};
-// use candid::{candid_method, Principal};
+// use candid::{candid_method, Decode, Encode, Principal};
 // use ic_cdk::api::{call::ManualReply, caller, data_certificate, set_certified_data, time, trap};
 // use ic_cdk::{query, update};
+// use ic_stable_structures::{
+//     memory_manager::{MemoryId, MemoryManager, VirtualMemory},
+//     DefaultMemoryImpl, StableCell,
+// };
 // use serde_bytes::ByteBuf;
 // use std::cell::RefCell;
 
@@ -30,10 +36,31 @@ mod tests;
 // #[link_section = "icp:public supported_certificate_versions"]
 // pub static SUPPORTED_CERTIFICATE_VERSIONS: [u8; 3] = *b"1,2";
 
+// const MEM_ID_ASSETS: MemoryId = MemoryId::new(0);
+// const MEM_ID_ASSET_CONTENTS: MemoryId = MemoryId::new(1);
+// const MEM_ID_ASSET_CHUNKS: MemoryId = MemoryId::new(2);
+// const MEM_ID_UPGRADE_STATE: MemoryId = MemoryId::new(3);
+
 // thread_local! {
+//     static MEMORY_MANAGER: MemoryManager<DefaultMemoryImpl> =
+//         MemoryManager::init(DefaultMemoryImpl::default());
+
+//     // Assets are kept in stable memory, the rest of the state is saved on upgrades
 //     static STATE: RefCell<State> = RefCell::new(State::default());
 // }
 
+// fn memory(id: MemoryId) -> VirtualMemory<DefaultMemoryImpl> {
+//     MEMORY_MANAGER.with(|mm| mm.get(id))
+// }
+
+// fn asset_memories() -> AssetMemories {
+//     AssetMemories {
+//         assets: memory(MEM_ID_ASSETS),
+//         contents: memory(MEM_ID_ASSET_CONTENTS),
+//         chunks: memory(MEM_ID_ASSET_CHUNKS),
+//     }
+// }
+
 // #[query]
 // #[candid_method(query)]
 // fn api_version() -> u16 {
@@ -421,23 +448,31 @@ mod tests;
 //     }
 //     STATE.with(|s| {
 //         let mut s = s.borrow_mut();
+//         *s = State::with_stable_memory(asset_memories());
 //         s.clear();
 //         s.grant_permission(caller(), &Permission::Commit);
 //     });
 // }
 
-// pub fn pre_upgrade() -> StableState {
-//     STATE.with(|s| s.take().into())
+// // Only the heap part of the state is saved, the assets are in stable memory already
+// pub fn pre_upgrade() {
+//     let stable_state: StableState = STATE.with(|s| s.take().into());
+//     let bytes = Encode!(&stable_state).expect("failed to encode stable state");
+//     StableCell::init(memory(MEM_ID_UPGRADE_STATE), vec![])
+//         .expect("failed to open upgrade state memory")
+//         .set(bytes)
+//         .expect("failed to save stable state");
 // }
 
-// pub fn post_upgrade(stable_state: StableState, args: Option<AssetCanisterArgs>) {
+// pub fn post_upgrade(args: Option<AssetCanisterArgs>) {
 //     let set_permissions = args.and_then(|args| {
 //         let AssetCanisterArgs::Upgrade(UpgradeArgs { set_permissions }) = args else {ic_cdk::trap("Cannot upgrade the canister with an Init argument. Please provide an Upgrade argument.")};
 //         set_permissions
 //     });
 
+//     let stable_state = restore_stable_state();
 //     STATE.with(|s| {
-//         *s.borrow_mut() = State::from(stable_state);
+//         *s.borrow_mut() = State::from_stable_state_with_memory(stable_state, asset_memories());
 //         set_certified_data(&s.borrow().root_hash());
 //         if let Some(set_permissions) = set_permissions {
 //             s.borrow_mut().set_permissions(set_permissions);
@@ -445,6 +480,23 @@ mod tests;
 //     });
 // }
 
+// // Versions keeping all assets on the heap saved the whole state with `stable_save` in place
+// // of the memory manager. It has to be read before the memory manager takes over the stable
+// // memory, `post_upgrade` then moves its assets into stable memory.
+// fn restore_stable_state() -> StableState {
+//     let mut magic = [0; 3];
+//     ic_cdk::api::stable::stable64_read(0, &mut magic);
+//     if &magic != b"MGR" {
+//         let (stable_state,): (StableState,) =
+//             ic_cdk::storage::stable_restore().expect("failed to restore stable state");
+//         return stable_state;
+//     }
+
+//     let cell = StableCell::init(memory(MEM_ID_UPGRADE_STATE), vec![])
+//         .expect("failed to open upgrade state memory");
+//     Decode!(cell.get(), StableState).expect("failed to decode stable state")
+// }
+
 // #[test]
 // fn candid_interface_compatibility() {
 //     use candid::utils::{service_compatible, CandidSource};
@@ -11,14 +11,17 @@ use crate::{
                 RequestHash, ResponseHash, WitnessResult,
             },
             http::{
+                build_ic_certificate_expression_from_headers,
                 build_ic_certificate_expression_from_headers_and_encoding,
-                build_ic_certificate_expression_header, response_hash, CallbackFunc, HttpRequest,
-                HttpResponse, StreamingCallbackHttpResponse, StreamingCallbackToken, FALLBACK_FILE,
+                build_ic_certificate_expression_header, response_hash, CallbackFunc, HeaderField,
+                HttpRequest, HttpResponse, StreamingCallbackHttpResponse, StreamingCallbackToken,
+                FALLBACK_FILE,
             },
             rc_bytes::RcBytes,
         },
         CertifiedResponses,
     },
+    asset_store::{AssetMemories, AssetStore, StableAssets},
     evidence::{EvidenceComputation, EvidenceComputation::Computed},
     types::*,
     url_decode::url_decode,
@@ -30,8 +33,8 @@ use num_traits::ToPrimitive;
 use serde::Serialize;
 use serde_bytes::ByteBuf;
 use sha2::Digest;
-use std::collections::{BTreeSet, HashMap};
-use std::convert::TryInto;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 /// The amount of time a batch is kept alive. Modifying the batch
 /// delays the expiry further.
@@ -63,6 +66,9 @@ const DEFAULT_ALIAS_ENABLED: bool = true;
 
 const STATUS_CODES_TO_CERTIFY: [u16; 2] = [200, 304];
 
+/// Range requests are only answered for this encoding, other encodings are always served whole.
+const RANGE_ENCODING: &str = "identity";
+
 const DEFAULT_MAX_COMPUTE_EVIDENCE_ITERATIONS: u16 = 20;
 
 type Timestamp = Int;
@@ -78,6 +84,18 @@ pub struct AssetEncoding {
     pub sha256: [u8; 32],
     pub certificate_expression: Option<CertificateExpression>,
     pub response_hashes: Option<HashMap<u16, [u8; 32]>>,
+    /// Certifies `206 Partial Content` and `416 Range Not Satisfiable` responses.
+    /// Only set for multi-chunk identity encodings.
+    pub range_certificate_expression: Option<CertificateExpression>,
+    /// One `206` response hash per chunk: a certified range always covers exactly one chunk.
+    pub partial_response_hashes: Option<Vec<[u8; 32]>>,
+    pub range_not_satisfiable_hash: Option<[u8; 32]>,
+    /// Length of each content chunk, so that responses can be built from single chunks
+    /// while `content_chunks` is not loaded from stable memory.
+    pub chunk_lengths: Option<Vec<usize>>,
+    /// SHA-256 of each content chunk, kept along with `partial_response_hashes` so that they
+    /// can be computed again without the content.
+    pub chunk_hashes: Option<Vec<[u8; 32]>>,
 }
 
 impl AssetEncoding {
@@ -108,6 +126,10 @@ impl AssetEncoding {
         })
     }
 
+    fn rewrite_hash_path(&self, prefix: &str) -> Option<HashTreePath> {
+        self.asset_hash_path_v2(&AssetPath::rewrite_path(prefix), 200)
+    }
+
     fn compute_response_hashes(
         &self,
         headers: &Option<HashMap<String, String>>,
@@ -144,6 +166,111 @@ impl AssetEncoding {
 
         response_hashes
     }
+
+    fn compute_range_response_hashes(
+        &mut self,
+        headers: &Option<HashMap<String, String>>,
+        max_age: &Option<u64>,
+        content_type: &str,
+    ) {
+        let base_headers = build_headers(
+            headers.as_ref().map(|h| h.iter()),
+            max_age,
+            content_type,
+            RANGE_ENCODING,
+            self.range_certificate_expression.as_ref(),
+        );
+        let range_headers = |content_range: String| -> Vec<(String, Value)> {
+            let mut headers = base_headers.clone();
+            headers.insert("content-range".to_string(), content_range);
+            headers
+                .into_iter()
+                .map(|(k, v)| (k, Value::String(v)))
+                .collect()
+        };
+
+        if self.has_content() {
+            self.chunk_hashes = Some(
+                self.content_chunks
+                    .iter()
+                    .map(|chunk| sha2::Sha256::digest(chunk).into())
+                    .collect(),
+            );
+        }
+        let chunk_hashes = self.chunk_hashes.as_deref().unwrap_or_default();
+
+        let mut partial_response_hashes = Vec::with_capacity(chunk_hashes.len());
+        for (index, body_hash) in chunk_hashes.iter().enumerate() {
+            let ResponseHash(hash) =
+                response_hash(&range_headers(self.content_range(index)), 206, body_hash);
+            partial_response_hashes.push(hash);
+        }
+
+        let empty_body_hash: [u8; 32] = sha2::Sha256::digest([]).into();
+        let ResponseHash(range_not_satisfiable_hash) = response_hash(
+            &range_headers(format!("bytes */{}", self.total_length)),
+            416,
+            &empty_body_hash,
+        );
+
+        self.partial_response_hashes = Some(partial_response_hashes);
+        self.range_not_satisfiable_hash = Some(range_not_satisfiable_hash);
+    }
+
+    fn range_hash_paths(&self, path: &AssetPath) -> Vec<HashTreePath> {
+        let (Some(ce), Some(partial), Some(not_satisfiable)) = (
+            self.range_certificate_expression.as_ref(),
+            self.partial_response_hashes.as_ref(),
+            self.range_not_satisfiable_hash.as_ref(),
+        ) else {
+            return vec![];
+        };
+
+        partial
+            .iter()
+            .chain(std::iter::once(not_satisfiable))
+            .map(|response_hash| {
+                path.hash_tree_path(ce, &RequestHash::default(), response_hash.into())
+            })
+            .collect()
+    }
+
+    // Assets read without their content keep the chunk lengths only
+    fn has_content(&self) -> bool {
+        !self.content_chunks.is_empty()
+    }
+
+    fn lengths(&self) -> Cow<'_, [usize]> {
+        if self.has_content() {
+            Cow::Owned(self.content_chunks.iter().map(|c| c.len()).collect())
+        } else {
+            Cow::Borrowed(self.chunk_lengths.as_deref().unwrap_or_default())
+        }
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.lengths().len()
+    }
+
+    /// `Content-Range` header value of the response for a single chunk.
+    pub fn content_range(&self, index: usize) -> String {
+        let lengths = self.lengths();
+        let start: usize = lengths[..index].iter().sum();
+        let end = start + lengths[index] - 1;
+        format!("bytes {}-{}/{}", start, end, self.total_length)
+    }
+
+    /// Index of the chunk holding the byte at `offset`, if any.
+    fn chunk_containing(&self, offset: usize) -> Option<usize> {
+        let mut chunk_end = 0;
+        for (index, length) in self.lengths().iter().enumerate() {
+            chunk_end += length;
+            if offset < chunk_end {
+                return Some(index);
+            }
+        }
+        None
+    }
 }
 
 #[derive(Default, Clone, Debug, CandidType, Deserialize)]
@@ -154,6 +281,11 @@ pub struct Asset {
     pub headers: Option<HashMap<String, String>>,
     pub is_aliased: Option<bool>,
     pub allow_raw_access: Option<bool>,
+    /// Served instead of the content if set. Only certified with certification v2.
+    pub redirect: Option<AssetRedirect>,
+    /// Path prefixes, e.g. `/app`, whose requests are answered with this asset unless they
+    /// match another asset. Only certified with certification v2.
+    pub rewrite_from: Option<Vec<String>>,
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize)]
@@ -180,6 +312,16 @@ pub struct AssetEncodingDetails {
     pub modified: Timestamp,
 }
 
+/// Encodings with identical content are stored once, so `physical_bytes` can be
+/// smaller than `logical_bytes`.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct StorageUsage {
+    /// Sum of the lengths of all encodings
+    pub logical_bytes: Nat,
+    /// Bytes of content actually stored
+    pub physical_bytes: Nat,
+}
+
 #[derive(Clone, Debug, CandidType, Deserialize)]
 pub struct CertifiedTree {
     pub certificate: Vec<u8>,
@@ -207,7 +349,7 @@ pub struct Configuration {
 
 #[derive(Default)]
 pub struct State {
-    assets: HashMap<AssetKey, Asset>,
+    assets: AssetStore,
     configuration: Configuration,
 
     chunks: HashMap<ChunkId, Chunk>,
@@ -216,6 +358,12 @@ pub struct State {
     batches: HashMap<BatchId, Batch>,
     next_batch_id: BatchId,
 
+    // generated pages for folder keys without an asset, not part of the stable state
+    directory_listings: HashMap<AssetKey, DirectoryListing>,
+
+    // rewrite prefixes and the asset serving each, rebuilt from the assets on restore
+    rewrite_sources: BTreeMap<String, AssetKey>,
+
     // permissions
     commit_principals: BTreeSet<Principal>,
     prepare_principals: BTreeSet<Principal>,
@@ -231,6 +379,50 @@ pub struct StableStatePermissions {
     manage_permissions: BTreeSet<Principal>,
 }
 
+/// A generated index of a folder key (ending in `/`) that has no asset of its own.
+/// Both representations are certified for the folder path, `Accept` picks the one served.
+#[derive(Clone, Debug)]
+struct DirectoryListing {
+    html: CertifiedPage,
+    json: CertifiedPage,
+}
+
+#[derive(Clone, Debug)]
+struct CertifiedPage {
+    headers: Vec<HeaderField>,
+    body: RcBytes,
+}
+
+impl CertifiedPage {
+    fn new(path: &AssetPath, content_type: &str, body: Vec<u8>) -> (Self, HashTreePath) {
+        let body_hash: [u8; 32] = sha2::Sha256::digest(&body).into();
+        let (headers, hash_path) = certified_response(
+            path,
+            200,
+            vec![("content-type".to_string(), content_type.to_string())],
+            &body_hash,
+        );
+        let page = Self {
+            headers,
+            body: RcBytes::from(ByteBuf::from(body)),
+        };
+        (page, hash_path)
+    }
+
+    fn to_response(&self, certificate_header: &HeaderField) -> HttpResponse {
+        let mut headers = self.headers.clone();
+        headers.push(certificate_header.clone());
+
+        HttpResponse {
+            status_code: 200,
+            headers,
+            body: self.body.clone(),
+            upgrade: None,
+            streaming_strategy: None,
+        }
+    }
+}
+
 #[derive(Clone, Debug, CandidType, Deserialize)]
 pub struct StableState {
     authorized: Vec<Principal>, // ignored if permissions is Some(_)
@@ -264,6 +456,20 @@ impl Asset {
             encoding.certificate_expression = Some(
                 build_ic_certificate_expression_from_headers_and_encoding(&headers, Some(enc_name)),
             );
+
+            // Partial responses additionally certify which bytes they contain
+            encoding.range_certificate_expression = if enc_name == RANGE_ENCODING
+                && encoding.chunk_count() > 1
+            {
+                let mut range_headers = headers.clone();
+                range_headers.push(("content-range".to_string(), Value::String("".to_string())));
+                Some(build_ic_certificate_expression_from_headers_and_encoding(
+                    &range_headers,
+                    Some(enc_name),
+                ))
+            } else {
+                None
+            };
         }
     }
 
@@ -288,6 +494,100 @@ impl Asset {
         )
     }
 
+    fn get_range_headers(&self, content_range: String) -> HashMap<String, String> {
+        let ce = self
+            .encodings
+            .get(RANGE_ENCODING)
+            .and_then(|e| e.range_certificate_expression.as_ref());
+        let mut headers = build_headers(
+            self.headers.as_ref().map(|h| h.iter()),
+            &self.max_age,
+            &self.content_type,
+            RANGE_ENCODING,
+            ce,
+        );
+        headers.insert("content-range".to_string(), content_range);
+        headers
+    }
+
+    /// Answers a `Range` request with a certified partial response.
+    /// Only one response per chunk can be certified, so the response covers the whole chunk
+    /// holding the first requested byte, as announced in `Content-Range`. For multi-part and
+    /// malformed ranges `None` is returned and the whole asset should be served instead,
+    /// which is always a valid answer.
+    pub fn build_range_response(
+        &self,
+        range: &str,
+        certificate_header: &HeaderField,
+        content_chunk: &dyn Fn(&AssetEncoding, usize) -> RcBytes,
+    ) -> Option<HttpResponse> {
+        let enc = self.encodings.get(RANGE_ENCODING)?;
+        enc.partial_response_hashes.as_ref()?;
+        if !enc.certified {
+            return None;
+        }
+
+        let (status_code, content_range, body) = match parse_range(range, enc.total_length)? {
+            ByteRange::Satisfiable(start) => {
+                let index = enc.chunk_containing(start)?;
+                (206, enc.content_range(index), content_chunk(enc, index))
+            }
+            ByteRange::NotSatisfiable => (
+                416,
+                format!("bytes */{}", enc.total_length),
+                RcBytes::default(),
+            ),
+        };
+
+        let mut headers = self.get_range_headers(content_range);
+        headers.insert(certificate_header.0.clone(), certificate_header.1.clone());
+
+        Some(HttpResponse {
+            status_code,
+            headers: headers.into_iter().collect(),
+            body,
+            upgrade: None,
+            streaming_strategy: None,
+        })
+    }
+
+    fn get_redirect_headers(&self) -> Option<Vec<HeaderField>> {
+        let redirect = self.redirect.as_ref()?;
+        let location = ("location".to_string(), redirect.location.clone());
+        let ce = build_ic_certificate_expression_from_headers(&[location.clone()]);
+        Some(vec![location, build_ic_certificate_expression_header(&ce)])
+    }
+
+    fn redirect_hash_path(&self, path: &AssetPath) -> Option<HashTreePath> {
+        let redirect = self.redirect.as_ref()?;
+        let location = ("location".to_string(), redirect.location.clone());
+        let empty_body_hash: [u8; 32] = sha2::Sha256::digest([]).into();
+        let (_, hash_path) =
+            certified_response(path, redirect.status_code, vec![location], &empty_body_hash);
+        Some(hash_path)
+    }
+
+    pub fn build_redirect_response(
+        &self,
+        certificate_header: &HeaderField,
+    ) -> Option<HttpResponse> {
+        let redirect = self.redirect.as_ref()?;
+        let mut headers = self.get_redirect_headers()?;
+        headers.push(certificate_header.clone());
+
+        Some(HttpResponse {
+            status_code: redirect.status_code,
+            headers,
+            body: RcBytes::default(),
+            upgrade: None,
+            streaming_strategy: None,
+        })
+    }
+
+    fn serves_rewrites(&self) -> bool {
+        self.redirect.is_none() && self.encodings.values().any(|enc| enc.certified)
+    }
+
     // certification v1 only certifies the most important encoding
     pub fn most_important_encoding_v1(&self) -> String {
         for enc in encoding_certification_order(self.encodings.keys()).into_iter() {
@@ -300,26 +600,65 @@ impl Asset {
 }
 
 impl State {
-    fn get_asset(&self, key: &AssetKey) -> Result<&Asset, String> {
-        self.assets
-            .get(key)
-            .or_else(|| {
-                let aliased = aliases_of(key)
-                    .into_iter()
-                    .find_map(|alias_key| self.assets.get(&alias_key));
-                if let Some(asset) = aliased {
-                    if asset.is_aliased.unwrap_or(DEFAULT_ALIAS_ENABLED) {
-                        aliased
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            })
+    /// A state whose assets are kept in stable memory, so they survive upgrades without
+    /// being serialized. Assets stored by an earlier instance are certified again from their
+    /// stored response hashes, their content is not read.
+    pub fn with_stable_memory(memories: AssetMemories) -> Self {
+        let mut state = Self {
+            assets: AssetStore::Stable(StableAssets::init(memories)),
+            ..Self::default()
+        };
+        state.recertify_stored_assets();
+        state
+    }
+
+    /// Restores a state saved as `StableState`, with its assets kept in stable memory.
+    /// Assets found in `stable_state`, as saved by versions that kept all assets on the heap,
+    /// are moved into stable memory.
+    pub fn from_stable_state_with_memory(
+        stable_state: StableState,
+        memories: AssetMemories,
+    ) -> Self {
+        Self::from_stable_state(
+            stable_state,
+            AssetStore::Stable(StableAssets::init(memories)),
+        )
+    }
+
+    // Content is read one chunk at a time with `content_chunk`
+    fn get_asset(&self, key: &AssetKey) -> Result<Cow<'_, Asset>, String> {
+        self.resolve_asset_key(key)
+            .and_then(|key| self.assets.get_without_content(&key))
             .ok_or_else(|| "asset not found".to_string())
     }
 
+    fn content_chunk(&self, enc: &AssetEncoding, index: usize) -> Result<RcBytes, String> {
+        self.assets
+            .content_chunk(enc, index)
+            .ok_or_else(|| "chunk index out of bounds".to_string())
+    }
+
+    // Updates only change which content the encodings point to, so content is not read
+    fn get_asset_for_update(&self, key: &str) -> Option<Asset> {
+        self.assets.get_without_content(key).map(Cow::into_owned)
+    }
+
+    // Key of the asset served for `key`, either `key` itself or the asset it is an alias of
+    fn resolve_asset_key(&self, key: &AssetKey) -> Option<AssetKey> {
+        if self.assets.contains_key(key) {
+            return Some(key.clone());
+        }
+        let alias_key = aliases_of(key)
+            .into_iter()
+            .find(|alias_key| self.assets.contains_key(alias_key))?;
+        let asset = self.assets.get_without_content(&alias_key)?;
+        if asset.is_aliased.unwrap_or(DEFAULT_ALIAS_ENABLED) {
+            Some(alias_key)
+        } else {
+            None
+        }
+    }
+
     pub fn set_permissions(
         &mut self,
         SetPermissions {
@@ -363,17 +702,29 @@ impl State {
         if self.assets.contains_key(&arg.key) {
             return Err("asset already exists".to_string());
         }
-        self.assets.insert(
-            arg.key,
-            Asset {
-                content_type: arg.content_type,
-                encodings: HashMap::new(),
-                max_age: arg.max_age,
-                headers: arg.headers,
-                is_aliased: arg.enable_aliasing,
-                allow_raw_access: arg.allow_raw_access,
-            },
-        );
+        if let Some(redirect) = &arg.redirect {
+            validate_redirect(redirect)?;
+        }
+        if let Some(rewrite_from) = &arg.rewrite_from {
+            self.validate_rewrite_from(&arg.key, rewrite_from)?;
+        }
+        let mut asset = Asset {
+            content_type: arg.content_type,
+            encodings: HashMap::new(),
+            max_age: arg.max_age,
+            headers: arg.headers,
+            is_aliased: arg.enable_aliasing,
+            allow_raw_access: arg.allow_raw_access,
+            redirect: arg.redirect,
+            rewrite_from: arg.rewrite_from,
+        };
+        self.index_rewrite_sources(&arg.key, &asset);
+        // a redirect is served without any content being set
+        if asset.redirect.is_some() {
+            let dependent_keys = self.aliased_keys(&arg.key, asset.is_aliased);
+            on_asset_change(&mut self.asset_hashes, &arg.key, &mut asset, dependent_keys);
+        }
+        self.assets.insert(arg.key, asset);
         Ok(())
     }
 
@@ -387,32 +738,32 @@ impl State {
         }
 
         let dependent_keys = self.dependent_keys(&arg.key);
-        let asset = self
-            .assets
-            .get_mut(&arg.key)
+        let mut asset = self
+            .get_asset_for_update(&arg.key)
             .ok_or_else(|| "asset not found".to_string())?;
 
         let now = Int::from(now);
 
         let mut content_chunks = vec![];
         for chunk_id in arg.chunk_ids.iter() {
-            let chunk = self.chunks.remove(chunk_id).expect("chunk not found");
+            let chunk = self
+                .chunks
+                .remove(chunk_id)
+                .ok_or_else(|| "chunk not found".to_string())?;
             content_chunks.push(chunk.content);
         }
 
-        let sha256: [u8; 32] = match arg.sha256 {
-            Some(bytes) => bytes
-                .into_vec()
-                .try_into()
-                .map_err(|_| "invalid SHA-256".to_string())?,
-            None => {
-                let mut hasher = sha2::Sha256::new();
-                for chunk in content_chunks.iter() {
-                    hasher.update(chunk);
-                }
-                hasher.finalize().into()
+        // Content is shared between encodings by hash, so a provided hash must be right
+        let mut hasher = sha2::Sha256::new();
+        for chunk in content_chunks.iter() {
+            hasher.update(chunk);
+        }
+        let sha256: [u8; 32] = hasher.finalize().into();
+        if let Some(provided_hash) = &arg.sha256 {
+            if provided_hash.as_ref() != sha256 {
+                return Err("sha256 mismatch".to_string());
             }
-        };
+        }
 
         let total_length: usize = content_chunks.iter().map(|c| c.len()).sum();
         let enc = AssetEncoding {
@@ -421,25 +772,29 @@ impl State {
             certified: false,
             total_length,
             sha256,
-            certificate_expression: None, // set by on_asset_change
-            response_hashes: None,        // set by on_asset_change
+            certificate_expression: None,       // set by on_asset_change
+            response_hashes: None,              // set by on_asset_change
+            range_certificate_expression: None, // set by on_asset_change
+            partial_response_hashes: None,      // set by on_asset_change
+            range_not_satisfiable_hash: None,   // set by on_asset_change
+            chunk_lengths: None,                // set by on_asset_change
+            chunk_hashes: None,                 // set by on_asset_change
         };
         asset.encodings.insert(arg.content_encoding, enc);
 
-        on_asset_change(&mut self.asset_hashes, &arg.key, asset, dependent_keys);
+        self.update_asset(&arg.key, asset, dependent_keys);
 
         Ok(())
     }
 
     pub fn unset_asset_content(&mut self, arg: UnsetAssetContentArguments) -> Result<(), String> {
         let dependent_keys = self.dependent_keys(&arg.key);
-        let asset = self
-            .assets
-            .get_mut(&arg.key)
+        let mut asset = self
+            .get_asset_for_update(&arg.key)
             .ok_or_else(|| "asset not found".to_string())?;
 
         if asset.encodings.remove(&arg.content_encoding).is_some() {
-            on_asset_change(&mut self.asset_hashes, &arg.key, asset, dependent_keys);
+            self.update_asset(&arg.key, asset, dependent_keys);
         }
 
         Ok(())
@@ -455,15 +810,19 @@ impl State {
                     self.asset_hashes.remove_fallback_responses_v1();
                 }
             }
-            self.assets.remove(&arg.key);
+            if let Some(asset) = self.get_asset_for_update(&arg.key) {
+                self.assets.remove(&arg.key);
+                for prefix in asset.rewrite_from.iter().flatten() {
+                    self.asset_hashes.remove_rewrite_responses(prefix);
+                    self.rewrite_sources.remove(prefix);
+                }
+            }
         }
         for key in aliases_of(&arg.key) {
             // if an existing file can be aliased to the deleted file it has to become a valid alias again
-            if self.assets.contains_key(&key) {
+            if let Some(asset) = self.assets.get_without_content(&key).map(Cow::into_owned) {
                 let dependent_keys = self.dependent_keys(&key);
-                if let Some(asset) = self.assets.get_mut(&key) {
-                    on_asset_change(&mut self.asset_hashes, &key, asset, dependent_keys);
-                }
+                self.update_asset(&key, asset, dependent_keys);
             }
         }
     }
@@ -472,6 +831,8 @@ impl State {
         self.assets.clear();
         self.batches.clear();
         self.chunks.clear();
+        self.directory_listings.clear();
+        self.rewrite_sources.clear();
         self.next_batch_id = Nat::from(1_u8);
         self.next_chunk_id = Nat::from(1_u8);
     }
@@ -511,16 +872,16 @@ impl State {
             .get("identity")
             .ok_or_else(|| "no identity encoding".to_string())?;
 
-        if id_enc.content_chunks.len() > 1 {
+        if id_enc.chunk_count() > 1 {
             return Err("Asset too large. Use get() and get_chunk() instead.".to_string());
         }
 
-        Ok(id_enc.content_chunks[0].clone())
+        self.content_chunk(id_enc, 0)
     }
 
     pub fn store(&mut self, arg: StoreArg, time: u64) -> Result<(), String> {
         let dependent_keys = self.dependent_keys(&arg.key);
-        let asset = self.assets.entry(arg.key.clone()).or_default();
+        let mut asset = self.get_asset_for_update(&arg.key).unwrap_or_default();
         asset.content_type = arg.content_type;
         asset.is_aliased = arg.aliased;
 
@@ -537,7 +898,7 @@ impl State {
         encoding.modified = Int::from(time);
         encoding.sha256 = hash;
 
-        on_asset_change(&mut self.asset_hashes, &arg.key, asset, dependent_keys);
+        self.update_asset(&arg.key, asset, dependent_keys);
         Ok(())
     }
 
@@ -621,6 +982,13 @@ impl State {
         Ok(chunk_id)
     }
 
+    pub fn chunk_content(&self, chunk_id: &ChunkId) -> Result<RcBytes, String> {
+        self.chunks
+            .get(chunk_id)
+            .map(|chunk| chunk.content.clone())
+            .ok_or_else(|| "chunk not found".to_string())
+    }
+
     pub fn commit_batch(&mut self, arg: CommitBatchArguments, now: u64) -> Result<(), String> {
         let batch_id = arg.batch_id;
         for op in arg.operations {
@@ -740,7 +1108,7 @@ impl State {
 
     pub fn list_assets(&self) -> Vec<AssetDetails> {
         self.assets
-            .iter()
+            .iter_without_content()
             .map(|(key, asset)| {
                 let mut encodings: Vec<_> = asset
                     .encodings
@@ -755,7 +1123,7 @@ impl State {
                 encodings.sort_by(|l, r| l.content_encoding.cmp(&r.content_encoding));
 
                 AssetDetails {
-                    key: key.clone(),
+                    key,
                     content_type: asset.content_type.clone(),
                     encodings,
                 }
@@ -763,6 +1131,47 @@ impl State {
             .collect::<Vec<_>>()
     }
 
+    pub fn storage_usage(&self) -> StorageUsage {
+        let logical_bytes: usize = self
+            .assets
+            .iter_without_content()
+            .map(|(_, asset)| {
+                asset
+                    .encodings
+                    .values()
+                    .map(|enc| enc.total_length)
+                    .sum::<usize>()
+            })
+            .sum();
+
+        StorageUsage {
+            logical_bytes: Nat::from(logical_bytes),
+            physical_bytes: Nat::from(self.assets.physical_size()),
+        }
+    }
+
+    /// Adds a reference to content kept alongside the assets, stored once with identical
+    /// asset content. See `AssetStore::insert_content`.
+    pub fn insert_content(&mut self, sha256: [u8; 32], chunks: Vec<RcBytes>) {
+        self.assets.insert_content(sha256, chunks);
+    }
+
+    pub fn retain_content(&mut self, sha256: &[u8; 32]) -> bool {
+        self.assets.retain_content(sha256)
+    }
+
+    pub fn release_content(&mut self, sha256: &[u8; 32]) {
+        self.assets.release_content(sha256);
+    }
+
+    pub fn stored_chunk_lengths(&self, sha256: &[u8; 32]) -> Option<Vec<usize>> {
+        self.assets.stored_chunk_lengths(sha256)
+    }
+
+    pub fn stored_chunk(&self, sha256: &[u8; 32], index: usize) -> Option<RcBytes> {
+        self.assets.stored_chunk(sha256, index)
+    }
+
     pub fn certified_tree(&self, certificate: &[u8]) -> CertifiedTree {
         let mut serializer = serde_cbor::ser::Serializer::new(vec![]);
         serializer.self_describe().unwrap();
@@ -783,7 +1192,7 @@ impl State {
         for enc in arg.accept_encodings.iter() {
             if let Some(asset_enc) = asset.encodings.get(enc) {
                 return Ok(EncodedAsset {
-                    content: asset_enc.content_chunks[0].clone(),
+                    content: self.content_chunk(asset_enc, 0)?,
                     content_type: asset.content_type.clone(),
                     content_encoding: enc.clone(),
                     total_length: Nat::from(asset_enc.total_length as u64),
@@ -807,12 +1216,12 @@ impl State {
             return Err("sha256 mismatch".to_string());
         }
 
-        if arg.index >= enc.content_chunks.len() {
+        if arg.index >= enc.chunk_count() {
             return Err("chunk index out of bounds".to_string());
         }
         let index: usize = arg.index.0.to_usize().unwrap();
 
-        Ok(enc.content_chunks[index].clone())
+        self.content_chunk(enc, index)
     }
 
     fn build_http_response(
@@ -829,12 +1238,20 @@ impl State {
             if !asset.allow_raw_access() && req.is_raw_domain() {
                 return req.redirect_from_raw_to_certified_domain();
             }
-        } else if let Ok(asset) = self.get_asset(&FALLBACK_FILE.to_string()) {
+        } else if let Ok(asset) =
+            self.get_asset(&self.fallback_key(path, req.get_certificate_version()))
+        {
             if !asset.allow_raw_access() && req.is_raw_domain() {
                 return req.redirect_from_raw_to_certified_domain();
             }
         }
 
+        let content_chunk = |enc: &AssetEncoding, index: usize| {
+            self.assets
+                .content_chunk(enc, index)
+                .expect("asset chunk missing")
+        };
+
         let (certificate_header, witness_result) = if req.get_certificate_version() == 1 {
             self.asset_hashes.witness_to_header_v1(path, certificate)
         } else {
@@ -842,9 +1259,11 @@ impl State {
         };
 
         if witness_result == WitnessResult::FallbackFound {
-            if let Ok(asset) = self.get_asset(&FALLBACK_FILE.to_string()) {
+            if let Ok(asset) =
+                self.get_asset(&self.fallback_key(path, req.get_certificate_version()))
+            {
                 if let Some(response) = HttpResponse::build_ok_from_requested_encodings(
-                    asset,
+                    &asset,
                     &requested_encodings,
                     path,
                     chunk_index,
@@ -852,6 +1271,7 @@ impl State {
                     &callback,
                     &etags,
                     req.get_certificate_version(),
+                    &content_chunk,
                 ) {
                     return response;
                 }
@@ -861,8 +1281,19 @@ impl State {
                 if !asset.allow_raw_access() && req.is_raw_domain() {
                     return req.redirect_from_raw_to_certified_domain();
                 }
+                // Redirects and partial responses can only be certified with certification v2
+                if req.get_certificate_version() != 1 {
+                    if let Some(response) = asset.build_redirect_response(&certificate_header) {
+                        return response;
+                    }
+                    if let Some(response) = req.get_header_value("Range").and_then(|range| {
+                        asset.build_range_response(range, &certificate_header, &content_chunk)
+                    }) {
+                        return response;
+                    }
+                }
                 if let Some(response) = HttpResponse::build_ok_from_requested_encodings(
-                    asset,
+                    &asset,
                     &requested_encodings,
                     path,
                     chunk_index,
@@ -870,14 +1301,103 @@ impl State {
                     &callback,
                     &etags,
                     req.get_certificate_version(),
+                    &content_chunk,
                 ) {
                     return response;
                 }
+            } else if let Some(listing) = self
+                .directory_listings
+                .get(path)
+                .filter(|_| req.get_certificate_version() != 1)
+            {
+                let wants_json = req
+                    .get_header_value("Accept")
+                    .map_or(false, |accept| accept.contains("application/json"));
+                return if wants_json {
+                    listing.json.to_response(&certificate_header)
+                } else {
+                    listing.html.to_response(&certificate_header)
+                };
             }
         }
         HttpResponse::build_404(certificate_header, req.get_certificate_version())
     }
 
+    /// Certifies a generated listing for a folder key that has no asset of its own.
+    /// Replaces an earlier listing for the same key.
+    pub fn certify_directory_listing(
+        &mut self,
+        key: &AssetKey,
+        html: Vec<u8>,
+        json: Vec<u8>,
+    ) -> Result<(), String> {
+        if !key.ends_with('/') {
+            return Err(format!("not a folder key: {}", key));
+        }
+        if self.resolve_asset_key(key).is_some() {
+            return Err(format!("an asset is served at {}", key));
+        }
+
+        self.asset_hashes.remove_responses_for_path(key);
+
+        let path = AssetPath::from(key);
+        let (html, html_hash_path) = CertifiedPage::new(&path, "text/html; charset=utf-8", html);
+        let (json, json_hash_path) = CertifiedPage::new(&path, "application/json", json);
+        self.asset_hashes
+            .certify_response_precomputed(&html_hash_path);
+        self.asset_hashes
+            .certify_response_precomputed(&json_hash_path);
+
+        self.directory_listings
+            .insert(key.clone(), DirectoryListing { html, json });
+        Ok(())
+    }
+
+    pub fn remove_directory_listing(&mut self, key: &AssetKey) {
+        // an asset aliased to the folder key may have taken over its certification
+        if self.directory_listings.remove(key).is_some() && self.resolve_asset_key(key).is_none() {
+            self.asset_hashes.remove_responses_for_path(key);
+        }
+    }
+
+    /// Removes the listings of `key` and of all folder keys below it.
+    pub fn remove_directory_listings(&mut self, key: &AssetKey) {
+        let keys: Vec<AssetKey> = self
+            .directory_listings
+            .keys()
+            .filter(|listing_key| listing_key.starts_with(key.as_str()))
+            .cloned()
+            .collect();
+
+        for listing_key in keys {
+            self.remove_directory_listing(&listing_key);
+        }
+    }
+
+    // Requests without a certified response of their own are answered by the asset with the
+    // longest matching rewrite prefix, or else by the fallback file.
+    fn fallback_key(&self, path: &str, cert_version: u16) -> AssetKey {
+        if cert_version != 1 {
+            // Prefixes are looked up longest first: the path itself, then each parent path
+            let mut prefix = path;
+            loop {
+                let rewrite = self.rewrite_sources.get(prefix).filter(|key| {
+                    self.assets
+                        .get_without_content(key)
+                        .map_or(false, |asset| asset.serves_rewrites())
+                });
+                if let Some(key) = rewrite {
+                    return key.clone();
+                }
+                match prefix.rfind('/') {
+                    Some(i) if i > 0 => prefix = &prefix[..i],
+                    _ => break,
+                }
+            }
+        }
+        FALLBACK_FILE.to_string()
+    }
+
     pub fn http_request(
         &self,
         req: HttpRequest,
@@ -943,10 +1463,10 @@ impl State {
         let chunk_index = index.0.to_usize().unwrap_or(usize::MAX);
 
         Ok(StreamingCallbackHttpResponse {
-            body: enc.content_chunks[chunk_index].clone(),
+            body: self.content_chunk(enc, chunk_index)?,
             token: StreamingCallbackToken::create_token(
                 &content_encoding,
-                enc.content_chunks.len(),
+                enc.chunk_count(),
                 enc.sha256,
                 &key,
                 chunk_index,
@@ -957,7 +1477,7 @@ impl State {
     pub fn get_asset_properties(&self, key: AssetKey) -> Result<AssetProperties, String> {
         let asset = self
             .assets
-            .get(&key)
+            .get_without_content(&key)
             .ok_or_else(|| "asset not found".to_string())?;
 
         Ok(AssetProperties {
@@ -965,15 +1485,24 @@ impl State {
             headers: asset.headers.clone(),
             allow_raw_access: asset.allow_raw_access,
             is_aliased: asset.is_aliased,
+            redirect: asset.redirect.clone(),
+            rewrite_from: asset.rewrite_from.clone(),
         })
     }
 
     pub fn set_asset_properties(&mut self, arg: SetAssetPropertiesArguments) -> Result<(), String> {
+        if let Some(Some(redirect)) = &arg.redirect {
+            validate_redirect(redirect)?;
+        }
+        if let Some(Some(rewrite_from)) = &arg.rewrite_from {
+            self.validate_rewrite_from(&arg.key, rewrite_from)?;
+        }
         let dependent_keys = self.dependent_keys(&arg.key);
-        let asset = self
+        let mut asset = self
             .assets
-            .get_mut(&arg.key)
-            .ok_or_else(|| "asset not found".to_string())?;
+            .get_without_content(&arg.key)
+            .ok_or_else(|| "asset not found".to_string())?
+            .into_owned();
 
         if let Some(headers) = arg.headers {
             asset.headers = headers
@@ -988,20 +1517,84 @@ impl State {
         if let Some(is_aliased) = arg.is_aliased {
             asset.is_aliased = is_aliased
         }
+        if let Some(redirect) = arg.redirect {
+            asset.redirect = redirect
+        }
+        if let Some(rewrite_from) = arg.rewrite_from {
+            for prefix in asset.rewrite_from.iter().flatten() {
+                self.asset_hashes.remove_rewrite_responses(prefix);
+                self.rewrite_sources.remove(prefix);
+            }
+            asset.rewrite_from = rewrite_from;
+            self.index_rewrite_sources(&arg.key, &asset);
+        }
+
+        self.update_asset(&arg.key, asset, dependent_keys);
 
-        on_asset_change(&mut self.asset_hashes, &arg.key, asset, dependent_keys);
+        Ok(())
+    }
+
+    /// Copies the asset at `from` to `to`, replacing any asset there. The copy shares the stored
+    /// content of the original, which is not read. Rewrite prefixes stay with the original.
+    pub fn copy_asset(&mut self, from: &AssetKey, to: &AssetKey) -> Result<(), String> {
+        let mut asset = self
+            .assets
+            .get_without_content(from)
+            .ok_or_else(|| "asset not found".to_string())?
+            .into_owned();
+        asset.rewrite_from = None;
+
+        self.delete_asset(DeleteAssetArguments { key: to.clone() });
+        let dependent_keys = self.aliased_keys(to, asset.is_aliased);
+        self.update_asset(to, asset, dependent_keys);
+
+        Ok(())
+    }
 
+    // Rewrite prefixes are normalized paths below the root, each served by a single asset.
+    fn validate_rewrite_from(&self, key: &AssetKey, rewrite_from: &[String]) -> Result<(), String> {
+        for prefix in rewrite_from {
+            if !prefix.starts_with('/') || prefix == "/" || prefix.ends_with('/') {
+                return Err(format!(
+                    "invalid rewrite prefix '{}': expected a path like '/app'",
+                    prefix
+                ));
+            }
+            if prefix.contains("<*>") {
+                return Err(format!("invalid rewrite prefix '{}'", prefix));
+            }
+            let taken_by = self
+                .rewrite_sources
+                .get(prefix)
+                .filter(|other_key| *other_key != key);
+            if let Some(other_key) = taken_by {
+                return Err(format!(
+                    "rewrite prefix '{}' is already used by asset '{}'",
+                    prefix, other_key
+                ));
+            }
+        }
         Ok(())
     }
 
+    fn index_rewrite_sources(&mut self, key: &AssetKey, asset: &Asset) {
+        for prefix in asset.rewrite_from.iter().flatten() {
+            self.rewrite_sources.insert(prefix.clone(), key.clone());
+        }
+    }
+
     // Returns keys that needs to be updated if the supplied key is changed.
     fn dependent_keys(&self, key: &AssetKey) -> Vec<AssetKey> {
-        if self
+        let is_aliased = self
             .assets
-            .get(key)
-            .and_then(|asset| asset.is_aliased)
-            .unwrap_or(DEFAULT_ALIAS_ENABLED)
-        {
+            .get_without_content(key)
+            .and_then(|asset| asset.is_aliased);
+        self.aliased_keys(key, is_aliased)
+    }
+
+    // Keys served by the asset at `key` if aliasing is enabled for it.
+    fn aliased_keys(&self, key: &AssetKey, is_aliased: Option<bool>) -> Vec<AssetKey> {
+        if is_aliased.unwrap_or(DEFAULT_ALIAS_ENABLED) {
             aliased_by(key)
                 .into_iter()
                 .filter(|k| !self.assets.contains_key(k))
@@ -1034,6 +1627,28 @@ impl State {
         }
     }
 
+    // Recertifies a changed asset and writes it back. `dependent_keys` are the keys it served
+    // before the change.
+    fn update_asset(&mut self, key: &AssetKey, mut asset: Asset, dependent_keys: Vec<AssetKey>) {
+        self.assets.share_content(&mut asset);
+        on_asset_change(&mut self.asset_hashes, key, &mut asset, dependent_keys);
+        self.assets.insert(key.clone(), asset);
+    }
+
+    // Certifies the assets already in the store from the response hashes computed when they
+    // were last changed.
+    fn recertify_stored_assets(&mut self) {
+        for key in self.assets.keys() {
+            let dependent_keys = self.dependent_keys(&key);
+            if let Some(asset) = self.assets.get_without_content(&key).map(Cow::into_owned) {
+                self.index_rewrite_sources(&key, &asset);
+                let mut affected_keys = dependent_keys;
+                affected_keys.push(key);
+                insert_asset_hashes(&mut self.asset_hashes, &asset, &affected_keys);
+            }
+        }
+    }
+
     fn certify_404_if_required(&mut self) {
         if !self
             .asset_hashes
@@ -1065,7 +1680,7 @@ impl From<State> for StableState {
         Self {
             authorized: vec![],
             permissions: Some(permissions),
-            stable_assets: state.assets,
+            stable_assets: state.assets.into_heap_assets(),
             next_batch_id: Some(state.next_batch_id),
             configuration: Some(state.configuration),
         }
@@ -1074,6 +1689,12 @@ impl From<State> for StableState {
 
 impl From<StableState> for State {
     fn from(stable_state: StableState) -> Self {
+        Self::from_stable_state(stable_state, AssetStore::default())
+    }
+}
+
+impl State {
+    fn from_stable_state(stable_state: StableState, assets: AssetStore) -> Self {
         let (commit_principals, prepare_principals, manage_permissions_principals) =
             if let Some(permissions) = stable_state.permissions {
                 (
@@ -1092,7 +1713,7 @@ impl From<StableState> for State {
             commit_principals,
             prepare_principals,
             manage_permissions_principals,
-            assets: stable_state.stable_assets,
+            assets,
             next_batch_id: stable_state
                 .next_batch_id
                 .unwrap_or_else(|| Nat::from(1_u8)),
@@ -1100,17 +1721,17 @@ impl From<StableState> for State {
             ..Self::default()
         };
 
-        let assets_keys: Vec<_> = state.assets.keys().cloned().collect();
-        for key in assets_keys {
+        state.recertify_stored_assets();
+
+        // Aliases depend on which other assets exist, so all assets are added before any of
+        // them is certified
+        for (key, asset) in stable_state.stable_assets.iter() {
+            state.assets.insert(key.clone(), asset.clone());
+        }
+        for (key, asset) in stable_state.stable_assets {
+            state.index_rewrite_sources(&key, &asset);
             let dependent_keys = state.dependent_keys(&key);
-            if let Some(asset) = state.assets.get_mut(&key) {
-                for enc in asset.encodings.values_mut() {
-                    enc.certified = false;
-                }
-                on_asset_change(&mut state.asset_hashes, &key, asset, dependent_keys);
-            } else {
-                // shouldn't reach this
-            }
+            state.update_asset(&key, asset, dependent_keys);
         }
         state
     }
@@ -1153,39 +1774,76 @@ fn on_asset_change(
     affected_keys.push(key.to_string());
 
     delete_preexisting_asset_hashes(asset_hashes, &affected_keys);
-
-    if asset.encodings.is_empty() {
-        return;
+    for prefix in asset.rewrite_from.iter().flatten() {
+        asset_hashes.remove_rewrite_responses(prefix);
     }
 
     for enc in asset.encodings.values_mut() {
         enc.certified = false;
+        if enc.has_content() {
+            enc.chunk_lengths = Some(enc.lengths().into_owned());
+        }
     }
 
-    asset.update_ic_certificate_expressions();
+    // A redirect replaces the content of the asset
+    if asset.redirect.is_none() && !asset.encodings.is_empty() {
+        asset.update_ic_certificate_expressions();
 
-    let most_important_encoding_v1 = asset.most_important_encoding_v1();
-    let Asset {
-        content_type,
-        encodings,
-        max_age,
-        headers,
-        ..
-    } = asset;
-    // Insert certified response values into hash_tree
-    // Once certification v1 support is removed, encoding_certification_order().iter() can be replaced with asset.encodings.iter_mut()
-    for enc_name in encoding_certification_order(encodings.keys()).iter() {
-        if let Some(enc) = encodings.get_mut(enc_name) {
+        let Asset {
+            content_type,
+            encodings,
+            max_age,
+            headers,
+            ..
+        } = asset;
+        for (enc_name, enc) in encodings.iter_mut() {
             enc.response_hashes =
                 Some(enc.compute_response_hashes(headers, max_age, content_type, enc_name));
+            if enc.range_certificate_expression.is_some() {
+                enc.compute_range_response_hashes(headers, max_age, content_type);
+            } else {
+                enc.partial_response_hashes = None;
+                enc.range_not_satisfiable_hash = None;
+                enc.chunk_hashes = None;
+            }
+            enc.certified = true;
+        }
+    }
+
+    insert_asset_hashes(asset_hashes, asset, &affected_keys);
+}
+
+// Inserts the certified responses of an asset into the hash tree. Expects the response hashes
+// of its certified encodings to be up to date.
+fn insert_asset_hashes(
+    asset_hashes: &mut CertifiedResponses,
+    asset: &Asset,
+    affected_keys: &[AssetKey],
+) {
+    if asset.redirect.is_some() {
+        for key in affected_keys.iter() {
+            if let Some(hash_path) = asset.redirect_hash_path(&AssetPath::from(key)) {
+                asset_hashes.certify_response_precomputed(&hash_path);
+            }
+        }
+        return;
+    }
 
+    let most_important_encoding_v1 = asset.most_important_encoding_v1();
+    // Once certification v1 support is removed, encoding_certification_order().iter() can be replaced with asset.encodings.iter()
+    for enc_name in encoding_certification_order(asset.encodings.keys()).iter() {
+        if let Some(enc) = asset.encodings.get(enc_name).filter(|enc| enc.certified) {
             insert_new_response_hashes_for_encoding(
                 asset_hashes,
                 enc,
-                &affected_keys,
+                affected_keys,
                 enc_name == &most_important_encoding_v1,
             );
-            enc.certified = true;
+            for prefix in asset.rewrite_from.iter().flatten() {
+                if let Some(hash_path) = enc.rewrite_hash_path(prefix) {
+                    asset_hashes.certify_response_precomputed(&hash_path);
+                }
+            }
         }
     }
 }
@@ -1207,7 +1865,7 @@ fn delete_preexisting_asset_hashes(
 fn insert_new_response_hashes_for_encoding(
     asset_hashes: &mut CertifiedResponses,
     enc: &AssetEncoding,
-    affected_keys: &Vec<String>,
+    affected_keys: &[String],
     is_most_important_encoding: bool,
 ) {
     let affected_keys_slice: Vec<&str> = affected_keys.iter().map(|s| s.as_str()).collect();
@@ -1226,6 +1884,9 @@ fn insert_new_response_hashes_for_encoding(
                 );
             }
         }
+        for hash_path in enc.range_hash_paths(&key_path) {
+            asset_hashes.certify_response_precomputed(&hash_path);
+        }
         if key == FALLBACK_FILE {
             if let Some(not_found_hash_path) = enc.not_found_hash_path() {
                 asset_hashes.certify_response_precomputed(&not_found_hash_path);
@@ -1234,6 +1895,75 @@ fn insert_new_response_hashes_for_encoding(
     }
 }
 
+// Adds the certificate expression covering exactly `headers` and returns all headers of the
+// response together with the path certifying it.
+fn certified_response(
+    path: &AssetPath,
+    status_code: u16,
+    mut headers: Vec<HeaderField>,
+    body_hash: &[u8; 32],
+) -> (Vec<HeaderField>, HashTreePath) {
+    let ce = build_ic_certificate_expression_from_headers(&headers);
+    headers.push(build_ic_certificate_expression_header(&ce));
+    let certified_headers: Vec<(String, Value)> = headers
+        .iter()
+        .map(|(k, v)| (k.clone(), Value::String(v.clone())))
+        .collect();
+    let response_hash = response_hash(&certified_headers, status_code, body_hash);
+    let hash_path = path.hash_tree_path(&ce, &RequestHash::default(), response_hash);
+    (headers, hash_path)
+}
+
+fn validate_redirect(redirect: &AssetRedirect) -> Result<(), String> {
+    if ![301, 302, 307, 308].contains(&redirect.status_code) {
+        return Err(format!(
+            "invalid redirect status code {}: expected 301, 302, 307 or 308",
+            redirect.status_code
+        ));
+    }
+    if redirect.location.is_empty() {
+        return Err("redirect location must not be empty".to_string());
+    }
+    Ok(())
+}
+
+enum ByteRange {
+    // Offset of the first requested byte
+    Satisfiable(usize),
+    NotSatisfiable,
+}
+
+// Parses a single `bytes=` range, see RFC 9110 section 14.1.
+// Returns `None` for malformed and multi-part ranges, which are ignored.
+fn parse_range(range: &str, total_length: usize) -> Option<ByteRange> {
+    let spec = range.trim().strip_prefix("bytes=")?.trim();
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    let start = if start.is_empty() {
+        // suffix range: the last `end` bytes
+        let suffix_length: usize = end.parse().ok()?;
+        if suffix_length == 0 {
+            return Some(ByteRange::NotSatisfiable);
+        }
+        total_length.saturating_sub(suffix_length)
+    } else {
+        let start: usize = start.parse().ok()?;
+        if !end.is_empty() && end.parse::<usize>().ok()? < start {
+            return None;
+        }
+        start
+    };
+
+    if start >= total_length {
+        return Some(ByteRange::NotSatisfiable);
+    }
+    Some(ByteRange::Satisfiable(start))
+}
+
 // path like /path/to/my/asset should also be valid for /path/to/my/asset.html or /path/to/my/asset/index.html
 fn aliases_of(key: &AssetKey) -> Vec<AssetKey> {
     if key.ends_with('/') {
@@ -1,11 +1,12 @@
 use crate::asset_certification::types::http::{
     CallbackFunc, HttpRequest, HttpResponse, StreamingCallbackToken, StreamingStrategy,
 };
+use crate::asset_store::AssetMemories;
 use crate::state_machine::{StableState, State, BATCH_EXPIRY_NANOS};
 use crate::types::{
-    AssetProperties, BatchId, BatchOperation, CommitBatchArguments, CommitProposedBatchArguments,
-    ComputeEvidenceArguments, CreateAssetArguments, CreateChunkArg, DeleteAssetArguments,
-    DeleteBatchArguments, GetArg, GetChunkArg, SetAssetContentArguments,
+    AssetProperties, AssetRedirect, BatchId, BatchOperation, CommitBatchArguments,
+    CommitProposedBatchArguments, ComputeEvidenceArguments, CreateAssetArguments, CreateChunkArg,
+    DeleteAssetArguments, DeleteBatchArguments, GetArg, GetChunkArg, SetAssetContentArguments,
     SetAssetPropertiesArguments,
 };
 use crate::url_decode::{url_decode, UrlDecodeError};
@@ -15,6 +16,10 @@ use ic_crypto_tree_hash::Digest;
 use ic_response_verification_test_utils::{
     base64_encode, create_canister_id, get_current_timestamp,
 };
+use ic_stable_structures::{
+    memory_manager::{MemoryId, MemoryManager},
+    DefaultMemoryImpl,
+};
 use serde_bytes::ByteBuf;
 use std::collections::HashMap;
 
@@ -106,6 +111,8 @@ struct AssetBuilder {
     headers: Option<HashMap<String, String>>,
     aliasing: Option<bool>,
     allow_raw_access: Option<bool>,
+    redirect: Option<AssetRedirect>,
+    rewrite_from: Option<Vec<String>>,
 }
 
 impl AssetBuilder {
@@ -118,6 +125,8 @@ impl AssetBuilder {
             headers: None,
             aliasing: None,
             allow_raw_access: None,
+            redirect: None,
+            rewrite_from: None,
         }
     }
 
@@ -152,6 +161,21 @@ impl AssetBuilder {
         self.allow_raw_access = allow_raw_access;
         self
     }
+
+    fn with_redirect(mut self, location: &str, status_code: u16) -> Self {
+        self.redirect = Some(AssetRedirect {
+            location: location.to_string(),
+            status_code,
+        });
+        self
+    }
+
+    fn with_rewrite_from(mut self, prefix: &str) -> Self {
+        self.rewrite_from
+            .get_or_insert_with(Vec::new)
+            .push(prefix.to_string());
+        self
+    }
 }
 
 struct RequestBuilder {
@@ -273,6 +297,8 @@ fn assemble_create_assets_and_set_contents_operations(
             headers: asset.headers,
             enable_aliasing: asset.aliasing,
             allow_raw_access: asset.allow_raw_access,
+            redirect: asset.redirect,
+            rewrite_from: asset.rewrite_from,
         }));
 
         for (enc, chunks) in asset.encodings {
@@ -578,6 +604,282 @@ fn serve_fallback_v2() {
     assert_eq!(fallback_response.body.as_ref(), INDEX_BODY);
 }
 
+#[test]
+fn serves_redirects_v2() {
+    let mut state = State::default();
+    let time_now = 100_000_000_000;
+
+    create_assets(
+        &mut state,
+        time_now,
+        vec![
+            AssetBuilder::new("/old.html", "text/html").with_redirect("/new.html", 301),
+            AssetBuilder::new("/new.html", "text/html")
+                .with_encoding("identity", vec![b"<html>new</html>"]),
+        ],
+    );
+
+    let response = certified_http_request(
+        &state,
+        RequestBuilder::get("/old.html")
+            .with_header("Accept-Encoding", "identity")
+            .with_certificate_version(2)
+            .build(),
+    );
+    assert_eq!(response.status_code, 301);
+    assert_eq!(lookup_header(&response, "location"), Some("/new.html"));
+    assert!(response.body.is_empty());
+
+    // aliases redirect as well
+    let response = certified_http_request(
+        &state,
+        RequestBuilder::get("/old")
+            .with_header("Accept-Encoding", "identity")
+            .with_certificate_version(2)
+            .build(),
+    );
+    assert_eq!(response.status_code, 301);
+
+    state
+        .set_asset_properties(SetAssetPropertiesArguments {
+            key: "/old.html".to_string(),
+            max_age: None,
+            headers: None,
+            allow_raw_access: None,
+            is_aliased: None,
+            redirect: Some(Some(AssetRedirect {
+                location: "https://example.com/".to_string(),
+                status_code: 308,
+            })),
+            rewrite_from: None,
+        })
+        .unwrap();
+    let response = certified_http_request(
+        &state,
+        RequestBuilder::get("/old.html")
+            .with_header("Accept-Encoding", "identity")
+            .with_certificate_version(2)
+            .build(),
+    );
+    assert_eq!(response.status_code, 308);
+    assert_eq!(
+        lookup_header(&response, "location"),
+        Some("https://example.com/")
+    );
+}
+
+#[test]
+fn rejects_invalid_redirects_and_rewrites() {
+    let mut state = State::default();
+    let time_now = 100_000_000_000;
+
+    create_assets(
+        &mut state,
+        time_now,
+        vec![AssetBuilder::new("/app/index.html", "text/html")
+            .with_encoding("identity", vec![b"<html>app</html>"])
+            .with_rewrite_from("/app")],
+    );
+
+    let set_properties = |redirect, rewrite_from| SetAssetPropertiesArguments {
+        key: "/app/index.html".to_string(),
+        max_age: None,
+        headers: None,
+        allow_raw_access: None,
+        is_aliased: None,
+        redirect,
+        rewrite_from,
+    };
+    let redirect = |status_code| {
+        Some(Some(AssetRedirect {
+            location: "/".to_string(),
+            status_code,
+        }))
+    };
+    assert!(state
+        .set_asset_properties(set_properties(redirect(200), None))
+        .unwrap_err()
+        .contains("invalid redirect status code"));
+    for prefix in ["/", "app", "/app/", "/app/<*>"] {
+        assert!(state
+            .set_asset_properties(set_properties(None, Some(Some(vec![prefix.to_string()]))))
+            .unwrap_err()
+            .contains("invalid rewrite prefix"));
+    }
+
+    assert_eq!(
+        state
+            .create_asset(CreateAssetArguments {
+                key: "/other.html".to_string(),
+                content_type: "text/html".to_string(),
+                max_age: None,
+                headers: None,
+                enable_aliasing: None,
+                allow_raw_access: None,
+                redirect: None,
+                rewrite_from: Some(vec!["/app".to_string()]),
+            })
+            .unwrap_err(),
+        "rewrite prefix '/app' is already used by asset '/app/index.html'"
+    );
+
+    // A prefix is free again once its asset no longer uses it
+    state
+        .set_asset_properties(set_properties(None, Some(Some(vec!["/web".to_string()]))))
+        .unwrap();
+    state
+        .create_asset(CreateAssetArguments {
+            key: "/other.html".to_string(),
+            content_type: "text/html".to_string(),
+            max_age: None,
+            headers: None,
+            enable_aliasing: None,
+            allow_raw_access: None,
+            redirect: None,
+            rewrite_from: Some(vec!["/app".to_string()]),
+        })
+        .unwrap();
+}
+
+#[test]
+fn serves_rewrites_v2() {
+    let mut state = State::default();
+    let time_now = 100_000_000_000;
+
+    const INDEX_BODY: &[u8] = b"<!DOCTYPE html><html></html>";
+    const APP_BODY: &[u8] = b"<!DOCTYPE html><html>app</html>";
+    const ADMIN_BODY: &[u8] = b"<!DOCTYPE html><html>admin</html>";
+
+    create_assets(
+        &mut state,
+        time_now,
+        vec![
+            AssetBuilder::new("/index.html", "text/html")
+                .with_encoding("identity", vec![INDEX_BODY]),
+            AssetBuilder::new("/app/index.html", "text/html")
+                .with_encoding("identity", vec![APP_BODY])
+                .with_rewrite_from("/app"),
+            AssetBuilder::new("/app/admin/index.html", "text/html")
+                .with_encoding("identity", vec![ADMIN_BODY])
+                .with_rewrite_from("/app/admin"),
+            AssetBuilder::new("/app/style.css", "text/css")
+                .with_encoding("identity", vec!["body {}"]),
+        ],
+    );
+
+    let get = |path: &str| {
+        certified_http_request(
+            &state,
+            RequestBuilder::get(path)
+                .with_header("Accept-Encoding", "identity")
+                .with_certificate_version(2)
+                .build(),
+        )
+    };
+
+    let response = get("/app/users/42");
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.body.as_ref(), APP_BODY);
+
+    let response = get("/app/admin/settings");
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.body.as_ref(), ADMIN_BODY);
+
+    let response = get("/app/style.css");
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.body.as_ref(), b"body {}");
+
+    let response = get("/application");
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.body.as_ref(), INDEX_BODY);
+
+    // certification v1 only knows about the fallback file
+    let response = certified_http_request(
+        &state,
+        RequestBuilder::get("/app/users/42")
+            .with_header("Accept-Encoding", "identity")
+            .with_certificate_version(1)
+            .build(),
+    );
+    assert_eq!(response.body.as_ref(), INDEX_BODY);
+
+    state.delete_asset(DeleteAssetArguments {
+        key: "/app/admin/index.html".to_string(),
+    });
+    let response = certified_http_request(
+        &state,
+        RequestBuilder::get("/app/admin/settings")
+            .with_header("Accept-Encoding", "identity")
+            .with_certificate_version(2)
+            .build(),
+    );
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.body.as_ref(), APP_BODY);
+}
+
+#[test]
+fn serves_directory_listings_v2() {
+    let mut state = State::default();
+    let time_now = 100_000_000_000;
+
+    create_assets(
+        &mut state,
+        time_now,
+        vec![AssetBuilder::new("/docs/a.txt", "text/plain").with_encoding("identity", vec![b"a"])],
+    );
+
+    state
+        .certify_directory_listing(
+            &"/docs/".to_string(),
+            b"<html>listing</html>".to_vec(),
+            b"{\"key\":\"/docs/\"}".to_vec(),
+        )
+        .unwrap();
+    assert!(state
+        .certify_directory_listing(&"/docs".to_string(), vec![], vec![])
+        .is_err());
+    assert!(state
+        .certify_directory_listing(&"/docs/a.txt".to_string(), vec![], vec![])
+        .is_err());
+
+    let response = certified_http_request(
+        &state,
+        RequestBuilder::get("/docs/")
+            .with_certificate_version(2)
+            .build(),
+    );
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.body.as_ref(), b"<html>listing</html>");
+    assert_eq!(
+        lookup_header(&response, "content-type"),
+        Some("text/html; charset=utf-8")
+    );
+
+    let response = certified_http_request(
+        &state,
+        RequestBuilder::get("/docs/")
+            .with_header("Accept", "application/json")
+            .with_certificate_version(2)
+            .build(),
+    );
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.body.as_ref(), b"{\"key\":\"/docs/\"}");
+    assert_eq!(
+        lookup_header(&response, "content-type"),
+        Some("application/json")
+    );
+
+    state.remove_directory_listings(&"/".to_string());
+    let response = state.http_request(
+        RequestBuilder::get("/docs/")
+            .with_certificate_version(2)
+            .build(),
+        &[],
+        unused_callback(),
+    );
+    assert_eq!(response.status_code, 404);
+}
+
 #[test]
 fn serve_fallback_v1() {
     let mut state = State::default();
@@ -924,6 +1226,391 @@ fn preserves_state_on_stable_roundtrip() {
     assert_eq!(response.body.as_ref(), INDEX_BODY);
 }
 
+fn asset_memories(memory_manager: &MemoryManager<DefaultMemoryImpl>) -> AssetMemories {
+    AssetMemories {
+        assets: memory_manager.get(MemoryId::new(0)),
+        contents: memory_manager.get(MemoryId::new(1)),
+        chunks: memory_manager.get(MemoryId::new(2)),
+    }
+}
+
+#[test]
+fn keeps_assets_in_stable_memory_across_upgrades() {
+    let memory_manager = MemoryManager::init(DefaultMemoryImpl::default());
+    let mut state = State::with_stable_memory(asset_memories(&memory_manager));
+    let time_now = 100_000_000_000;
+
+    const INDEX_BODY: &[u8] = b"<!DOCTYPE html><html>Index</html>";
+    const APP_BODY: &[u8] = b"<!DOCTYPE html><html>app</html>";
+    const DATA_CHUNK_0: &[u8] = b"first chunk";
+    const DATA_CHUNK_1: &[u8] = b"second chunk";
+
+    create_assets(
+        &mut state,
+        time_now,
+        vec![
+            AssetBuilder::new("/index.html", "text/html")
+                .with_encoding("identity", vec![INDEX_BODY]),
+            AssetBuilder::new("/data.bin", "application/octet-stream")
+                .with_encoding("identity", vec![DATA_CHUNK_0, DATA_CHUNK_1]),
+            AssetBuilder::new("/app/index.html", "text/html")
+                .with_encoding("identity", vec![APP_BODY])
+                .with_rewrite_from("/app"),
+            AssetBuilder::new("/removed.html", "text/html")
+                .with_encoding("identity", vec![INDEX_BODY]),
+        ],
+    );
+    state.delete_asset(DeleteAssetArguments {
+        key: "/removed.html".to_string(),
+    });
+
+    // Only the heap state goes through the upgrade
+    let stable_state: StableState = state.into();
+    let state = State::from_stable_state_with_memory(stable_state, asset_memories(&memory_manager));
+
+    assert_eq!(state.list_assets().len(), 3);
+
+    // Rewrite prefixes are known again after the upgrade
+    let response = certified_http_request(
+        &state,
+        RequestBuilder::get("/app/users/42")
+            .with_header("Accept-Encoding", "identity")
+            .with_certificate_version(2)
+            .build(),
+    );
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.body.as_ref(), APP_BODY);
+
+    let response = certified_http_request(
+        &state,
+        RequestBuilder::get("/index.html")
+            .with_header("Accept-Encoding", "gzip,identity")
+            .build(),
+    );
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.body.as_ref(), INDEX_BODY);
+
+    let response = certified_http_request(&state, RequestBuilder::get("/removed.html").build());
+    assert_eq!(response.body.as_ref(), INDEX_BODY);
+    assert_eq!(response.status_code, 200, "falls back to /index.html");
+
+    let chunk_0 = state
+        .get(GetArg {
+            key: "/data.bin".to_string(),
+            accept_encodings: vec!["identity".to_string()],
+        })
+        .unwrap();
+    assert_eq!(chunk_0.content.as_ref(), DATA_CHUNK_0);
+    let chunk_1 = state
+        .get_chunk(GetChunkArg {
+            key: "/data.bin".to_string(),
+            content_encoding: "identity".to_string(),
+            index: Nat::from(1_u8),
+            sha256: chunk_0.sha256,
+        })
+        .unwrap();
+    assert_eq!(chunk_1.as_ref(), DATA_CHUNK_1);
+}
+
+#[test]
+fn moves_heap_assets_into_stable_memory() {
+    let mut state = State::default();
+    let time_now = 100_000_000_000;
+
+    const INDEX_BODY: &[u8] = b"<!DOCTYPE html><html>Index</html>";
+
+    create_assets(
+        &mut state,
+        time_now,
+        vec![AssetBuilder::new("/index.html", "text/html")
+            .with_encoding("identity", vec![INDEX_BODY])],
+    );
+
+    let memory_manager = MemoryManager::init(DefaultMemoryImpl::default());
+    let stable_state: StableState = state.into();
+    let state = State::from_stable_state_with_memory(stable_state, asset_memories(&memory_manager));
+
+    // Nothing is left to serialize on the next upgrade
+    let stable_state: StableState = state.into();
+    let heap_state: State = stable_state.into();
+    assert!(heap_state.list_assets().is_empty());
+
+    let stable_state: StableState = heap_state.into();
+    let state = State::from_stable_state_with_memory(stable_state, asset_memories(&memory_manager));
+    let response = certified_http_request(
+        &state,
+        RequestBuilder::get("/index.html")
+            .with_header("Accept-Encoding", "gzip,identity")
+            .build(),
+    );
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.body.as_ref(), INDEX_BODY);
+}
+
+#[test]
+fn stores_identical_content_once() {
+    let mut state = State::default();
+    let time_now = 100_000_000_000;
+
+    const BODY: &[u8] = b"<!DOCTYPE html><html>Same content</html>";
+    const OTHER_BODY: &[u8] = b"<!DOCTYPE html><html>Other content</html>";
+
+    create_assets(
+        &mut state,
+        time_now,
+        vec![
+            AssetBuilder::new("/a.html", "text/html").with_encoding("identity", vec![BODY]),
+            AssetBuilder::new("/b.html", "text/html").with_encoding("identity", vec![BODY]),
+            AssetBuilder::new("/c.html", "text/html").with_encoding("identity", vec![OTHER_BODY]),
+        ],
+    );
+
+    let usage = state.storage_usage();
+    assert_eq!(
+        usage.logical_bytes,
+        Nat::from(2 * BODY.len() + OTHER_BODY.len())
+    );
+    assert_eq!(
+        usage.physical_bytes,
+        Nat::from(BODY.len() + OTHER_BODY.len())
+    );
+
+    // The shared content is kept until the last asset using it is deleted
+    state.delete_asset(DeleteAssetArguments {
+        key: "/a.html".to_string(),
+    });
+    assert_eq!(
+        state.storage_usage().physical_bytes,
+        Nat::from(BODY.len() + OTHER_BODY.len())
+    );
+    let response = certified_http_request(&state, RequestBuilder::get("/b.html").build());
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.body.as_ref(), BODY);
+
+    state.delete_asset(DeleteAssetArguments {
+        key: "/b.html".to_string(),
+    });
+    assert_eq!(
+        state.storage_usage().physical_bytes,
+        Nat::from(OTHER_BODY.len())
+    );
+}
+
+#[test]
+fn stores_identical_content_once_in_stable_memory() {
+    let memory_manager = MemoryManager::init(DefaultMemoryImpl::default());
+    let mut state = State::with_stable_memory(asset_memories(&memory_manager));
+    let time_now = 100_000_000_000;
+
+    const CHUNK_0: &[u8] = b"<!DOCTYPE html>";
+    const CHUNK_1: &[u8] = b"<html>Same content</html>";
+    const BODY: &[u8] = b"<!DOCTYPE html><html>Same content</html>";
+
+    create_assets(
+        &mut state,
+        time_now,
+        vec![
+            AssetBuilder::new("/chunked.html", "text/html")
+                .with_encoding("identity", vec![CHUNK_0, CHUNK_1]),
+            AssetBuilder::new("/whole.html", "text/html").with_encoding("identity", vec![BODY]),
+        ],
+    );
+
+    let usage = state.storage_usage();
+    assert_eq!(usage.logical_bytes, Nat::from(2 * BODY.len()));
+    assert_eq!(usage.physical_bytes, Nat::from(BODY.len()));
+
+    // Served with the chunk boundaries of the content stored first
+    let chunk_0 = state
+        .get(GetArg {
+            key: "/whole.html".to_string(),
+            accept_encodings: vec!["identity".to_string()],
+        })
+        .unwrap();
+    assert_eq!(chunk_0.content.as_ref(), CHUNK_0);
+    assert_eq!(chunk_0.total_length, Nat::from(BODY.len()));
+
+    state.delete_asset(DeleteAssetArguments {
+        key: "/chunked.html".to_string(),
+    });
+    let chunk_1 = state
+        .get_chunk(GetChunkArg {
+            key: "/whole.html".to_string(),
+            content_encoding: "identity".to_string(),
+            index: Nat::from(1_u8),
+            sha256: chunk_0.sha256,
+        })
+        .unwrap();
+    assert_eq!(chunk_1.as_ref(), CHUNK_1);
+
+    state.delete_asset(DeleteAssetArguments {
+        key: "/whole.html".to_string(),
+    });
+    assert_eq!(state.storage_usage().physical_bytes, Nat::from(0_u8));
+}
+
+#[test]
+fn copies_stable_assets_sharing_their_content() {
+    let memory_manager = MemoryManager::init(DefaultMemoryImpl::default());
+    let mut state = State::with_stable_memory(asset_memories(&memory_manager));
+    let time_now = 100_000_000_000;
+
+    const CHUNK_0: &[u8] = b"first chunk";
+    const CHUNK_1: &[u8] = b"second chunk";
+
+    create_assets(
+        &mut state,
+        time_now,
+        vec![
+            AssetBuilder::new("/data.bin", "application/octet-stream")
+                .with_encoding("identity", vec![CHUNK_0, CHUNK_1]),
+            AssetBuilder::new("/moved.bin", "application/octet-stream")
+                .with_encoding("identity", vec![b"replaced"]),
+        ],
+    );
+
+    state
+        .copy_asset(&"/data.bin".to_string(), &"/moved.bin".to_string())
+        .unwrap();
+    state.delete_asset(DeleteAssetArguments {
+        key: "/data.bin".to_string(),
+    });
+
+    let usage = state.storage_usage();
+    assert_eq!(
+        usage.logical_bytes,
+        Nat::from(CHUNK_0.len() + CHUNK_1.len())
+    );
+    assert_eq!(usage.physical_bytes, usage.logical_bytes);
+
+    let response = certified_http_request(
+        &state,
+        RequestBuilder::get("/moved.bin")
+            .with_header("Accept-Encoding", "identity")
+            .build(),
+    );
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.body.as_ref(), CHUNK_0);
+    let StreamingStrategy::Callback { token, .. } = response
+        .streaming_strategy
+        .expect("missing streaming strategy");
+    let streaming_response = state.http_request_streaming_callback(token).unwrap();
+    assert_eq!(streaming_response.body.as_ref(), CHUNK_1);
+
+    assert_eq!(
+        state.copy_asset(&"/data.bin".to_string(), &"/other.bin".to_string()),
+        Err("asset not found".to_string())
+    );
+}
+
+#[test]
+fn serves_stable_assets_one_chunk_at_a_time() {
+    let memory_manager = MemoryManager::init(DefaultMemoryImpl::default());
+    let mut state = State::with_stable_memory(asset_memories(&memory_manager));
+    let time_now = 100_000_000_000;
+
+    const CHUNK_0: &[u8] = b"<!DOCTYPE html>";
+    const CHUNK_1: &[u8] = b"<html>Index</html>";
+
+    create_assets(
+        &mut state,
+        time_now,
+        vec![AssetBuilder::new("/index.html", "text/html")
+            .with_encoding("identity", vec![CHUNK_0, CHUNK_1])],
+    );
+
+    // Recertified from the stored chunk lengths and hashes, without reading the content
+    state
+        .set_asset_properties(SetAssetPropertiesArguments {
+            key: "/index.html".to_string(),
+            max_age: Some(Some(604800)),
+            headers: None,
+            allow_raw_access: None,
+            is_aliased: None,
+            redirect: None,
+            rewrite_from: None,
+        })
+        .unwrap();
+
+    let response = certified_http_request(
+        &state,
+        RequestBuilder::get("/index.html")
+            .with_header("Accept-Encoding", "identity")
+            .build(),
+    );
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.body.as_ref(), CHUNK_0);
+    assert_eq!(
+        lookup_header(&response, "cache-control"),
+        Some("max-age=604800")
+    );
+    let StreamingStrategy::Callback { token, .. } = response
+        .streaming_strategy
+        .expect("missing streaming strategy");
+    let streaming_response = state.http_request_streaming_callback(token).unwrap();
+    assert_eq!(streaming_response.body.as_ref(), CHUNK_1);
+
+    let response = state.http_request(
+        RequestBuilder::get("/index.html")
+            .with_header("Accept-Encoding", "identity")
+            .with_header("Range", format!("bytes={}-", CHUNK_0.len()))
+            .with_certificate_version(2)
+            .build(),
+        &[],
+        unused_callback(),
+    );
+    assert_eq!(response.status_code, 206);
+    assert_eq!(response.body.as_ref(), CHUNK_1);
+    assert!(lookup_header(&response, "ic-certificateexpression")
+        .unwrap()
+        .contains("content-range"));
+}
+
+#[test]
+fn rejects_content_not_matching_its_sha256() {
+    let mut state = State::default();
+    let time_now = 100_000_000_000;
+
+    let batch_id = state.create_batch(time_now).unwrap();
+    let chunk_id = state
+        .create_chunk(
+            CreateChunkArg {
+                batch_id: batch_id.clone(),
+                content: ByteBuf::from(b"content".to_vec()),
+            },
+            time_now,
+        )
+        .unwrap();
+
+    let error_msg = state
+        .commit_batch(
+            CommitBatchArguments {
+                batch_id,
+                operations: vec![
+                    BatchOperation::CreateAsset(CreateAssetArguments {
+                        key: "/file.txt".to_string(),
+                        content_type: "text/plain".to_string(),
+                        max_age: None,
+                        headers: None,
+                        enable_aliasing: None,
+                        allow_raw_access: None,
+                        redirect: None,
+                        rewrite_from: None,
+                    }),
+                    BatchOperation::SetAssetContent(SetAssetContentArguments {
+                        key: "/file.txt".to_string(),
+                        content_encoding: "identity".to_string(),
+                        chunk_ids: vec![chunk_id],
+                        sha256: Some(ByteBuf::from(vec![0; 32])),
+                    }),
+                ],
+            },
+            time_now,
+        )
+        .unwrap_err();
+    assert_eq!(error_msg, "sha256 mismatch");
+}
+
 #[test]
 fn uses_streaming_for_multichunk_assets() {
     let mut state = State::default();
@@ -1025,6 +1712,124 @@ fn get_and_get_chunk_for_multichunk_assets() {
     );
 }
 
+#[test]
+fn serves_range_requests_by_chunk() {
+    let mut state = State::default();
+    let time_now = 100_000_000_000;
+
+    const CHUNK_1: &[u8] = b"<!DOCTYPE html>";
+    const CHUNK_2: &[u8] = b"<html>Index</html>";
+    let total_length = CHUNK_1.len() + CHUNK_2.len();
+
+    create_assets(
+        &mut state,
+        time_now,
+        vec![AssetBuilder::new("/index.html", "text/html")
+            .with_encoding("identity", vec![CHUNK_1, CHUNK_2])],
+    );
+
+    let range_request = |range: &str| {
+        state.http_request(
+            RequestBuilder::get("/index.html")
+                .with_header("Accept-Encoding", "identity")
+                .with_header("Range", range)
+                .with_certificate_version(2)
+                .build(),
+            &[],
+            unused_callback(),
+        )
+    };
+
+    let response = range_request(&format!("bytes={}-", CHUNK_1.len()));
+    assert_eq!(response.status_code, 206);
+    assert_eq!(response.body.as_ref(), CHUNK_2);
+    assert_eq!(
+        lookup_header(&response, "content-range"),
+        Some(
+            format!(
+                "bytes {}-{}/{}",
+                CHUNK_1.len(),
+                total_length - 1,
+                total_length
+            )
+            .as_str()
+        )
+    );
+    assert!(lookup_header(&response, "ic-certificateexpression")
+        .unwrap()
+        .contains("content-range"));
+
+    // the response may contain more than requested, but always the whole chunk
+    let response = range_request("bytes=0-3");
+    assert_eq!(response.status_code, 206);
+    assert_eq!(response.body.as_ref(), CHUNK_1);
+    assert_eq!(
+        lookup_header(&response, "content-range"),
+        Some(format!("bytes 0-{}/{}", CHUNK_1.len() - 1, total_length).as_str())
+    );
+
+    let response = range_request(&format!("bytes=-{}", CHUNK_2.len()));
+    assert_eq!(response.status_code, 206);
+    assert_eq!(response.body.as_ref(), CHUNK_2);
+
+    // a range starting within a chunk is answered with the chunk holding its first byte
+    let response = range_request("bytes=1-");
+    assert_eq!(response.status_code, 206);
+    assert_eq!(response.body.as_ref(), CHUNK_1);
+    assert_eq!(
+        lookup_header(&response, "content-range"),
+        Some(format!("bytes 0-{}/{}", CHUNK_1.len() - 1, total_length).as_str())
+    );
+
+    let response = range_request(&format!("bytes={}-", CHUNK_1.len() + 3));
+    assert_eq!(response.status_code, 206);
+    assert_eq!(response.body.as_ref(), CHUNK_2);
+
+    let response = range_request("bytes=-1");
+    assert_eq!(response.status_code, 206);
+    assert_eq!(response.body.as_ref(), CHUNK_2);
+
+    let response = range_request(&format!("bytes={}-", total_length));
+    assert_eq!(response.status_code, 416);
+    assert!(response.body.is_empty());
+    assert_eq!(
+        lookup_header(&response, "content-range"),
+        Some(format!("bytes */{}", total_length).as_str())
+    );
+
+    // multi-part and malformed ranges are answered with the full asset
+    for range in ["bytes=0-1,5-6", "bytes=5-1", "items=0-"] {
+        let response = range_request(range);
+        assert_eq!(response.status_code, 200, "range {}", range);
+        assert_eq!(response.body.as_ref(), CHUNK_1);
+    }
+}
+
+#[test]
+fn ignores_range_requests_for_single_chunk_assets() {
+    let mut state = State::default();
+    let time_now = 100_000_000_000;
+
+    const BODY: &[u8] = b"<!DOCTYPE html><html>Index</html>";
+
+    create_assets(
+        &mut state,
+        time_now,
+        vec![AssetBuilder::new("/index.html", "text/html").with_encoding("identity", vec![BODY])],
+    );
+
+    let response = certified_http_request(
+        &state,
+        RequestBuilder::get("/index.html")
+            .with_header("Accept-Encoding", "identity")
+            .with_header("Range", "bytes=0-")
+            .with_certificate_version(2)
+            .build(),
+    );
+    assert_eq!(response.status_code, 200);
+    assert_eq!(response.body.as_ref(), BODY);
+}
+
 #[test]
 fn supports_max_age_headers() {
     let mut state = State::default();
@@ -1192,7 +1997,9 @@ fn supports_getting_and_setting_asset_properties() {
                 "*".into()
             )])),
             allow_raw_access: None,
-            is_aliased: None
+            is_aliased: None,
+            redirect: None,
+            rewrite_from: None,
         })
     );
     assert_eq!(
@@ -1204,7 +2011,9 @@ fn supports_getting_and_setting_asset_properties() {
                 "nosniff".into()
             )])),
             allow_raw_access: None,
-            is_aliased: None
+            is_aliased: None,
+            redirect: None,
+            rewrite_from: None,
         })
     );
 
@@ -1217,7 +2026,9 @@ fn supports_getting_and_setting_asset_properties() {
                 "nosniff".into()
             )]))),
             allow_raw_access: None,
-            is_aliased: None
+            is_aliased: None,
+            redirect: None,
+            rewrite_from: None,
         })
         .is_ok());
     assert_eq!(
@@ -1229,7 +2040,9 @@ fn supports_getting_and_setting_asset_properties() {
                 "nosniff".into()
             )])),
             allow_raw_access: None,
-            is_aliased: None
+            is_aliased: None,
+            redirect: None,
+            rewrite_from: None,
         })
     );
 
@@ -1239,7 +2052,9 @@ fn supports_getting_and_setting_asset_properties() {
             max_age: Some(None),
             headers: Some(None),
             allow_raw_access: None,
-            is_aliased: None
+            is_aliased: None,
+            redirect: None,
+            rewrite_from: None,
         })
         .is_ok());
     assert_eq!(
@@ -1248,7 +2063,9 @@ fn supports_getting_and_setting_asset_properties() {
             max_age: None,
             headers: None,
             allow_raw_access: None,
-            is_aliased: None
+            is_aliased: None,
+            redirect: None,
+            rewrite_from: None,
         })
     );
 
@@ -1261,7 +2078,9 @@ fn supports_getting_and_setting_asset_properties() {
                 "nosniff".into()
             )]))),
             allow_raw_access: None,
-            is_aliased: None
+            is_aliased: None,
+            redirect: None,
+            rewrite_from: None,
         })
         .is_ok());
     assert_eq!(
@@ -1273,7 +2092,9 @@ fn supports_getting_and_setting_asset_properties() {
                 "nosniff".into()
             )])),
             allow_raw_access: None,
-            is_aliased: None
+            is_aliased: None,
+            redirect: None,
+            rewrite_from: None,
         })
     );
 
@@ -1283,7 +2104,9 @@ fn supports_getting_and_setting_asset_properties() {
             max_age: None,
             headers: Some(Some(HashMap::from([("new-header".into(), "value".into())]))),
             allow_raw_access: None,
-            is_aliased: None
+            is_aliased: None,
+            redirect: None,
+            rewrite_from: None,
         })
         .is_ok());
     assert_eq!(
@@ -1292,7 +2115,9 @@ fn supports_getting_and_setting_asset_properties() {
             max_age: Some(1),
             headers: Some(HashMap::from([("new-header".into(), "value".into())])),
             allow_raw_access: None,
-            is_aliased: None
+            is_aliased: None,
+            redirect: None,
+            rewrite_from: None,
         })
     );
 
@@ -1302,7 +2127,9 @@ fn supports_getting_and_setting_asset_properties() {
             max_age: Some(Some(2)),
             headers: None,
             allow_raw_access: None,
-            is_aliased: None
+            is_aliased: None,
+            redirect: None,
+            rewrite_from: None,
         })
         .is_ok());
     assert_eq!(
@@ -1311,7 +2138,9 @@ fn supports_getting_and_setting_asset_properties() {
             max_age: Some(2),
             headers: Some(HashMap::from([("new-header".into(), "value".into())])),
             allow_raw_access: None,
-            is_aliased: None
+            is_aliased: None,
+            redirect: None,
+            rewrite_from: None,
         })
     );
 
@@ -1321,7 +2150,9 @@ fn supports_getting_and_setting_asset_properties() {
             max_age: None,
             headers: None,
             allow_raw_access: None,
-            is_aliased: Some(Some(false))
+            is_aliased: Some(Some(false)),
+            redirect: None,
+            rewrite_from: None,
         })
         .is_ok());
     assert_eq!(
@@ -1330,7 +2161,9 @@ fn supports_getting_and_setting_asset_properties() {
             max_age: Some(2),
             headers: Some(HashMap::from([("new-header".into(), "value".into())])),
             allow_raw_access: None,
-            is_aliased: Some(false)
+            is_aliased: Some(false),
+            redirect: None,
+            rewrite_from: None,
         })
     );
 
@@ -1340,7 +2173,9 @@ fn supports_getting_and_setting_asset_properties() {
             max_age: None,
             headers: Some(None),
             allow_raw_access: None,
-            is_aliased: Some(None)
+            is_aliased: Some(None),
+            redirect: None,
+            rewrite_from: None,
         })
         .is_ok());
     assert_eq!(
@@ -1349,7 +2184,9 @@ fn supports_getting_and_setting_asset_properties() {
             max_age: Some(2),
             headers: None,
             allow_raw_access: None,
-            is_aliased: None
+            is_aliased: None,
+            redirect: None,
+            rewrite_from: None,
         })
     );
 }
@@ -1376,6 +2213,8 @@ fn create_asset_fails_if_asset_exists() {
                 headers: None,
                 allow_raw_access: None,
                 enable_aliasing: None,
+                redirect: None,
+                rewrite_from: None,
             })
             .unwrap_err()
             == "asset already exists"
@@ -1458,6 +2297,8 @@ fn alias_enable_and_disable() {
             headers: None,
             allow_raw_access: None,
             is_aliased: Some(Some(false)),
+            redirect: None,
+            rewrite_from: None,
         })
         .is_ok());
 
@@ -1487,6 +2328,8 @@ fn alias_enable_and_disable() {
             headers: None,
             allow_raw_access: None,
             is_aliased: Some(Some(true)),
+            redirect: None,
+            rewrite_from: None,
         })
         .is_ok());
     let alias_add_html_again =
@@ -1803,6 +2646,8 @@ mod certificate_expression {
                 )]))),
                 allow_raw_access: None,
                 is_aliased: None,
+                redirect: None,
+                rewrite_from: None,
             })
             .unwrap();
         let response = certified_http_request(
@@ -1962,6 +2807,8 @@ mod evidence_computation {
             headers: None,
             enable_aliasing: None,
             allow_raw_access: None,
+            redirect: None,
+            rewrite_from: None,
         };
         let set_asset_content = SetAssetContentArguments {
             key: "/a/b/c".to_string(),
@@ -2027,6 +2874,8 @@ mod evidence_computation {
             headers: None,
             enable_aliasing: None,
             allow_raw_access: None,
+            redirect: None,
+            rewrite_from: None,
         };
         let set_asset_content = SetAssetContentArguments {
             key: "/a/b/c".to_string(),
@@ -2071,6 +2920,8 @@ mod evidence_computation {
             headers: None,
             enable_aliasing: None,
             allow_raw_access: None,
+            redirect: None,
+            rewrite_from: None,
         };
         let cba = CommitBatchArguments {
             batch_id: batch_id.clone(),
@@ -2103,6 +2954,8 @@ mod evidence_computation {
             headers: None,
             enable_aliasing: None,
             allow_raw_access: None,
+            redirect: None,
+            rewrite_from: None,
         };
         let set_asset_content = SetAssetContentArguments {
             key: "/a/b/c".to_string(),
@@ -2173,6 +3026,8 @@ mod evidence_computation {
                         headers: None,
                         enable_aliasing: None,
                         allow_raw_access: None,
+                        redirect: None,
+                        rewrite_from: None,
                     }),],
                 })
                 .is_ok());
@@ -2196,6 +3051,8 @@ mod evidence_computation {
                         headers: None,
                         enable_aliasing: None,
                         allow_raw_access: None,
+                        redirect: None,
+                        rewrite_from: None,
                     }),],
                 })
                 .is_ok());
@@ -2226,6 +3083,8 @@ mod evidence_computation {
                         ])),
                         enable_aliasing: Some(true),
                         allow_raw_access: Some(false),
+                        redirect: None,
+                        rewrite_from: None,
                     }),],
                 })
                 .is_ok());
@@ -2252,6 +3111,8 @@ mod evidence_computation {
                         ])),
                         enable_aliasing: Some(true),
                         allow_raw_access: Some(false),
+                        redirect: None,
+                        rewrite_from: None,
                     }),],
                 })
                 .is_ok());
@@ -2282,6 +3143,8 @@ mod evidence_computation {
                     headers: None,
                     enable_aliasing: None,
                     allow_raw_access: None,
+                    redirect: None,
+                    rewrite_from: None,
                 }),],
             })
             .is_ok());
@@ -2305,6 +3168,8 @@ mod evidence_computation {
                     headers: None,
                     enable_aliasing: None,
                     allow_raw_access: None,
+                    redirect: None,
+                    rewrite_from: None,
                 }),],
             })
             .is_ok());
@@ -2335,6 +3200,8 @@ mod evidence_computation {
                     headers: None,
                     enable_aliasing: None,
                     allow_raw_access: None,
+                    redirect: None,
+                    rewrite_from: None,
                 }),],
             })
             .is_ok());
@@ -2358,6 +3225,8 @@ mod evidence_computation {
                     headers: None,
                     enable_aliasing: None,
                     allow_raw_access: None,
+                    redirect: None,
+                    rewrite_from: None,
                 }),],
             })
             .is_ok());
@@ -2388,6 +3257,8 @@ mod evidence_computation {
                     headers: None,
                     enable_aliasing: None,
                     allow_raw_access: None,
+                    redirect: None,
+                    rewrite_from: None,
                 }),],
             })
             .is_ok());
@@ -2411,6 +3282,8 @@ mod evidence_computation {
                     headers: None,
                     enable_aliasing: None,
                     allow_raw_access: None,
+                    redirect: None,
+                    rewrite_from: None,
                 }),],
             })
             .is_ok());
@@ -2435,6 +3308,8 @@ mod evidence_computation {
                     headers: None,
                     enable_aliasing: None,
                     allow_raw_access: None,
+                    redirect: None,
+                    rewrite_from: None,
                 }),],
             })
             .is_ok());
@@ -2467,6 +3342,8 @@ mod evidence_computation {
                     headers: Some(HashMap::from([("H1".to_string(), "V1".to_string()),])),
                     enable_aliasing: None,
                     allow_raw_access: None,
+                    redirect: None,
+                    rewrite_from: None,
                 }),],
             })
             .is_ok());
@@ -2490,6 +3367,8 @@ mod evidence_computation {
                     headers: Some(HashMap::from([("H1".to_string(), "V2".to_string()),])),
                     enable_aliasing: None,
                     allow_raw_access: None,
+                    redirect: None,
+                    rewrite_from: None,
                 }),],
             })
             .is_ok());
@@ -2513,6 +3392,8 @@ mod evidence_computation {
                     headers: Some(HashMap::from([("H2".to_string(), "V1".to_string()),])),
                     enable_aliasing: None,
                     allow_raw_access: None,
+                    redirect: None,
+                    rewrite_from: None,
                 }),],
             })
             .is_ok());
@@ -2540,6 +3421,8 @@ mod evidence_computation {
                     ])),
                     enable_aliasing: None,
                     allow_raw_access: None,
+                    redirect: None,
+                    rewrite_from: None,
                 }),],
             })
             .is_ok());
@@ -2575,6 +3458,8 @@ mod evidence_computation {
                     headers: None,
                     enable_aliasing: None,
                     allow_raw_access: None,
+                    redirect: None,
+                    rewrite_from: None,
                 }),],
             })
             .is_ok());
@@ -2598,6 +3483,8 @@ mod evidence_computation {
                     headers: None,
                     enable_aliasing: Some(false),
                     allow_raw_access: None,
+                    redirect: None,
+                    rewrite_from: None,
                 }),],
             })
             .is_ok());
@@ -2622,6 +3509,8 @@ mod evidence_computation {
                     headers: None,
                     enable_aliasing: Some(true),
                     allow_raw_access: None,
+                    redirect: None,
+                    rewrite_from: None,
                 }),],
             })
             .is_ok());
@@ -2654,6 +3543,8 @@ mod evidence_computation {
                     headers: None,
                     enable_aliasing: None,
                     allow_raw_access: None,
+                    redirect: None,
+                    rewrite_from: None,
                 }),],
             })
             .is_ok());
@@ -2677,6 +3568,8 @@ mod evidence_computation {
                     headers: None,
                     enable_aliasing: None,
                     allow_raw_access: Some(false),
+                    redirect: None,
+                    rewrite_from: None,
                 }),],
             })
             .is_ok());
@@ -2701,6 +3594,8 @@ mod evidence_computation {
                     headers: None,
                     enable_aliasing: None,
                     allow_raw_access: Some(true),
+                    redirect: None,
+                    rewrite_from: None,
                 }),],
             })
             .is_ok());
@@ -3229,7 +4124,9 @@ mod evidence_computation {
                         max_age: Some(Some(100)),
                         headers: None,
                         allow_raw_access: Some(Some(false)),
-                        is_aliased: Some(Some(true))
+                        is_aliased: Some(Some(true)),
+                        redirect: None,
+                        rewrite_from: None,
                     }
                 ),],
             })
@@ -3253,7 +4150,9 @@ mod evidence_computation {
                         max_age: Some(Some(100)),
                         headers: None,
                         allow_raw_access: Some(Some(false)),
-                        is_aliased: Some(Some(true))
+                        is_aliased: Some(Some(true)),
+                        redirect: None,
+                        rewrite_from: None,
                     }
                 ),],
             })
@@ -3291,6 +4190,8 @@ mod evidence_computation {
                                 headers: headers.clone(),
                                 allow_raw_access: *allow_raw_access,
                                 is_aliased: *is_aliased,
+                                redirect: None,
+                                rewrite_from: None,
                             });
                         }
                     }
@@ -32,6 +32,16 @@ pub struct CreateAssetArguments {
     pub headers: Option<HashMap<String, String>>,
     pub enable_aliasing: Option<bool>,
     pub allow_raw_access: Option<bool>,
+    pub redirect: Option<AssetRedirect>,
+    pub rewrite_from: Option<Vec<String>>,
+}
+
+/// Answers requests for an asset with a redirect instead of its content.
+#[derive(Clone, Debug, CandidType, Deserialize, PartialEq, Eq)]
+pub struct AssetRedirect {
+    pub location: String,
+    /// One of 301, 302, 307 or 308
+    pub status_code: u16,
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize)]
@@ -140,6 +150,8 @@ pub struct AssetProperties {
     pub headers: Option<HashMap<String, String>>,
     pub allow_raw_access: Option<bool>,
     pub is_aliased: Option<bool>,
+    pub redirect: Option<AssetRedirect>,
+    pub rewrite_from: Option<Vec<String>>,
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize)]
@@ -149,6 +161,8 @@ pub struct SetAssetPropertiesArguments {
     pub headers: Option<Option<HashMap<String, String>>>,
     pub allow_raw_access: Option<Option<bool>>,
     pub is_aliased: Option<Option<bool>>,
+    pub redirect: Option<Option<AssetRedirect>>,
+    pub rewrite_from: Option<Option<Vec<String>>>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, CandidType, Deserialize)]
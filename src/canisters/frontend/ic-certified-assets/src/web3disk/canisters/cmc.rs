@@ -1,30 +1,33 @@
+use crate::web3disk::stores::config::ConfigStore;
 use candid::{CandidType, Deserialize, Principal};
 use ic_cdk::call;
-use ic_ledger_types::{BlockIndex, MAINNET_CYCLES_MINTING_CANISTER_ID};
+use ic_ledger_types::BlockIndex;
 
 /*
-Notify CMC of a ICP tx for cycle minting
+Notify CMC of a ICP tx for cycle minting.
+Notifying is idempotent, so any error may be retried with the same block index.
 */
-pub async fn notify_top_up(block_index: BlockIndex) -> Cycles {
+pub async fn notify_top_up(block_index: BlockIndex) -> Result<Cycles, NotifyError> {
     let arg = NotifyTopUpArg {
         block_index,
         canister_id: ic_cdk::api::id(),
     };
 
     let notify_top_ip_result = call::<(NotifyTopUpArg,), (NotifyTopUpResult,)>(
-        MAINNET_CYCLES_MINTING_CANISTER_ID,
+        ConfigStore::cmc_canister_id(),
         "notify_top_up",
         (arg,),
     )
     .await
-    .expect("Failed to call notify_top_up")
+    .map_err(|(code, msg)| NotifyError::Other {
+        error_message: format!("Failed to call notify_top_up: {}", msg),
+        error_code: code as u64,
+    })?
     .0;
 
     match notify_top_ip_result {
-        NotifyTopUpResult::Ok(cycles) => cycles,
-        NotifyTopUpResult::Err(err) => {
-            ic_cdk::trap(&format!("Failed to notify_top_up: {:?}", err));
-        }
+        NotifyTopUpResult::Ok(cycles) => Ok(cycles),
+        NotifyTopUpResult::Err(err) => Err(err),
     }
 }
 
@@ -55,3 +58,15 @@ pub enum NotifyError {
     Processing,
     TransactionTooOld(BlockIndex),
 }
+
+impl NotifyError {
+    // The CMC rejected the transfer for good, notifying it again cannot mint cycles
+    pub fn is_retryable(&self) -> bool {
+        !matches!(
+            self,
+            NotifyError::Refunded { .. }
+                | NotifyError::InvalidTransaction(_)
+                | NotifyError::TransactionTooOld(_)
+        )
+    }
+}
@@ -1,7 +1,10 @@
 use candid::Principal;
 use ic_cdk::{
     api::management_canister::{
-        main::{canister_status as ic_canister_status, update_settings, CanisterStatusResponse, UpdateSettingsArgument},
+        main::{
+            canister_status as ic_canister_status, raw_rand, update_settings,
+            CanisterStatusResponse, UpdateSettingsArgument,
+        },
         provisional::{CanisterIdRecord, CanisterSettings},
     },
     trap,
@@ -39,3 +42,10 @@ pub async fn add_controller(p: Principal) {
         .await
         .unwrap_or_else(|err| trap(&format!("{:?}, {:?}", err.0, err.1)));
 }
+
+pub async fn random_bytes() -> Vec<u8> {
+    raw_rand()
+        .await
+        .unwrap_or_else(|err| trap(&format!("{:?}, {:?}", err.0, err.1)))
+        .0
+}
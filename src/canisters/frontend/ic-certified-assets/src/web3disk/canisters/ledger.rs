@@ -1,11 +1,11 @@
-use candid::{CandidType, Deserialize, Principal};
+use crate::web3disk::stores::config::ConfigStore;
+use candid::{CandidType, Deserialize};
 use ic_cdk::api::time;
 use ic_cdk::id;
 use ic_ledger_types::transfer;
 use ic_ledger_types::{
     account_balance, AccountBalanceArgs, AccountIdentifier, BlockIndex, Memo, Subaccount,
-    Timestamp, Tokens, TransferArgs, DEFAULT_SUBACCOUNT, MAINNET_CYCLES_MINTING_CANISTER_ID,
-    MAINNET_LEDGER_CANISTER_ID,
+    Timestamp, Tokens, TransferArgs, DEFAULT_SUBACCOUNT,
 };
 
 pub static MEMO_TOP_UP_CANISTER: Memo = Memo(1347768404_u64);
@@ -40,7 +40,7 @@ pub async fn icp_balance() -> Tokens {
         account: AccountIdentifier::new(&id(), &DEFAULT_SUBACCOUNT),
     };
 
-    let balance = account_balance(MAINNET_LEDGER_CANISTER_ID, arg)
+    let balance = account_balance(ConfigStore::ledger_canister_id(), arg)
         .await
         .expect("Failed to query ledger canister for balance");
 
@@ -50,21 +50,19 @@ pub async fn icp_balance() -> Tokens {
 /*
 Transfer ICP to CMC canister for cycle minting
 */
-pub async fn transfer_icp_to_cmc_for_cycles_minting() -> BlockIndex {
-    let amount = icp_balance().await - ICP_TRANSACTION_FEE;
-
+pub async fn transfer_icp_to_cmc_for_cycles_minting(amount: Tokens) -> BlockIndex {
     let arg = TransferArgs {
         memo: MEMO_TOP_UP_CANISTER,
         amount,
         fee: ICP_TRANSACTION_FEE,
         from_subaccount: None,
-        to: AccountIdentifier::new(&MAINNET_CYCLES_MINTING_CANISTER_ID, &Subaccount::from(id())),
+        to: AccountIdentifier::new(&ConfigStore::cmc_canister_id(), &Subaccount::from(id())),
         created_at_time: Some(Timestamp {
             timestamp_nanos: time(),
         }),
     };
 
-    transfer(MAINNET_LEDGER_CANISTER_ID, arg)
+    transfer(ConfigStore::ledger_canister_id(), arg)
         .await
         .expect("Failed to transfer ICP to CMC")
         .unwrap_or_else(|err| {
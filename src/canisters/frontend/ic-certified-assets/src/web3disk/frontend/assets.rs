@@ -71,6 +71,8 @@ pub fn init_frontend_assets() {
         max_age: None,
         is_aliased: None,
         allow_raw_access: None,
+        redirect: None,
+        rewrite_from: None,
     };
 
     StateStore::set_asset_properties(arg).unwrap_or_else(|_| trap("set_asset_properties failed"));
@@ -1,17 +1,34 @@
 pub mod settings_page;
 
-use self::settings_page::{CanisterInfo, CanisterOwners, owners, settings_info};
-use super::canisters::ic::add_controller;
+use self::settings_page::{owners, settings_info, CanisterInfo, CanisterOwners};
+use super::canisters::ic::{add_controller, random_bytes};
 use super::canisters::ledger::DefaultAccountAndBalance;
-use super::stores::config::{handle_grant_ownership, ConfigStore, GrantOwnershipArgs, Status};
+use super::stores::audit::{AuditLog, AuditLogArgs, AuditLogPage, Operation};
+use super::stores::config::{
+    accept_transfer, cancel_transfer, handle_grant_ownership, propose_transfer, ConfigStore,
+    GrantOwnershipArgs, PendingTransfer, ProposeTransferArgs, Quota, Status, TopUpPolicy,
+};
+use super::stores::fs::{
+    api::FsStore,
+    listing::DirectoryListing,
+    search::{SearchArgs, SearchIndex, SearchPage},
+    trash::{Trash, TrashItem},
+    Key, Metadata,
+};
 use super::stores::heap::StateStore;
+use super::stores::members::{Invite, Member, MemberStore, Role};
+use super::stores::sharing::{CreateShareArgs, SetVisibilityArgs, Share, SharingStore, Visibility};
+use super::stores::top_ups::{PendingTopUp, TopUp, TopUpStore};
+use super::stores::uploads::{StartUploadArgs, UploadChunkArgs, UploadStatus, UploadStore};
 use super::W3D_VERSION;
 use crate::asset_certification::types::http::{
     CallbackFunc, HttpRequest, HttpResponse, StreamingCallbackHttpResponse, StreamingCallbackToken,
 };
-use crate::types::Permission;
-use candid::{candid_method, Principal};
-use ic_cdk::api::data_certificate;
+use crate::asset_certification::types::rc_bytes::RcBytes;
+use crate::state_machine::StorageUsage;
+use crate::types::{BatchId, Permission, StoreArg};
+use candid::{candid_method, CandidType, Deserialize, Principal};
+use ic_cdk::api::{data_certificate, set_certified_data, time};
 use ic_cdk::{caller, query, trap, update};
 
 #[update(guard = "can_commit")]
@@ -20,10 +37,11 @@ async fn w3d_default_account_and_balance() -> DefaultAccountAndBalance {
     crate::web3disk::canisters::ledger::get_default_account_and_balance().await
 }
 
-#[update(guard = "can_commit")]
+#[update(guard = "can_manage_permissions")]
 #[candid_method(update)]
 async fn w3d_top_up() {
     crate::web3disk::interface::settings_page::top_up().await;
+    audit(Operation::TopUp, None);
 }
 
 #[update(guard = "is_controller")]
@@ -33,11 +51,50 @@ async fn w3d_grant_ownership(arg: GrantOwnershipArgs) {
         trap("Already initialized")
     }
 
+    let ii_principal = arg.ii_principal;
     handle_grant_ownership(arg).await;
+    audit(Operation::GrantOwnership, Some(ii_principal.to_text()));
+}
+
+#[update(guard = "can_manage_permissions")]
+#[candid_method(update)]
+fn w3d_propose_transfer(args: ProposeTransferArgs) -> PendingTransfer {
+    let transfer = propose_transfer(caller(), args, time()).unwrap_or_else(|msg| trap(&msg));
+    audit(Operation::ProposeTransfer, Some(transfer.to.to_text()));
+
+    transfer
+}
+
+#[update(guard = "can_manage_permissions")]
+#[candid_method(update)]
+fn w3d_cancel_transfer() {
+    cancel_transfer().unwrap_or_else(|msg| trap(&msg));
+    audit(Operation::CancelTransfer, None);
+}
+
+// Readable by the proposed owner, who is not a member yet
+#[query]
+#[candid_method(query)]
+fn w3d_pending_transfer() -> Option<PendingTransfer> {
+    if caller() == Principal::anonymous() {
+        trap("Anonymous principal not allowed")
+    }
+
+    ConfigStore::pending_transfer()
+}
+
+// Returns the error instead of trapping so a rollback after a failed controller update is kept
+#[update]
+#[candid_method(update)]
+async fn w3d_accept_transfer() -> Result<(), String> {
+    accept_transfer(caller(), time()).await?;
+    audit(Operation::AcceptTransfer, Some(caller().to_text()));
+
+    Ok(())
 }
 
 // LOGIN
-#[query(guard = "can_commit")]
+#[query(guard = "can_view")]
 #[candid_method(query)]
 fn w3d_status() -> Status {
     if caller() == Principal::anonymous() {
@@ -60,19 +117,118 @@ async fn w3d_active() -> bool {
     }
 }
 
-#[query(guard = "can_commit")]
+#[query(guard = "can_view")]
 #[candid_method(query)]
 fn w3d_api_version() -> String {
     W3D_VERSION.to_string()
 }
 
-#[update(guard = "can_commit")]
+#[update(guard = "can_view")]
 #[candid_method(update)]
 pub async fn w3d_settings_info() -> CanisterInfo {
     settings_info().await
 }
 
-#[update(guard = "can_commit")]
+#[query(guard = "can_view")]
+#[candid_method(query)]
+fn w3d_top_up_policy() -> TopUpPolicy {
+    ConfigStore::top_up_policy()
+}
+
+#[update(guard = "can_manage_permissions")]
+#[candid_method(update)]
+fn w3d_set_top_up_policy(policy: TopUpPolicy) {
+    ConfigStore::set_top_up_policy(policy);
+    audit(Operation::SetTopUpPolicy, None);
+}
+
+#[query(guard = "can_view")]
+#[candid_method(query)]
+fn w3d_top_up_history() -> Vec<TopUp> {
+    TopUpStore::history()
+}
+
+#[query(guard = "can_view")]
+#[candid_method(query)]
+fn w3d_pending_top_ups() -> Vec<PendingTopUp> {
+    TopUpStore::pending()
+}
+
+#[query(guard = "can_view")]
+#[candid_method(query)]
+fn w3d_quota() -> Quota {
+    ConfigStore::quota()
+}
+
+#[update(guard = "can_manage_permissions")]
+#[candid_method(update)]
+fn w3d_set_quota(quota: Quota) {
+    ConfigStore::set_quota(quota);
+    audit(Operation::SetQuota, None);
+}
+
+// Logical bytes count identical files each time, physical bytes count their content once
+#[query(guard = "can_view")]
+#[candid_method(query)]
+fn w3d_storage_usage() -> StorageUsage {
+    FsStore::storage_usage()
+}
+
+#[query(guard = "can_view")]
+#[candid_method(query)]
+fn w3d_directory_listing() -> bool {
+    ConfigStore::directory_listing()
+}
+
+#[query(guard = "can_view")]
+#[candid_method(query)]
+fn w3d_trash_retention_days() -> u64 {
+    ConfigStore::trash_retention_days()
+}
+
+#[update(guard = "can_manage_permissions")]
+#[candid_method(update)]
+fn w3d_set_trash_retention_days(days: u64) {
+    ConfigStore::set_trash_retention_days(days);
+    audit(Operation::SetTrashRetention, Some(days.to_string()));
+}
+
+#[query(guard = "can_view")]
+#[candid_method(query)]
+fn w3d_thumbnail_max_source_bytes() -> u64 {
+    ConfigStore::thumbnail_max_source_bytes()
+}
+
+#[update(guard = "can_manage_permissions")]
+#[candid_method(update)]
+fn w3d_set_thumbnail_max_source_bytes(bytes: u64) {
+    ConfigStore::set_thumbnail_max_source_bytes(bytes);
+    audit(Operation::SetThumbnailLimit, Some(bytes.to_string()));
+}
+
+#[query(guard = "can_view")]
+#[candid_method(query)]
+fn w3d_max_upload_bytes() -> u64 {
+    ConfigStore::max_upload_bytes()
+}
+
+#[update(guard = "can_manage_permissions")]
+#[candid_method(update)]
+fn w3d_set_max_upload_bytes(bytes: u64) {
+    ConfigStore::set_max_upload_bytes(bytes);
+    audit(Operation::SetUploadLimit, Some(bytes.to_string()));
+}
+
+#[update(guard = "can_manage_permissions")]
+#[candid_method(update)]
+fn w3d_set_directory_listing(enabled: bool) {
+    ConfigStore::set_directory_listing(enabled);
+    DirectoryListing::refresh_all();
+    set_certified_data(&StateStore::root_hash());
+    audit(Operation::SetDirectoryListing, Some(enabled.to_string()));
+}
+
+#[update(guard = "can_view")]
 #[candid_method(update)]
 async fn w3d_owners() -> CanisterOwners {
     owners().await
@@ -82,17 +238,263 @@ async fn w3d_owners() -> CanisterOwners {
 #[candid_method(update)]
 async fn w3d_add_controller(p: Principal) {
     add_controller(p).await;
+    audit(Operation::AddController, Some(p.to_text()));
 }
 
-#[query(guard = "can_commit")]
+#[query(guard = "can_view")]
 #[candid_method(query)]
 fn w3d_ii_principal() -> Principal {
     ConfigStore::ii_principal().unwrap_or_else(|| trap("No II principal set"))
 }
 
+#[update(guard = "can_commit")]
+#[candid_method(update)]
+fn w3d_store(arg: StoreArg) {
+    let key = arg.key.clone();
+    FsStore::store(arg, time()).unwrap_or_else(|msg| trap(&msg));
+    set_certified_data(&StateStore::root_hash());
+    audit(Operation::Store, Some(key));
+}
+
+#[update(guard = "can_commit")]
+#[candid_method(update)]
+fn w3d_create_folder(key: String) -> Metadata {
+    let metadata = FsStore::create_folder(&Key::new(&key), time()).unwrap_or_else(|msg| trap(&msg));
+    set_certified_data(&StateStore::root_hash());
+    audit(Operation::CreateFolder, Some(key));
+
+    metadata
+}
+
+#[query(guard = "can_view")]
+#[candid_method(query)]
+fn w3d_list(key: String) -> Vec<Metadata> {
+    FsStore::list(&Key::new(&key)).unwrap_or_else(|msg| trap(&msg))
+}
+
+#[query(guard = "can_view")]
+#[candid_method(query)]
+fn w3d_stat(key: String) -> Metadata {
+    FsStore::stat(&Key::new(&key)).unwrap_or_else(|msg| trap(&msg))
+}
+
+#[query(guard = "can_view")]
+#[candid_method(query)]
+fn w3d_search(args: SearchArgs) -> SearchPage {
+    SearchIndex::search(args).unwrap_or_else(|msg| trap(&msg))
+}
+
+// Thumbnails of public files are also served next to them, see `Thumbnail::key`
+#[query(guard = "can_view")]
+#[candid_method(query)]
+fn w3d_thumbnail(key: String, size: u32) -> RcBytes {
+    FsStore::read_thumbnail(&Key::new(&key), size).unwrap_or_else(|msg| trap(&msg))
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct MoveArgs {
+    from: String,
+    to: String,
+}
+
+#[update(guard = "can_commit")]
+#[candid_method(update)]
+fn w3d_move(arg: MoveArgs) {
+    FsStore::rename(&Key::new(&arg.from), &Key::new(&arg.to), time())
+        .unwrap_or_else(|msg| trap(&msg));
+    set_certified_data(&StateStore::root_hash());
+    audit(Operation::Move, Some(format!("{} -> {}", arg.from, arg.to)));
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CopyArgs {
+    from: String,
+    to: String,
+}
+
+#[update(guard = "can_commit")]
+#[candid_method(update)]
+fn w3d_copy(arg: CopyArgs) {
+    FsStore::copy(&Key::new(&arg.from), &Key::new(&arg.to), time())
+        .unwrap_or_else(|msg| trap(&msg));
+    set_certified_data(&StateStore::root_hash());
+    audit(Operation::Copy, Some(format!("{} -> {}", arg.from, arg.to)));
+}
+
+#[update(guard = "can_commit")]
+#[candid_method(update)]
+fn w3d_delete(key: String) {
+    FsStore::delete(&Key::new(&key), time()).unwrap_or_else(|msg| trap(&msg));
+    set_certified_data(&StateStore::root_hash());
+    audit(Operation::Delete, Some(key));
+}
+
+#[query(guard = "can_view")]
+#[candid_method(query)]
+fn w3d_list_trash() -> Vec<TrashItem> {
+    Trash::list()
+}
+
+#[update(guard = "can_commit")]
+#[candid_method(update)]
+fn w3d_restore_from_trash(id: u64) {
+    let item = Trash::restore(id, time()).unwrap_or_else(|msg| trap(&msg));
+    set_certified_data(&StateStore::root_hash());
+    audit(Operation::RestoreFromTrash, Some(item.key.to_string()));
+}
+
+#[update(guard = "can_commit")]
+#[candid_method(update)]
+fn w3d_purge_from_trash(id: u64) {
+    let item = Trash::purge(id).unwrap_or_else(|msg| trap(&msg));
+    audit(Operation::PurgeFromTrash, Some(item.key.to_string()));
+}
+
+#[update(guard = "can_commit")]
+#[candid_method(update)]
+fn w3d_empty_trash() {
+    Trash::empty().unwrap_or_else(|msg| trap(&msg));
+    audit(Operation::EmptyTrash, None);
+}
+
+#[update(guard = "can_commit")]
+#[candid_method(update)]
+fn w3d_start_upload(arg: StartUploadArgs) -> BatchId {
+    UploadStore::start(caller(), arg, time()).unwrap_or_else(|msg| trap(&msg))
+}
+
+#[update(guard = "can_commit")]
+#[candid_method(update)]
+fn w3d_upload_chunk(arg: UploadChunkArgs) {
+    UploadStore::upload_chunk(caller(), arg, time()).unwrap_or_else(|msg| trap(&msg));
+}
+
+#[query(guard = "can_commit")]
+#[candid_method(query)]
+fn w3d_upload_status(session_id: BatchId) -> UploadStatus {
+    UploadStore::status(caller(), &session_id, time()).unwrap_or_else(|msg| trap(&msg))
+}
+
+#[update(guard = "can_commit")]
+#[candid_method(update)]
+fn w3d_commit_upload(session_id: BatchId) {
+    let key = UploadStore::commit(caller(), &session_id, time()).unwrap_or_else(|msg| trap(&msg));
+    set_certified_data(&StateStore::root_hash());
+    audit(Operation::CommitUpload, Some(key));
+}
+
+#[update(guard = "can_commit")]
+#[candid_method(update)]
+fn w3d_cancel_upload(session_id: BatchId) {
+    UploadStore::cancel(caller(), &session_id).unwrap_or_else(|msg| trap(&msg));
+}
+
+#[update(guard = "can_commit")]
+#[candid_method(update)]
+fn w3d_set_visibility(arg: SetVisibilityArgs) {
+    let target = format!("{} {:?}", arg.key, arg.visibility);
+    SharingStore::set_visibility(arg).unwrap_or_else(|msg| trap(&msg));
+    set_certified_data(&StateStore::root_hash());
+    audit(Operation::SetVisibility, Some(target));
+}
+
+#[query(guard = "can_view")]
+#[candid_method(query)]
+fn w3d_visibility(key: String) -> Visibility {
+    SharingStore::visibility(&Key::new(&key))
+}
+
+#[update(guard = "can_commit")]
+#[candid_method(update)]
+async fn w3d_create_share(arg: CreateShareArgs) -> Share {
+    let token = hex::encode(random_bytes().await);
+
+    let share = SharingStore::create_share(arg, token, time()).unwrap_or_else(|msg| trap(&msg));
+    audit(Operation::CreateShare, Some(share.key.to_string()));
+
+    share
+}
+
+#[query(guard = "can_commit")]
+#[candid_method(query)]
+fn w3d_list_shares() -> Vec<Share> {
+    SharingStore::list_shares(time())
+}
+
+#[update(guard = "can_commit")]
+#[candid_method(update)]
+fn w3d_revoke_share(token: String) {
+    SharingStore::revoke_share(token).unwrap_or_else(|msg| trap(&msg));
+    audit(Operation::RevokeShare, None);
+}
+
+#[update(guard = "can_manage_permissions")]
+#[candid_method(update)]
+async fn w3d_invite_member(role: Role) -> Invite {
+    let code = hex::encode(random_bytes().await);
+
+    let invite = MemberStore::invite(caller(), role, code, time());
+    audit(Operation::InviteMember, Some(format!("{:?}", role)));
+
+    invite
+}
+
+#[query(guard = "can_manage_permissions")]
+#[candid_method(query)]
+fn w3d_list_invites() -> Vec<Invite> {
+    MemberStore::invites(time())
+}
+
+#[update(guard = "can_manage_permissions")]
+#[candid_method(update)]
+fn w3d_revoke_invite(code: String) {
+    MemberStore::revoke_invite(code).unwrap_or_else(|msg| trap(&msg));
+    audit(Operation::RevokeInvite, None);
+}
+
+// Open to any signed in principal holding an invite code
+#[update]
+#[candid_method(update)]
+fn w3d_accept_invite(code: String) -> Member {
+    let member = MemberStore::accept(caller(), code, time()).unwrap_or_else(|msg| trap(&msg));
+    audit(Operation::AcceptInvite, Some(format!("{:?}", member.role)));
+
+    member
+}
+
+#[update(guard = "can_manage_permissions")]
+#[candid_method(update)]
+fn w3d_remove_member(principal: Principal) {
+    MemberStore::remove(principal).unwrap_or_else(|msg| trap(&msg));
+    audit(Operation::RemoveMember, Some(principal.to_text()));
+}
+
+#[query(guard = "can_view")]
+#[candid_method(query)]
+fn w3d_members() -> Vec<Member> {
+    MemberStore::members()
+}
+
+#[query(guard = "can_commit")]
+#[candid_method(query)]
+fn w3d_audit_log(args: AuditLogArgs) -> AuditLogPage {
+    AuditLog::page(args)
+}
+
 #[query]
 #[candid_method(query)]
 fn http_request(req: HttpRequest) -> HttpResponse {
+    // Share links count downloads, which needs an update call
+    if SharingStore::is_share_request(&req) {
+        return HttpResponse {
+            status_code: 200,
+            headers: vec![],
+            body: RcBytes::default(),
+            upgrade: Some(true),
+            streaming_strategy: None,
+        };
+    }
+
     let certificate = data_certificate().unwrap_or_else(|| trap("no data certificate available"));
 
     StateStore::http_request(
@@ -102,16 +504,43 @@ fn http_request(req: HttpRequest) -> HttpResponse {
     )
 }
 
+#[update]
+#[candid_method(update)]
+fn http_request_update(req: HttpRequest) -> HttpResponse {
+    SharingStore::http_request(
+        req,
+        CallbackFunc::new(ic_cdk::id(), "http_request_streaming_callback".to_string()),
+        time(),
+    )
+}
+
 #[query]
 #[candid_method(query)]
 fn http_request_streaming_callback(token: StreamingCallbackToken) -> StreamingCallbackHttpResponse {
+    if SharingStore::is_share_token(&token) {
+        return SharingStore::streaming_callback(token, time()).unwrap_or_else(|msg| trap(&msg));
+    }
+
     StateStore::http_request_streaming_callback(token)
 }
 
+fn audit(operation: Operation, target: Option<String>) {
+    AuditLog::record(caller(), time(), operation, target);
+}
+
 fn can_commit() -> Result<(), String> {
     StateStore::can(Permission::Commit)
 }
 
+// Commit implies Prepare, so editors and owners can view as well
+fn can_view() -> Result<(), String> {
+    StateStore::can(Permission::Prepare)
+}
+
+fn can_manage_permissions() -> Result<(), String> {
+    StateStore::can(Permission::ManagePermissions)
+}
+
 fn is_controller() -> Result<(), String> {
     let caller = caller();
     if ic_cdk::api::is_controller(&caller) {
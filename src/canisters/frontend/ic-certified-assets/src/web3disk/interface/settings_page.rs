@@ -1,17 +1,61 @@
 use crate::web3disk::{
-    canisters::{cmc::notify_top_up, ledger::transfer_icp_to_cmc_for_cycles_minting},
-    stores::config::ConfigStore,
+    canisters::{
+        cmc::notify_top_up,
+        ledger::{icp_balance, transfer_icp_to_cmc_for_cycles_minting, ICP_TRANSACTION_FEE},
+    },
+    stores::{
+        audit::{AuditLog, Operation},
+        config::ConfigStore,
+        fs::api::FsStore,
+        members::{Member, MemberStore},
+        top_ups::{PendingTopUp, TopUpStore},
+    },
 };
 use candid::{CandidType, Deserialize, Principal};
 use ic_cdk::{
-    api::management_canister::{
-        main::{canister_status, CanisterStatusResponse},
-        provisional::CanisterIdRecord,
+    api::{
+        canister_balance128,
+        management_canister::{
+            main::{canister_status, CanisterStatusResponse},
+            provisional::CanisterIdRecord,
+        },
+        time,
     },
     trap,
 };
+use ic_ledger_types::{BlockIndex, Tokens};
 use num_bigint::BigUint;
 use num_traits::ToPrimitive;
+use std::{cell::Cell, time::Duration};
+
+// How often the auto top up policy checks the cycles balance and pending top ups are retried
+const AUTO_TOP_UP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// A top up that could not be notified for a day is recorded as failed
+const MAX_NOTIFY_ATTEMPTS: u32 = 24;
+
+thread_local! {
+    // Prevents a second auto top up while the previous one is still waiting on the ledger
+    static AUTO_TOP_UP_RUNNING: Cell<bool> = Cell::new(false);
+}
+
+/*
+Holds `AUTO_TOP_UP_RUNNING` for one auto top up. The flag is reset on drop, which also
+happens when a call traps: the cdk drops the future in the cleanup callback.
+*/
+struct AutoTopUpGuard;
+
+impl AutoTopUpGuard {
+    fn acquire() -> Option<Self> {
+        (!AUTO_TOP_UP_RUNNING.with(|running| running.replace(true))).then_some(AutoTopUpGuard)
+    }
+}
+
+impl Drop for AutoTopUpGuard {
+    fn drop(&mut self) {
+        AUTO_TOP_UP_RUNNING.with(|running| running.set(false));
+    }
+}
 
 #[derive(Clone, CandidType)]
 pub struct CanisterInfo {
@@ -19,6 +63,14 @@ pub struct CanisterInfo {
     pub cycles: f64,
     // memory size in MB
     pub memory: f64,
+    // bytes used by user files
+    pub used_bytes: u64,
+    // bytes actually stored, files with identical content are stored once
+    pub stored_bytes: u64,
+    pub max_bytes: Option<u64>,
+    pub available_bytes: Option<u64>,
+    // estimated from the observed cycles burn rate
+    pub days_until_freeze: Option<f64>,
 }
 
 impl From<CanisterStatusResponse> for CanisterInfo {
@@ -53,7 +105,18 @@ impl From<CanisterStatusResponse> for CanisterInfo {
         // Divide by 10^3 to get MB with three decimals
         let memory: f64 = memory / 1000 as f64;
 
-        Self { cycles, memory }
+        let used_bytes = FsStore::used_bytes();
+        let max_bytes = ConfigStore::quota().max_bytes;
+
+        Self {
+            cycles,
+            memory,
+            used_bytes,
+            stored_bytes: FsStore::stored_bytes(),
+            max_bytes,
+            available_bytes: max_bytes.map(|max_bytes| max_bytes.saturating_sub(used_bytes)),
+            days_until_freeze: None,
+        }
     }
 }
 
@@ -67,12 +130,134 @@ pub async fn settings_info() -> CanisterInfo {
         .unwrap_or_else(|err| trap(&format!("{:?}", err)))
         .0;
 
-    CanisterInfo::from(canister_status_response)
+    let days_until_freeze = days_until_freeze(&canister_status_response);
+
+    CanisterInfo {
+        days_until_freeze,
+        ..CanisterInfo::from(canister_status_response)
+    }
+}
+
+/*
+The canister freezes once its balance drops below what it burns idle during the freezing threshold.
+Without an observed burn rate yet, the idle burn rate reported by the IC is used.
+*/
+fn days_until_freeze(canister_status: &CanisterStatusResponse) -> Option<f64> {
+    let cycles = canister_status.cycles.0.to_u128()?;
+    let idle_burn_per_day = canister_status.idle_cycles_burned_per_day.0.to_u128()?;
+    let freezing_threshold = canister_status.settings.freezing_threshold.0.to_u128()?;
+
+    let burn_per_day = ConfigStore::record_cycles(cycles, ic_cdk::api::time())
+        .filter(|burn_rate| *burn_rate > 0)
+        .unwrap_or(idle_burn_per_day);
+
+    if burn_per_day == 0 {
+        return None;
+    }
+
+    let freeze_reserve = idle_burn_per_day * freezing_threshold / (24 * 60 * 60);
+
+    Some(cycles.saturating_sub(freeze_reserve) as f64 / burn_per_day as f64)
 }
 
+/*
+Convert the whole ICP balance to cycles. The outcome is recorded in the top up history,
+a notification that fails with a retryable error is retried from the hourly timer.
+*/
 pub async fn top_up() {
-    let index = transfer_icp_to_cmc_for_cycles_minting().await;
-    notify_top_up(index).await;
+    let balance = icp_balance().await;
+    if balance <= ICP_TRANSACTION_FEE {
+        trap("ICP balance too low to top up");
+    }
+
+    let amount = balance - ICP_TRANSACTION_FEE;
+    let block_index = transfer_icp_to_cmc_for_cycles_minting(amount).await;
+
+    notify_pending(add_pending(block_index, amount, false)).await;
+}
+
+/*
+Retry pending top ups and check the auto top up policy on an interval.
+Timers do not survive upgrades, so this is called from both `init` and `post_upgrade`.
+Pending top ups are kept in stable memory and picked up again after an upgrade.
+*/
+pub fn start_auto_top_up_timer() {
+    ic_cdk_timers::set_timer_interval(AUTO_TOP_UP_INTERVAL, || {
+        ic_cdk::spawn(retry_pending_top_ups());
+        ic_cdk::spawn(auto_top_up());
+    });
+}
+
+async fn retry_pending_top_ups() {
+    for pending in TopUpStore::pending() {
+        notify_pending(pending).await;
+    }
+}
+
+async fn auto_top_up() {
+    let policy = ConfigStore::top_up_policy();
+    if !policy.enabled || canister_balance128() >= policy.trigger_threshold {
+        return;
+    }
+
+    let _guard = match AutoTopUpGuard::acquire() {
+        Some(guard) => guard,
+        None => return,
+    };
+
+    let balance = icp_balance().await;
+    let spendable = Tokens::from_e8s(balance.e8s().saturating_sub(ICP_TRANSACTION_FEE.e8s()));
+    let amount = Tokens::from_e8s(spendable.e8s().min(policy.max_icp_e8s));
+
+    if amount.e8s() > 0 {
+        let block_index = transfer_icp_to_cmc_for_cycles_minting(amount).await;
+
+        AuditLog::record(
+            ic_cdk::api::id(),
+            time(),
+            Operation::AutoTopUp,
+            Some(block_index.to_string()),
+        );
+
+        notify_pending(add_pending(block_index, amount, true)).await;
+    }
+}
+
+// Persisted before the CMC is notified, the ICP is already spent
+fn add_pending(block_index: BlockIndex, amount: Tokens, automatic: bool) -> PendingTopUp {
+    let pending = PendingTopUp {
+        block_index,
+        icp_e8s: amount.e8s(),
+        time: time(),
+        automatic,
+        attempts: 0,
+    };
+    TopUpStore::add_pending(pending.clone());
+
+    pending
+}
+
+/*
+Notify the CMC of a pending transfer and record the minted cycles. Retryable errors keep
+the top up pending until `MAX_NOTIFY_ATTEMPTS` is reached, other errors are recorded right away.
+*/
+async fn notify_pending(pending: PendingTopUp) {
+    let block_index = pending.block_index;
+
+    match notify_top_up(block_index).await {
+        Ok(cycles) => TopUpStore::complete(block_index, Ok(cycles), time()),
+        Err(err) if err.is_retryable() && pending.attempts + 1 < MAX_NOTIFY_ATTEMPTS => {
+            TopUpStore::record_attempt(block_index)
+        }
+        Err(err) => TopUpStore::complete(
+            block_index,
+            Err(format!(
+                "Failed to notify_top_up for block {}: {:?}",
+                block_index, err
+            )),
+            time(),
+        ),
+    }
 }
 
 #[derive(Clone, CandidType, Deserialize)]
@@ -80,6 +265,7 @@ pub struct CanisterOwners {
     pub web3disk: Principal,
     pub ii_principal: Principal,
     pub owners: Option<Vec<Principal>>,
+    pub members: Vec<Member>,
 }
 
 pub async fn owners() -> CanisterOwners {
@@ -107,5 +293,6 @@ pub async fn owners() -> CanisterOwners {
         web3disk,
         ii_principal,
         owners: Some(owners),
+        members: MemberStore::members(),
     }
 }
@@ -5,26 +5,55 @@ mod stores;
 
 use self::{
     frontend::assets::init_frontend_assets,
-    stores::{config::ConfigStore, heap::StateStore},
+    interface::settings_page::start_auto_top_up_timer,
+    stores::{
+        config::{ConfigStore, Web3DiskArgs},
+        fs::{api::FsStore, trash::start_trash_purge_timer},
+        heap::StateStore,
+        members::{MemberStore, Role},
+    },
 };
 use crate::types::Permission;
+use candid::candid_method;
+use ic_cdk::api::{set_certified_data, trap};
 
 pub const W3D_VERSION: &str = "0.0.2";
 
 #[ic_cdk::init]
-pub fn init() {
+#[candid_method(init)]
+pub fn init(args: Option<Web3DiskArgs>) {
     StateStore::clear();
+    setup(args);
+}
+
+// Shared by install and upgrade. The certified assets are kept across upgrades.
+fn setup(args: Option<Web3DiskArgs>) {
+    ConfigStore::apply_args(args.unwrap_or_default());
+
     StateStore::grant_permission(ic_cdk::caller(), &Permission::Commit);
 
     // Init frontend dir in `STATE` thread local storage
     init_frontend_assets();
+
+    start_auto_top_up_timer();
+    start_trash_purge_timer();
 }
 
 #[ic_cdk::post_upgrade]
-pub fn post_upgrade() {
-    init();
+pub fn post_upgrade(args: Option<Web3DiskArgs>) {
+    setup(args);
+
+    // Certify user files the http view does not know yet, and regenerate directory listings
+    FsStore::restore().unwrap_or_else(|msg| trap(&msg));
+    set_certified_data(&StateStore::root_hash());
 
-    if let Some(ii_principal) = ConfigStore::ii_principal() {
-        StateStore::grant_permission(ii_principal, &Permission::Commit);
+    // Disks set up before multi user support only know their ii_principal.
+    // Once there are members, the ii_principal may have been removed on purpose.
+    if MemberStore::is_empty() {
+        if let Some(ii_principal) = ConfigStore::ii_principal() {
+            MemberStore::add(ii_principal, Role::Owner, ic_cdk::api::time());
+        }
     }
+
+    MemberStore::restore_permissions();
 }
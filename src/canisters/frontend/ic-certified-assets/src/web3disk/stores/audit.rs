@@ -0,0 +1,261 @@
+use super::{MemoryManagerStore, MEM_ID_AUDIT_LOG};
+use candid::{CandidType, Decode, Deserialize, Encode, Principal};
+use ic_stable_structures::{
+    memory_manager::VirtualMemory, storable::Bound, BTreeMap as StableBTree, DefaultMemoryImpl,
+    Storable,
+};
+use std::{borrow::Cow, cell::RefCell};
+
+// Oldest entries are dropped once the log holds this many
+const MAX_AUDIT_ENTRIES: u64 = 10_000;
+
+const MAX_AUDIT_PAGE_SIZE: u32 = 100;
+
+// Targets are keys or principals, long keys are cut to keep entries bounded
+const MAX_TARGET_LENGTH: usize = 1024;
+
+thread_local! {
+    // Entries keyed by an increasing sequence number
+    static AUDIT_LOG: RefCell<StableBTree<u64, AuditEntry, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTree::init(MemoryManagerStore::get(MEM_ID_AUDIT_LOG))
+    );
+}
+
+#[derive(CandidType, Deserialize, Clone, Copy, Debug)]
+pub enum Operation {
+    GrantOwnership,
+    AddController,
+    TopUp,
+    AutoTopUp,
+    SetQuota,
+    SetTopUpPolicy,
+    InviteMember,
+    RevokeInvite,
+    AcceptInvite,
+    RemoveMember,
+    ProposeTransfer,
+    CancelTransfer,
+    AcceptTransfer,
+    Store,
+    CommitUpload,
+    CreateFolder,
+    Move,
+    Copy,
+    Delete,
+    SetVisibility,
+    CreateShare,
+    RevokeShare,
+    SetDirectoryListing,
+    RestoreFromTrash,
+    PurgeFromTrash,
+    EmptyTrash,
+    SetTrashRetention,
+    SetThumbnailLimit,
+    SetUploadLimit,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct AuditEntry {
+    pub id: u64,
+    pub caller: Principal,
+    pub time: u64,
+    pub operation: Operation,
+    pub target: Option<String>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct AuditLogArgs {
+    // Only return entries older than this id, the newest entries are returned when unset
+    pub before: Option<u64>,
+    pub limit: u32,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct AuditLogPage {
+    pub entries: Vec<AuditEntry>,
+    // Pass as `before` to fetch the next page
+    pub next: Option<u64>,
+}
+
+pub struct AuditLog;
+
+impl AuditLog {
+    pub fn record(caller: Principal, time: u64, operation: Operation, target: Option<String>) {
+        let target = target.map(|mut target| {
+            if target.len() > MAX_TARGET_LENGTH {
+                let mut end = MAX_TARGET_LENGTH;
+                while !target.is_char_boundary(end) {
+                    end -= 1;
+                }
+                target.truncate(end);
+            }
+            target
+        });
+
+        AUDIT_LOG.with(|tree| {
+            let mut tree = tree.borrow_mut();
+            let id = tree.last_key_value().map_or(0, |(id, _)| id + 1);
+
+            tree.insert(
+                id,
+                AuditEntry {
+                    id,
+                    caller,
+                    time,
+                    operation,
+                    target,
+                },
+            );
+
+            // Append-only, the oldest entries are the only ones ever removed
+            while tree.len() > MAX_AUDIT_ENTRIES {
+                match tree.first_key_value() {
+                    Some((oldest, _)) => tree.remove(&oldest),
+                    None => break,
+                };
+            }
+        });
+    }
+
+    /*
+    Newest entries first. Ids are contiguous since only the oldest entries are ever removed,
+    so a page is a plain id range.
+    */
+    pub fn page(args: AuditLogArgs) -> AuditLogPage {
+        let limit = args.limit.clamp(1, MAX_AUDIT_PAGE_SIZE) as u64;
+
+        AUDIT_LOG.with(|tree| {
+            let tree = tree.borrow();
+
+            let empty = AuditLogPage {
+                entries: vec![],
+                next: None,
+            };
+
+            let (first, last) = match (tree.first_key_value(), tree.last_key_value()) {
+                (Some((first, _)), Some((last, _))) => (first, last),
+                _ => return empty,
+            };
+
+            let end = args.before.unwrap_or(u64::MAX).min(last + 1);
+            if end <= first {
+                return empty;
+            }
+            let start = end.saturating_sub(limit).max(first);
+
+            let mut entries: Vec<AuditEntry> =
+                tree.range(start..end).map(|(_, entry)| entry).collect();
+            entries.reverse();
+
+            AuditLogPage {
+                entries,
+                next: (start > first).then_some(start),
+            }
+        })
+    }
+}
+
+impl Storable for AuditEntry {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1280,
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode AuditEntry"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(&bytes, Self).expect("Failed to decode AuditEntry")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(count: u64) {
+        for time in 0..count {
+            AuditLog::record(Principal::anonymous(), time, Operation::Store, None);
+        }
+    }
+
+    fn ids(page: &AuditLogPage) -> Vec<u64> {
+        page.entries.iter().map(|entry| entry.id).collect()
+    }
+
+    #[test]
+    fn pages_newest_entries_first() {
+        record(5);
+
+        let page = AuditLog::page(AuditLogArgs {
+            before: None,
+            limit: 2,
+        });
+        assert_eq!(ids(&page), vec![4, 3]);
+        assert_eq!(page.next, Some(3));
+
+        let page = AuditLog::page(AuditLogArgs {
+            before: page.next,
+            limit: 2,
+        });
+        assert_eq!(ids(&page), vec![2, 1]);
+
+        let page = AuditLog::page(AuditLogArgs {
+            before: page.next,
+            limit: 2,
+        });
+        assert_eq!(ids(&page), vec![0]);
+        assert_eq!(page.next, None);
+
+        let page = AuditLog::page(AuditLogArgs {
+            before: Some(0),
+            limit: 2,
+        });
+        assert!(page.entries.is_empty());
+    }
+
+    #[test]
+    fn clamps_the_page_size() {
+        record(MAX_AUDIT_PAGE_SIZE as u64 + 1);
+
+        let page = AuditLog::page(AuditLogArgs {
+            before: None,
+            limit: 0,
+        });
+        assert_eq!(ids(&page), vec![MAX_AUDIT_PAGE_SIZE as u64]);
+
+        let page = AuditLog::page(AuditLogArgs {
+            before: None,
+            limit: u32::MAX,
+        });
+        assert_eq!(page.entries.len(), MAX_AUDIT_PAGE_SIZE as usize);
+        assert_eq!(page.next, Some(1));
+    }
+
+    #[test]
+    fn drops_the_oldest_entries() {
+        record(MAX_AUDIT_ENTRIES + 2);
+
+        let page = AuditLog::page(AuditLogArgs {
+            before: Some(3),
+            limit: MAX_AUDIT_PAGE_SIZE,
+        });
+        assert_eq!(ids(&page), vec![2]);
+        assert_eq!(page.next, None);
+    }
+
+    #[test]
+    fn cuts_long_targets_at_a_char_boundary() {
+        let target = format!("/{}", "é".repeat(MAX_TARGET_LENGTH));
+        AuditLog::record(Principal::anonymous(), 0, Operation::Delete, Some(target));
+
+        let page = AuditLog::page(AuditLogArgs {
+            before: None,
+            limit: 1,
+        });
+        let target = page.entries[0].target.as_ref().unwrap();
+        assert_eq!(target.len(), MAX_TARGET_LENGTH - 1);
+        assert!(target.starts_with("/é"));
+    }
+}
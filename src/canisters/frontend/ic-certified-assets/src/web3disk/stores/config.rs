@@ -1,10 +1,7 @@
-use super::heap::StateStore;
-use crate::{
-    types::Permission,
-    web3disk::{
-        canisters::ic::canister_status,
-        stores::{MemoryManagerStore, MEM_ID_CONFIG},
-    },
+use super::members::{Member, MemberStore, Role};
+use crate::web3disk::{
+    canisters::ic::canister_status,
+    stores::{MemoryManagerStore, MEM_ID_CONFIG},
 };
 use candid::{CandidType, Decode, Deserialize, Encode, Principal};
 use ic_cdk::{
@@ -14,6 +11,7 @@ use ic_cdk::{
     },
     trap,
 };
+use ic_ledger_types::{MAINNET_CYCLES_MINTING_CANISTER_ID, MAINNET_LEDGER_CANISTER_ID};
 use ic_stable_structures::{
     cell::Cell as StableCell, memory_manager::VirtualMemory, storable::Bound, DefaultMemoryImpl,
     Storable,
@@ -63,12 +61,316 @@ impl ConfigStore {
     pub fn is_active() -> bool {
         CONFIG.with(|refcell| refcell.borrow().get().is_active())
     }
+
+    pub fn pending_transfer() -> Option<PendingTransfer> {
+        CONFIG.with(|refcell| refcell.borrow().get().pending_transfer)
+    }
+
+    fn set_pending_transfer(pending_transfer: Option<PendingTransfer>) {
+        CONFIG.with(|refcell| {
+            let mut refcell = refcell.borrow_mut();
+            let mut config = refcell.get().clone();
+
+            config.pending_transfer = pending_transfer;
+            refcell.set(config).expect("Failed to set pending transfer");
+        });
+    }
+
+    // Restores owner and status as a whole, used to roll back a failed transfer
+    fn set_ownership(ii_principal: Option<Principal>, status: Status) {
+        CONFIG.with(|refcell| {
+            let mut refcell = refcell.borrow_mut();
+            let mut config = refcell.get().clone();
+
+            config.ii_principal = ii_principal;
+            config.status = status;
+            refcell.set(config).expect("Failed to set ownership");
+        });
+    }
+
+    pub fn quota() -> Quota {
+        CONFIG.with(|refcell| refcell.borrow().get().quota.unwrap_or_default())
+    }
+
+    pub fn set_quota(quota: Quota) {
+        CONFIG.with(|refcell| {
+            let mut refcell = refcell.borrow_mut();
+            let mut config = refcell.get().clone();
+
+            config.quota = Some(quota);
+            refcell.set(config).expect("Failed to set quota");
+        });
+    }
+
+    pub fn top_up_policy() -> TopUpPolicy {
+        CONFIG.with(|refcell| refcell.borrow().get().top_up_policy.unwrap_or_default())
+    }
+
+    pub fn set_top_up_policy(policy: TopUpPolicy) {
+        CONFIG.with(|refcell| {
+            let mut refcell = refcell.borrow_mut();
+            let mut config = refcell.get().clone();
+
+            config.top_up_policy = Some(policy);
+            refcell.set(config).expect("Failed to set top up policy");
+        });
+    }
+
+    pub fn directory_listing() -> bool {
+        CONFIG.with(|refcell| refcell.borrow().get().directory_listing.unwrap_or_default())
+    }
+
+    pub fn set_directory_listing(enabled: bool) {
+        CONFIG.with(|refcell| {
+            let mut refcell = refcell.borrow_mut();
+            let mut config = refcell.get().clone();
+
+            config.directory_listing = Some(enabled);
+            refcell
+                .set(config)
+                .expect("Failed to set directory listing");
+        });
+    }
+
+    pub fn trash_retention_days() -> u64 {
+        CONFIG.with(|refcell| {
+            refcell
+                .borrow()
+                .get()
+                .trash_retention_days
+                .unwrap_or(DEFAULT_TRASH_RETENTION_DAYS)
+        })
+    }
+
+    pub fn set_trash_retention_days(days: u64) {
+        CONFIG.with(|refcell| {
+            let mut refcell = refcell.borrow_mut();
+            let mut config = refcell.get().clone();
+
+            config.trash_retention_days = Some(days);
+            refcell.set(config).expect("Failed to set trash retention");
+        });
+    }
+
+    pub fn thumbnail_max_source_bytes() -> u64 {
+        CONFIG.with(|refcell| {
+            refcell
+                .borrow()
+                .get()
+                .thumbnail_max_source_bytes
+                .unwrap_or(DEFAULT_THUMBNAIL_MAX_SOURCE_BYTES)
+        })
+    }
+
+    pub fn set_thumbnail_max_source_bytes(bytes: u64) {
+        CONFIG.with(|refcell| {
+            let mut refcell = refcell.borrow_mut();
+            let mut config = refcell.get().clone();
+
+            config.thumbnail_max_source_bytes = Some(bytes);
+            refcell.set(config).expect("Failed to set thumbnail limit");
+        });
+    }
+
+    pub fn max_upload_bytes() -> u64 {
+        CONFIG.with(|refcell| {
+            refcell
+                .borrow()
+                .get()
+                .max_upload_bytes
+                .unwrap_or(DEFAULT_MAX_UPLOAD_BYTES)
+        })
+    }
+
+    pub fn set_max_upload_bytes(bytes: u64) {
+        CONFIG.with(|refcell| {
+            let mut refcell = refcell.borrow_mut();
+            let mut config = refcell.get().clone();
+
+            config.max_upload_bytes = Some(bytes);
+            refcell.set(config).expect("Failed to set upload limit");
+        });
+    }
+
+    pub fn ledger_canister_id() -> Principal {
+        CONFIG.with(|refcell| {
+            refcell
+                .borrow()
+                .get()
+                .ledger_canister_id
+                .unwrap_or(MAINNET_LEDGER_CANISTER_ID)
+        })
+    }
+
+    pub fn cmc_canister_id() -> Principal {
+        CONFIG.with(|refcell| {
+            refcell
+                .borrow()
+                .get()
+                .cmc_canister_id
+                .unwrap_or(MAINNET_CYCLES_MINTING_CANISTER_ID)
+        })
+    }
+
+    /*
+    Persist the canister ids passed on install or upgrade.
+    Ids left out keep their current value, so an upgrade without arguments changes nothing.
+    */
+    pub fn apply_args(args: Web3DiskArgs) {
+        CONFIG.with(|refcell| {
+            let mut refcell = refcell.borrow_mut();
+            let mut config = refcell.get().clone();
+
+            if let Some(ledger_canister_id) = args.ledger_canister_id {
+                config.ledger_canister_id = Some(ledger_canister_id);
+            }
+            if let Some(cmc_canister_id) = args.cmc_canister_id {
+                config.cmc_canister_id = Some(cmc_canister_id);
+            }
+
+            refcell.set(config).expect("Failed to set canister ids");
+        });
+    }
+
+    /*
+    Record the current cycles balance and return the observed burn rate in cycles per day.
+    A balance higher than the last sample means a top up happened, the previous rate is kept then.
+    */
+    pub fn record_cycles(cycles: u128, time: u64) -> Option<u128> {
+        CONFIG.with(|refcell| {
+            let mut refcell = refcell.borrow_mut();
+            let mut config = refcell.get().clone();
+
+            if let Some(sample) = config.cycles_sample {
+                let elapsed = time.saturating_sub(sample.time);
+
+                // Samples taken in quick succession say little about the burn rate
+                if elapsed < MIN_BURN_RATE_INTERVAL_NANOS {
+                    return config.burn_rate;
+                }
+
+                if cycles <= sample.cycles {
+                    let burned = sample.cycles - cycles;
+                    config.burn_rate = Some(burned * NANOS_PER_DAY / elapsed as u128);
+                }
+            }
+
+            config.cycles_sample = Some(CyclesSample { cycles, time });
+            refcell.set(config).expect("Failed to record cycles");
+
+            config.burn_rate
+        })
+    }
+}
+
+const NANOS_PER_DAY: u128 = 24 * 60 * 60 * 1_000_000_000;
+const DEFAULT_TRASH_RETENTION_DAYS: u64 = 30;
+const DEFAULT_THUMBNAIL_MAX_SOURCE_BYTES: u64 = 4 * 1024 * 1024;
+// Chunks of open upload sessions are held on the heap until commit
+const DEFAULT_MAX_UPLOAD_BYTES: u64 = 512 * 1024 * 1024;
+const MIN_BURN_RATE_INTERVAL_NANOS: u64 = 60 * 60 * 1_000_000_000;
+
+/*
+Storage limits for user files. Unset values mean no limit.
+`low_cycles_threshold` is the cycles balance writes must leave in the canister.
+*/
+#[derive(CandidType, Deserialize, Default, Clone, Copy, Debug)]
+pub struct Quota {
+    pub max_bytes: Option<u64>,
+    pub low_cycles_threshold: Option<u128>,
+}
+
+impl Quota {
+    /*
+    Check whether `new_bytes` more bytes fit in the quota. Besides the balance threshold,
+    enough cycles must be left to keep the new bytes stored for `STORAGE_RESERVE_SECONDS`.
+    The balance is only read when a threshold is set.
+    */
+    pub fn check(
+        &self,
+        used_bytes: u64,
+        new_bytes: u64,
+        cycles: impl FnOnce() -> u128,
+    ) -> Result<(), String> {
+        if let Some(max_bytes) = self.max_bytes {
+            if used_bytes + new_bytes > max_bytes {
+                return Err(format!(
+                    "Storage quota exceeded: {} of {} bytes used, {} bytes requested",
+                    used_bytes, max_bytes, new_bytes
+                ));
+            }
+        }
+
+        if let Some(threshold) = self.low_cycles_threshold {
+            let cycles = cycles();
+            let storage_cost = new_bytes as u128 * STORAGE_CYCLES_PER_GIB_SECOND
+                / (1024 * 1024 * 1024)
+                * STORAGE_RESERVE_SECONDS;
+
+            if cycles.saturating_sub(storage_cost) < threshold {
+                return Err(format!(
+                    "Cycles balance of {} would drop below the threshold of {}",
+                    cycles, threshold
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Storage fee on a 13 node subnet
+const STORAGE_CYCLES_PER_GIB_SECOND: u128 = 127_000;
+const STORAGE_RESERVE_SECONDS: u128 = 30 * 24 * 60 * 60;
+
+/*
+When enabled, ICP on the default account is converted to cycles as soon as the balance
+drops below `trigger_threshold`, spending at most `max_icp_e8s` per top up.
+*/
+#[derive(CandidType, Deserialize, Default, Clone, Copy, Debug)]
+pub struct TopUpPolicy {
+    pub enabled: bool,
+    pub trigger_threshold: u128,
+    pub max_icp_e8s: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Copy)]
+struct CyclesSample {
+    cycles: u128,
+    time: u64,
 }
 
 #[derive(CandidType, Deserialize, Default, Clone, Copy)]
 struct Config {
     pub status: Status,
     pub ii_principal: Option<Principal>,
+    // Optional so configs written by earlier versions still decode
+    pub quota: Option<Quota>,
+    pub cycles_sample: Option<CyclesSample>,
+    pub burn_rate: Option<u128>,
+    pub top_up_policy: Option<TopUpPolicy>,
+    // Mainnet canisters are used when unset
+    pub ledger_canister_id: Option<Principal>,
+    pub cmc_canister_id: Option<Principal>,
+    pub pending_transfer: Option<PendingTransfer>,
+    // Serve generated index pages for public folders without an index.html
+    pub directory_listing: Option<bool>,
+    // Deleted files are purged from the trash after this many days
+    pub trash_retention_days: Option<u64>,
+    // Only images up to this size get thumbnails, 0 disables them
+    pub thumbnail_max_source_bytes: Option<u64>,
+    // Open upload sessions may reserve at most this many bytes in total
+    pub max_upload_bytes: Option<u64>,
+}
+
+/*
+Install and upgrade arguments. Point these at locally deployed ledger and CMC canisters
+to run the payment flows against a local replica.
+*/
+#[derive(CandidType, Deserialize, Clone, Debug, Default)]
+pub struct Web3DiskArgs {
+    pub ledger_canister_id: Option<Principal>,
+    pub cmc_canister_id: Option<Principal>,
 }
 
 #[derive(CandidType, Deserialize, Default, Clone, Copy)]
@@ -78,7 +380,7 @@ pub enum Status {
     Active(Mode),
 }
 
-#[derive(CandidType, Deserialize, Clone, Debug, Copy)]
+#[derive(CandidType, Deserialize, Clone, Debug, Copy, PartialEq, Eq)]
 pub enum Mode {
     Developer,
     Trial,
@@ -112,8 +414,8 @@ impl Config {
 
 impl Storable for Config {
     const BOUND: Bound = Bound::Bounded {
-        max_size: 256,
-        is_fixed_size: true,
+        max_size: 1024,
+        is_fixed_size: false,
     };
 
     fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
@@ -128,7 +430,7 @@ impl Storable for Config {
 #[derive(Clone, Debug, CandidType, Deserialize)]
 pub struct GrantOwnershipArgs {
     mode: Mode,
-    ii_principal: Principal,
+    pub ii_principal: Principal,
 }
 
 pub async fn handle_grant_ownership(args: GrantOwnershipArgs) {
@@ -189,7 +491,438 @@ pub async fn handle_grant_ownership(args: GrantOwnershipArgs) {
     }
 }
 
+// Proposed transfers the new owner did not accept within a day are dropped
+const TRANSFER_EXPIRY_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ProposeTransferArgs {
+    pub to: Principal,
+    pub mode: Mode,
+}
+
+/*
+A transfer of the disk to a new owner and/or mode, waiting for the new owner to accept.
+`accepting` is set while the controllers are being updated, so a transfer is applied at most once.
+*/
+#[derive(CandidType, Deserialize, Clone, Copy, Debug)]
+pub struct PendingTransfer {
+    pub to: Principal,
+    pub mode: Mode,
+    pub proposed_by: Principal,
+    pub expires_at: u64,
+    pub accepting: bool,
+}
+
+/*
+First step of an ownership transfer or mode change, proposed by a current owner.
+Proposing the current owner with another mode changes only the mode, the owner accepts
+it like any other transfer. Proposals that would change nothing are rejected.
+*/
+pub fn propose_transfer(
+    caller: Principal,
+    args: ProposeTransferArgs,
+    time: u64,
+) -> Result<PendingTransfer, String> {
+    if !ConfigStore::is_active() {
+        return Err("Not initialized".to_string());
+    }
+
+    if args.to == Principal::anonymous() {
+        return Err("Anonymous principal not allowed".to_string());
+    }
+
+    if ConfigStore::ii_principal() == Some(args.to)
+        && matches!(ConfigStore::status(), Status::Active(mode) if mode == args.mode)
+    {
+        return Err("Transfer changes neither the owner nor the mode".to_string());
+    }
+
+    if ConfigStore::pending_transfer().is_some_and(|transfer| transfer.accepting) {
+        return Err("A transfer is being accepted".to_string());
+    }
+
+    let transfer = PendingTransfer {
+        to: args.to,
+        mode: args.mode,
+        proposed_by: caller,
+        expires_at: time + TRANSFER_EXPIRY_NANOS,
+        accepting: false,
+    };
+    ConfigStore::set_pending_transfer(Some(transfer));
+
+    Ok(transfer)
+}
+
+pub fn cancel_transfer() -> Result<(), String> {
+    match ConfigStore::pending_transfer() {
+        Some(transfer) if transfer.accepting => Err("A transfer is being accepted".to_string()),
+        Some(_) => {
+            ConfigStore::set_pending_transfer(None);
+            Ok(())
+        }
+        None => Err("No pending transfer".to_string()),
+    }
+}
+
+/*
+Second step, called by the new owner. Config and members are switched over before the
+controllers are updated with a single `update_settings` call. If that call fails, the
+previous config and members are restored and the transfer stays pending so it can be retried.
+*/
+pub async fn accept_transfer(caller: Principal, time: u64) -> Result<(), String> {
+    valid_transfer(caller, time)?;
+    let settings = canister_status().await.settings;
+
+    // Re-check, the transfer may have been cancelled or accepted while waiting for the status
+    let transfer = valid_transfer(caller, time)?;
+    if transfer.accepting {
+        return Err("Transfer is already being accepted".to_string());
+    }
+
+    let previous_owner = ConfigStore::ii_principal();
+    let canister_id = ic_cdk::api::id();
+    let controllers = match transfer.mode {
+        // The II principal is no controller in trial mode
+        Mode::Trial => settings
+            .controllers
+            .into_iter()
+            .filter(|p| Some(*p) != previous_owner)
+            .collect(),
+        // Keep the dev IDs, replacing only the previous II principal
+        Mode::Developer => {
+            let mut controllers: Vec<Principal> = settings
+                .controllers
+                .into_iter()
+                .filter(|p| Some(*p) != previous_owner && *p != transfer.to)
+                .collect();
+            controllers.push(transfer.to);
+            controllers
+        }
+        Mode::User => vec![canister_id, transfer.to],
+    };
+
+    // Switch over and lock the transfer before awaiting the management canister
+    let mut rollback = TransferRollback::switch_over(transfer, time);
+
+    let arg = UpdateSettingsArgument {
+        canister_id,
+        settings: CanisterSettings {
+            controllers: Some(controllers),
+            memory_allocation: Some(settings.memory_allocation),
+            compute_allocation: Some(settings.compute_allocation),
+            freezing_threshold: Some(settings.freezing_threshold),
+            reserved_cycles_limit: Some(settings.reserved_cycles_limit),
+        },
+    };
+
+    match update_settings(arg).await {
+        Ok(()) => {
+            rollback.done = true;
+            ConfigStore::set_pending_transfer(None);
+            Ok(())
+        }
+        Err(err) => Err(format!(
+            "Failed to update controllers: {:?}, {}",
+            err.0, err.1
+        )),
+    }
+}
+
+/*
+Restores the config and members from before a transfer was accepted and unlocks the transfer,
+unless the controllers were updated. Runs on drop, so this also happens when the
+`update_settings` callback traps: the cdk drops the future in the cleanup callback.
+*/
+struct TransferRollback {
+    transfer: PendingTransfer,
+    previous_owner: Option<Principal>,
+    previous_status: Status,
+    previous_members: Vec<Member>,
+    done: bool,
+}
+
+impl TransferRollback {
+    // Hand config and members over to the new owner, remembering what to restore
+    fn switch_over(transfer: PendingTransfer, time: u64) -> Self {
+        let rollback = Self {
+            transfer,
+            previous_owner: ConfigStore::ii_principal(),
+            previous_status: ConfigStore::status(),
+            previous_members: MemberStore::members(),
+            done: false,
+        };
+
+        ConfigStore::set_pending_transfer(Some(PendingTransfer {
+            accepting: true,
+            ..transfer
+        }));
+        ConfigStore::set_ownership(Some(transfer.to), Status::Active(transfer.mode));
+        MemberStore::add(transfer.to, Role::Owner, time);
+        if let Some(previous_owner) = rollback.previous_owner.filter(|p| *p != transfer.to) {
+            // The previous owner may already have left the members list
+            let _ = MemberStore::remove(previous_owner);
+        }
+
+        rollback
+    }
+}
+
+impl Drop for TransferRollback {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+
+        ConfigStore::set_ownership(self.previous_owner, self.previous_status);
+        MemberStore::restore(std::mem::take(&mut self.previous_members));
+        ConfigStore::set_pending_transfer(Some(self.transfer));
+    }
+}
+
+fn valid_transfer(caller: Principal, time: u64) -> Result<PendingTransfer, String> {
+    let transfer = ConfigStore::pending_transfer()
+        .filter(|transfer| transfer.accepting || transfer.expires_at > time)
+        .ok_or_else(|| "No pending transfer".to_string())?;
+
+    if transfer.to != caller {
+        return Err("Transfer is proposed to another principal".to_string());
+    }
+
+    Ok(transfer)
+}
+
 fn grant_commit_permission(p: Principal) {
     ConfigStore::set_ii_principal(p);
-    StateStore::grant_permission(p, &Permission::Commit);
+    MemberStore::add(p, Role::Owner, ic_cdk::api::time());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+    const HOUR_NANOS: u64 = 60 * 60 * 1_000_000_000;
+
+    #[test]
+    fn quota_limits_stored_bytes() {
+        let quota = Quota {
+            max_bytes: Some(1_000),
+            low_cycles_threshold: None,
+        };
+
+        assert!(quota.check(600, 400, || 0).is_ok());
+        assert_eq!(
+            quota.check(600, 401, || 0),
+            Err("Storage quota exceeded: 600 of 1000 bytes used, 401 bytes requested".to_string())
+        );
+    }
+
+    #[test]
+    fn quota_reads_the_balance_only_with_a_threshold() {
+        let quota = Quota::default();
+        assert!(quota
+            .check(u64::MAX / 2, GIB, || panic!("balance read"))
+            .is_ok());
+    }
+
+    #[test]
+    fn quota_reserves_cycles_to_keep_new_bytes_stored() {
+        let quota = Quota {
+            max_bytes: None,
+            low_cycles_threshold: Some(1_000_000),
+        };
+        let storage_cost = STORAGE_CYCLES_PER_GIB_SECOND * STORAGE_RESERVE_SECONDS;
+
+        assert!(quota.check(0, 0, || 1_000_000).is_ok());
+        assert!(quota.check(0, 0, || 999_999).is_err());
+        assert!(quota.check(0, GIB, || 1_000_000 + storage_cost).is_ok());
+        assert_eq!(
+            quota.check(0, GIB, || storage_cost),
+            Err(format!(
+                "Cycles balance of {} would drop below the threshold of 1000000",
+                storage_cost
+            ))
+        );
+    }
+
+    #[test]
+    fn stores_the_quota() {
+        assert!(ConfigStore::quota().max_bytes.is_none());
+
+        ConfigStore::set_quota(Quota {
+            max_bytes: Some(GIB),
+            low_cycles_threshold: Some(1_000),
+        });
+
+        let quota = ConfigStore::quota();
+        assert_eq!(quota.max_bytes, Some(GIB));
+        assert_eq!(quota.low_cycles_threshold, Some(1_000));
+    }
+
+    #[test]
+    fn keeps_canister_ids_not_passed_on_upgrade() {
+        assert_eq!(
+            ConfigStore::ledger_canister_id(),
+            MAINNET_LEDGER_CANISTER_ID
+        );
+        assert_eq!(
+            ConfigStore::cmc_canister_id(),
+            MAINNET_CYCLES_MINTING_CANISTER_ID
+        );
+
+        let ledger = Principal::from_slice(&[1]);
+        let cmc = Principal::from_slice(&[2]);
+        ConfigStore::apply_args(Web3DiskArgs {
+            ledger_canister_id: Some(ledger),
+            cmc_canister_id: Some(cmc),
+        });
+        ConfigStore::apply_args(Web3DiskArgs::default());
+        assert_eq!(ConfigStore::ledger_canister_id(), ledger);
+        assert_eq!(ConfigStore::cmc_canister_id(), cmc);
+
+        ConfigStore::apply_args(Web3DiskArgs {
+            ledger_canister_id: None,
+            cmc_canister_id: Some(ledger),
+        });
+        assert_eq!(ConfigStore::ledger_canister_id(), ledger);
+        assert_eq!(ConfigStore::cmc_canister_id(), ledger);
+    }
+
+    fn principal(id: u8) -> Principal {
+        Principal::from_slice(&[id])
+    }
+
+    // An active disk in user mode owned by principal 1
+    fn activate() {
+        ConfigStore::set_ownership(Some(principal(1)), Status::Active(Mode::User));
+        MemberStore::add(principal(1), Role::Owner, 0);
+    }
+
+    fn propose(to: Principal, mode: Mode, time: u64) -> Result<PendingTransfer, String> {
+        propose_transfer(principal(1), ProposeTransferArgs { to, mode }, time)
+    }
+
+    #[test]
+    fn validates_proposed_transfers() {
+        assert_eq!(
+            propose(principal(2), Mode::User, 0).err(),
+            Some("Not initialized".to_string())
+        );
+
+        activate();
+        assert_eq!(
+            propose(Principal::anonymous(), Mode::User, 0).err(),
+            Some("Anonymous principal not allowed".to_string())
+        );
+        assert_eq!(
+            propose(principal(1), Mode::User, 0).err(),
+            Some("Transfer changes neither the owner nor the mode".to_string())
+        );
+
+        let transfer = propose(principal(2), Mode::Developer, 5).unwrap();
+        assert_eq!(transfer.expires_at, 5 + TRANSFER_EXPIRY_NANOS);
+        assert!(!transfer.accepting);
+
+        cancel_transfer().unwrap();
+        assert!(ConfigStore::pending_transfer().is_none());
+        assert_eq!(cancel_transfer(), Err("No pending transfer".to_string()));
+    }
+
+    #[test]
+    fn accepts_only_valid_transfers() {
+        activate();
+        propose(principal(2), Mode::User, 0).unwrap();
+
+        assert_eq!(
+            valid_transfer(principal(3), 1).err(),
+            Some("Transfer is proposed to another principal".to_string())
+        );
+        assert!(valid_transfer(principal(2), TRANSFER_EXPIRY_NANOS - 1).is_ok());
+        assert_eq!(
+            valid_transfer(principal(2), TRANSFER_EXPIRY_NANOS).err(),
+            Some("No pending transfer".to_string())
+        );
+    }
+
+    #[test]
+    fn rolls_back_a_transfer_that_did_not_complete() {
+        activate();
+        MemberStore::add(principal(3), Role::Editor, 0);
+        let transfer = propose(principal(2), Mode::Trial, 0).unwrap();
+
+        let rollback = TransferRollback::switch_over(transfer, 1);
+        assert_eq!(ConfigStore::ii_principal(), Some(principal(2)));
+        assert!(matches!(ConfigStore::status(), Status::Active(Mode::Trial)));
+        assert_eq!(MemberStore::role(principal(1)), None);
+        assert_eq!(MemberStore::role(principal(2)), Some(Role::Owner));
+        assert!(ConfigStore::pending_transfer().is_some_and(|transfer| transfer.accepting));
+
+        // While accepting, the transfer can be neither cancelled nor replaced
+        assert!(cancel_transfer().is_err());
+        assert!(propose(principal(3), Mode::User, 1).is_err());
+
+        drop(rollback);
+        assert_eq!(ConfigStore::ii_principal(), Some(principal(1)));
+        assert!(matches!(ConfigStore::status(), Status::Active(Mode::User)));
+        assert_eq!(MemberStore::role(principal(1)), Some(Role::Owner));
+        assert_eq!(MemberStore::role(principal(2)), None);
+        assert_eq!(MemberStore::role(principal(3)), Some(Role::Editor));
+        assert!(ConfigStore::pending_transfer().is_some_and(|transfer| !transfer.accepting));
+    }
+
+    #[test]
+    fn keeps_a_completed_transfer() {
+        activate();
+        let transfer = propose(principal(2), Mode::Developer, 0).unwrap();
+
+        let mut rollback = TransferRollback::switch_over(transfer, 1);
+        rollback.done = true;
+        drop(rollback);
+
+        assert_eq!(ConfigStore::ii_principal(), Some(principal(2)));
+        assert!(matches!(
+            ConfigStore::status(),
+            Status::Active(Mode::Developer)
+        ));
+        assert_eq!(MemberStore::role(principal(1)), None);
+        assert_eq!(MemberStore::role(principal(2)), Some(Role::Owner));
+    }
+
+    #[test]
+    fn changes_only_the_mode_for_the_current_owner() {
+        activate();
+        MemberStore::add(principal(3), Role::Editor, 0);
+        let transfer = propose(principal(1), Mode::Trial, 0).unwrap();
+
+        // The owner accepts their own mode change
+        assert!(valid_transfer(principal(1), 1).is_ok());
+
+        let mut rollback = TransferRollback::switch_over(transfer, 1);
+        rollback.done = true;
+        drop(rollback);
+
+        assert_eq!(ConfigStore::ii_principal(), Some(principal(1)));
+        assert!(matches!(ConfigStore::status(), Status::Active(Mode::Trial)));
+        assert_eq!(MemberStore::role(principal(1)), Some(Role::Owner));
+        assert_eq!(MemberStore::role(principal(3)), Some(Role::Editor));
+    }
+
+    #[test]
+    fn computes_the_burn_rate_per_day() {
+        assert_eq!(ConfigStore::record_cycles(10_000_000, 0), None);
+
+        // Too soon after the first sample to tell
+        assert_eq!(ConfigStore::record_cycles(9_000_000, HOUR_NANOS / 2), None);
+
+        assert_eq!(
+            ConfigStore::record_cycles(9_000_000, 2 * HOUR_NANOS),
+            Some(12_000_000)
+        );
+
+        // A top up keeps the previous rate
+        assert_eq!(
+            ConfigStore::record_cycles(50_000_000, 4 * HOUR_NANOS),
+            Some(12_000_000)
+        );
+    }
 }
@@ -1,6 +1,609 @@
+use super::{
+    listing::DirectoryListing,
+    search::SearchIndex,
+    store::{FileDataStore, MetadataStore},
+    thumbnails::{Thumbnail, Thumbnails},
+    trash::Trash,
+    File, FileMetadata, Key, Metadata, MAX_CONTENT_TYPE_LENGTH,
+};
+use crate::{
+    state_machine::StorageUsage,
+    types::{BatchId, ChunkId, DeleteAssetArguments, DeleteBatchArguments, StoreArg},
+    web3disk::stores::{
+        config::ConfigStore, heap::StateStore, sharing::SharingStore, uploads::UploadStore,
+    },
+};
+use candid::Nat;
+use ic_cdk::api::canister_balance128;
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+
 pub struct FsStore;
 
-impl FsStore {}
+impl FsStore {
+    /*
+    Store a user file in stable memory and add it to the certified http view.
+    Missing parent folders are created along the way.
+    */
+    pub fn store(arg: StoreArg, time: u64) -> Result<(), String> {
+        let key = Key::new(&arg.key);
+        Self::check_outside_trash(&key)?;
+        Self::check_file_key(&key)?;
+        Self::check_content_type(&arg.content_type)?;
+
+        if arg.content_encoding != "identity" {
+            return Err(format!(
+                "Unsupported content encoding: {}",
+                arg.content_encoding
+            ));
+        }
+
+        Self::check_quota(&key, arg.content.len() as u64)?;
+
+        let sha256 = Sha256::digest(&arg.content).to_vec();
+        if let Some(provided_hash) = &arg.sha256 {
+            if provided_hash.as_slice() != sha256.as_slice() {
+                return Err("sha256 mismatch".to_string());
+            }
+        }
+
+        // Private and shared files are kept out of the certified public tree
+        if SharingStore::is_public(&key) {
+            StateStore::store(
+                StoreArg {
+                    sha256: Some(ByteBuf::from(sha256.clone())),
+                    aliased: Some(false),
+                    ..arg.clone()
+                },
+                time,
+            )?;
+        }
+
+        let chunks = vec![File::from(arg.content)];
+        Self::persist(&key, arg.content_type, chunks, sha256, None, time);
+        DirectoryListing::refresh(&key);
+
+        Ok(())
+    }
+
+    /*
+    Commit chunks uploaded into a batch as a user file. The content is checked against
+    the expected SHA-256 before anything is written.
+    */
+    pub fn commit_chunks(
+        key: &Key,
+        content_type: String,
+        batch_id: BatchId,
+        chunk_ids: Vec<ChunkId>,
+        expected_sha256: &[u8],
+        time: u64,
+    ) -> Result<(), String> {
+        Self::check_outside_trash(key)?;
+        Self::check_file_key(key)?;
+        Self::check_content_type(&content_type)?;
+
+        let chunks = chunk_ids
+            .iter()
+            .map(StateStore::chunk_content)
+            .collect::<Result<Vec<File>, String>>()?;
+
+        Self::check_quota(key, chunks.iter().map(|chunk| chunk.len() as u64).sum())?;
+
+        let mut hasher = Sha256::new();
+        for chunk in chunks.iter() {
+            hasher.update(chunk);
+        }
+        let sha256 = hasher.finalize().to_vec();
+
+        if sha256.as_slice() != expected_sha256 {
+            return Err("sha256 mismatch".to_string());
+        }
+
+        if SharingStore::is_public(key) {
+            StateStore::commit_chunks(
+                batch_id,
+                key.to_string(),
+                content_type.clone(),
+                chunk_ids,
+                sha256.clone(),
+                time,
+            )?;
+        } else {
+            StateStore::delete_batch(DeleteBatchArguments { batch_id })?;
+        }
+
+        Self::persist(key, content_type, chunks, sha256, None, time);
+        DirectoryListing::refresh(key);
+
+        Ok(())
+    }
+
+    /*
+    Add public files missing from the certified http view, called in `post_upgrade`.
+    The http view keeps its assets in stable memory, so this only copies files after an
+    upgrade from a version that kept them on the heap. The same goes for the search index.
+    Files stored by versions without thumbnails get them once they are stored again.
+    */
+    pub fn restore() -> Result<(), String> {
+        for metadata in MetadataStore::list_files() {
+            if SharingStore::is_public(&metadata.key)
+                && !StateStore::contains_asset(&metadata.key.to_string())
+            {
+                Thumbnails::certify(&metadata);
+                Self::certify(metadata)?;
+            }
+        }
+        DirectoryListing::refresh_all();
+        SearchIndex::restore();
+
+        Ok(())
+    }
+
+    /*
+    Bring the certified http view of a file or folder in line with its visibility,
+    adding public files and removing private or shared ones.
+    */
+    pub fn recertify(key: &Key) -> Result<(), String> {
+        let files = match Self::stat(key)? {
+            Metadata::File(file) => vec![file],
+            Metadata::Folder(_) => MetadataStore::list_descendants(key)
+                .into_iter()
+                .filter_map(|metadata| match metadata {
+                    Metadata::File(file) => Some(file),
+                    Metadata::Folder(_) => None,
+                })
+                .collect(),
+        };
+
+        for file in files {
+            Thumbnails::certify(&file);
+            if SharingStore::is_public(&file.key) {
+                Self::certify(file)?;
+            } else {
+                StateStore::delete_asset(DeleteAssetArguments {
+                    key: file.key.to_string(),
+                });
+            }
+        }
+        DirectoryListing::refresh(key);
+
+        Ok(())
+    }
+
+    /*
+    Read a single chunk of a file straight from stable memory, used to serve files
+    that are not part of the certified tree. Returns the metadata, the chunk and the chunk count.
+    */
+    pub fn read_chunk(key: &Key, index: u32) -> Result<(FileMetadata, File, u32), String> {
+        let metadata = match MetadataStore::get(key) {
+            Some(Metadata::File(file)) => file,
+            _ => return Err(format!("Not found: {}", key.0)),
+        };
+
+        let chunk = FileDataStore::get_chunk(&metadata, index)
+            .ok_or_else(|| format!("Chunk {} not found: {}", index, key.0))?;
+        let chunk_count = FileDataStore::chunk_count(&metadata);
+
+        Ok((metadata, chunk, chunk_count))
+    }
+
+    pub fn read_thumbnail(key: &Key, size: u32) -> Result<File, String> {
+        match MetadataStore::get(key) {
+            Some(Metadata::File(file)) => Thumbnails::get(&file, size),
+            _ => Err(format!("Not found: {}", key.0)),
+        }
+    }
+
+    pub fn create_folder(key: &Key, time: u64) -> Result<Metadata, String> {
+        Self::check_outside_trash(key)?;
+        Self::insert_folder(key, time)
+    }
+
+    fn insert_folder(key: &Key, time: u64) -> Result<Metadata, String> {
+        key.validate()?;
+
+        if !key.is_dir() {
+            return Err(format!("Folder key must end with '/': {}", key.0));
+        }
+
+        if MetadataStore::contains_key(key) {
+            return Err(format!("Folder already exists: {}", key.0));
+        }
+
+        Self::create_ancestors(key, time);
+
+        let metadata = Metadata::new_folder_metadata(key, time);
+        MetadataStore::insert(key, metadata.clone());
+        DirectoryListing::refresh(key);
+
+        Ok(metadata)
+    }
+
+    pub fn stat(key: &Key) -> Result<Metadata, String> {
+        match MetadataStore::get(key) {
+            Some(metadata) => Ok(metadata),
+            // The root folder only gets persisted once something is stored in it
+            None if key.is_root() => Ok(Metadata::new_folder_metadata(key, 0)),
+            None => Err(format!("Not found: {}", key.0)),
+        }
+    }
+
+    /*
+    List the direct children of a folder
+    */
+    pub fn list(folder: &Key) -> Result<Vec<Metadata>, String> {
+        if let Metadata::File(_) = Self::stat(folder)? {
+            return Err(format!("Not a folder: {}", folder.0));
+        }
+
+        // The trash is listed separately
+        let children = MetadataStore::list_children(folder)
+            .into_iter()
+            .filter(|metadata| folder.in_trash() || !metadata.key().in_trash())
+            .collect();
+
+        Ok(children)
+    }
+
+    /*
+    Move or rename a file or folder. Folders are moved recursively.
+    */
+    pub fn rename(from: &Key, to: &Key, time: u64) -> Result<(), String> {
+        Self::check_outside_trash(from)?;
+        Self::check_outside_trash(to)?;
+        Self::move_recursive(from, to, time)
+    }
+
+    /*
+    Copy a file or folder. Folders are copied recursively.
+    */
+    pub fn copy(from: &Key, to: &Key, time: u64) -> Result<(), String> {
+        Self::check_outside_trash(to)?;
+        Self::check_quota(to, Self::size(from)?)?;
+
+        Self::copy_recursive(from, to, time, Self::copy_file)?;
+        DirectoryListing::refresh(to);
+
+        Ok(())
+    }
+
+    /*
+    Move a file or a folder including everything in it to the trash
+    */
+    pub fn delete(key: &Key, time: u64) -> Result<(), String> {
+        if key.is_root() {
+            return Err("Cannot delete the root folder".to_string());
+        }
+
+        if key.in_trash() {
+            return Err(format!("Already in the trash: {}", key.0));
+        }
+
+        Trash::put(key, time).map(|_| ())
+    }
+
+    // Total size of the files at or below `key`
+    pub fn size(key: &Key) -> Result<u64, String> {
+        match Self::stat(key)? {
+            Metadata::File(file) => Ok(file.size),
+            Metadata::Folder(_) => Ok(Self::files_size(MetadataStore::list_descendants(key))),
+        }
+    }
+
+    pub fn check_outside_trash(key: &Key) -> Result<(), String> {
+        if key.in_trash() {
+            return Err(format!("Cannot write to the trash: {}", key.0));
+        }
+
+        Ok(())
+    }
+
+    pub fn check_content_type(content_type: &str) -> Result<(), String> {
+        if content_type.len() > MAX_CONTENT_TYPE_LENGTH {
+            return Err(format!(
+                "Content type exceeds {} bytes",
+                MAX_CONTENT_TYPE_LENGTH
+            ));
+        }
+
+        Ok(())
+    }
+
+    // A move keeps the creation time and the shares of the original files
+    pub(super) fn move_recursive(from: &Key, to: &Key, time: u64) -> Result<(), String> {
+        Self::copy_recursive(from, to, time, Self::move_file)?;
+        DirectoryListing::refresh(to);
+        SharingStore::move_shares(from, to);
+        Self::delete_permanently(from)
+    }
+
+    /*
+    Delete a file or a folder including everything in it, without going through the trash
+    */
+    pub(super) fn delete_permanently(key: &Key) -> Result<(), String> {
+        let metadata = MetadataStore::get(key).ok_or_else(|| format!("Not found: {}", key.0))?;
+
+        let mut deleted = MetadataStore::list_descendants(key);
+        deleted.push(metadata);
+
+        for metadata in deleted {
+            if let Metadata::File(file) = &metadata {
+                FileDataStore::remove(file);
+                Thumbnails::release(file);
+                Thumbnails::uncertify(file);
+                SearchIndex::remove(&file.key);
+                StateStore::delete_asset(DeleteAssetArguments {
+                    key: file.key.to_string(),
+                });
+            }
+            MetadataStore::remove(metadata.key());
+        }
+
+        SharingStore::remove_subtree(key);
+        DirectoryListing::refresh(key);
+
+        Ok(())
+    }
+
+    // Total size of all user files, counting identical files each time. Quotas apply to this size.
+    pub fn used_bytes() -> u64 {
+        MetadataStore::list_files()
+            .iter()
+            .map(|file| file.size)
+            .sum()
+    }
+
+    // Bytes of file content actually stored, files with identical content are stored once
+    pub fn stored_bytes() -> u64 {
+        FileDataStore::stored_bytes()
+    }
+
+    pub fn storage_usage() -> StorageUsage {
+        StorageUsage {
+            logical_bytes: Nat::from(Self::used_bytes()),
+            physical_bytes: Nat::from(Self::stored_bytes()),
+        }
+    }
+
+    /*
+    Check that writing `size` bytes to `key` stays within the quota.
+    The size of a file being overwritten is freed by the write,
+    bytes reserved by open upload sessions are counted as used.
+    */
+    pub fn check_quota(key: &Key, size: u64) -> Result<(), String> {
+        let replaced = match MetadataStore::get(key) {
+            Some(Metadata::File(file)) => file.size,
+            _ => 0,
+        };
+
+        ConfigStore::quota().check(
+            Self::used_bytes().saturating_sub(replaced) + UploadStore::pending_bytes(),
+            size,
+            canister_balance128,
+        )
+    }
+
+    fn files_size(metadata: Vec<Metadata>) -> u64 {
+        metadata
+            .iter()
+            .map(|metadata| match metadata {
+                Metadata::File(file) => file.size,
+                Metadata::Folder(_) => 0,
+            })
+            .sum()
+    }
+
+    /*
+    Copy a file or folder with `copy_file` for each file, which is either a copy or a move
+    */
+    fn copy_recursive(
+        from: &Key,
+        to: &Key,
+        time: u64,
+        copy_file: fn(FileMetadata, &Key, u64) -> Result<(), String>,
+    ) -> Result<(), String> {
+        to.validate()?;
+
+        if from.is_dir() != to.is_dir() {
+            return Err("Source and destination must both be files or both be folders".to_string());
+        }
+
+        if from == to || from.contains(to) {
+            return Err(format!("Cannot copy {} into itself", from.0));
+        }
+
+        if MetadataStore::contains_key(to) {
+            return Err(format!("Destination already exists: {}", to.0));
+        }
+
+        let source = Self::stat(from)?;
+
+        // Carry visibility along so a private item never turns public by moving it
+        SharingStore::copy_visibility(from, to);
+
+        match source {
+            Metadata::File(file) => copy_file(file, to, time),
+            Metadata::Folder(_) => {
+                Self::insert_folder(to, time)?;
+
+                for metadata in MetadataStore::list_descendants(from) {
+                    let key = metadata.key().rebase(from, to);
+                    key.validate()?;
+
+                    match metadata {
+                        Metadata::File(file) => copy_file(file, &key, time)?,
+                        Metadata::Folder(_) => {
+                            MetadataStore::insert(&key, Metadata::new_folder_metadata(&key, time))
+                        }
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    fn copy_file(file: FileMetadata, to: &Key, time: u64) -> Result<(), String> {
+        Self::check_file_key(to)?;
+
+        let chunks = FileDataStore::get(&file)
+            .ok_or_else(|| format!("File data missing: {}", file.key.0))?;
+
+        if SharingStore::is_public(to) {
+            StateStore::store_chunks(
+                to.to_string(),
+                file.content_type.clone(),
+                &chunks,
+                file.sha256.clone(),
+                time,
+            )?;
+        }
+
+        Self::persist(
+            to,
+            file.content_type,
+            chunks,
+            file.sha256,
+            file.thumbnails,
+            time,
+        );
+
+        Ok(())
+    }
+
+    /*
+    Move a file to a key that is not taken, without reading its content. The content and
+    thumbnails are referenced at the new key before `delete_permanently` releases the original.
+    A file that only turns public by the move is certified from its content.
+    */
+    fn move_file(file: FileMetadata, to: &Key, time: u64) -> Result<(), String> {
+        Self::check_file_key(to)?;
+
+        let from = file.key.clone();
+        let moved = FileMetadata {
+            key: to.clone(),
+            name: to.file_name().unwrap_or_default(),
+            extension: to.extension(),
+            last_modified: time,
+            last_accessed: time,
+            ..file
+        };
+
+        if SharingStore::is_public(to) {
+            if StateStore::contains_asset(&from.to_string()) {
+                StateStore::copy_asset(&from.to_string(), &to.to_string())?;
+            } else {
+                Self::certify(moved.clone())?;
+            }
+        }
+
+        FileDataStore::retain(&moved.sha256);
+        Thumbnails::retain(moved.thumbnails.as_deref().unwrap_or_default());
+
+        Self::create_ancestors(to, time);
+        SearchIndex::rename(&from, &moved);
+        Thumbnails::certify(&moved);
+        MetadataStore::insert(to, Metadata::File(moved));
+
+        Ok(())
+    }
+
+    fn certify(file: FileMetadata) -> Result<(), String> {
+        let chunks = FileDataStore::get(&file)
+            .ok_or_else(|| format!("File data missing: {}", file.key.0))?;
+
+        StateStore::store_chunks(
+            file.key.to_string(),
+            file.content_type,
+            &chunks,
+            file.sha256,
+            file.last_modified,
+        )
+    }
+
+    fn check_file_key(key: &Key) -> Result<(), String> {
+        key.validate()?;
+
+        if key.is_dir() {
+            return Err(format!("Cannot store a file at a folder key: {}", key.0));
+        }
+
+        if let Some(Metadata::Folder(_)) = MetadataStore::get(key) {
+            return Err(format!("Folder already exists: {}", key.0));
+        }
+
+        if Thumbnail::is_thumbnail_key(key) {
+            return Err(format!("Key is reserved for thumbnails: {}", key.0));
+        }
+
+        Ok(())
+    }
+
+    /*
+    Write file data and metadata to stable memory once the certified view is updated.
+    Thumbnails are rendered unless taken over from the file being copied.
+    */
+    fn persist(
+        key: &Key,
+        content_type: String,
+        chunks: Vec<File>,
+        sha256: Vec<u8>,
+        thumbnails: Option<Vec<Thumbnail>>,
+        time: u64,
+    ) {
+        let existing = match MetadataStore::get(key) {
+            Some(Metadata::File(existing)) => Some(existing),
+            _ => None,
+        };
+        let created = existing.as_ref().map_or(time, |existing| existing.created);
+
+        let mut metadata = FileMetadata {
+            key: key.clone(),
+            name: key.file_name().unwrap_or_default(),
+            extension: key.extension(),
+            content_type,
+            size: chunks.iter().map(|chunk| chunk.len() as u64).sum(),
+            created,
+            last_modified: time,
+            last_accessed: time,
+            sha256,
+            thumbnails: None,
+        };
+        metadata.thumbnails = Some(match thumbnails {
+            Some(thumbnails) => {
+                Thumbnails::retain(&thumbnails);
+                thumbnails
+            }
+            None => Thumbnails::create(&metadata, &chunks),
+        });
+
+        Self::create_ancestors(key, time);
+        SearchIndex::update(&metadata, &chunks);
+        // The new content is referenced before the replaced one is released, they may be the same
+        FileDataStore::insert(&metadata.sha256, chunks);
+        if let Some(existing) = existing {
+            FileDataStore::remove(&existing);
+            Thumbnails::release(&existing);
+        }
+        Thumbnails::certify(&metadata);
+        MetadataStore::insert(key, Metadata::File(metadata));
+    }
+
+    // Create folder metadata for all missing parent folders and touch the existing ones
+    fn create_ancestors(key: &Key, time: u64) {
+        for ancestor in key.ancestors() {
+            let metadata = match MetadataStore::get(&ancestor) {
+                Some(Metadata::Folder(mut folder)) => {
+                    folder.last_modified = time;
+                    Metadata::Folder(folder)
+                }
+                _ => Metadata::new_folder_metadata(&ancestor, time),
+            };
+
+            MetadataStore::insert(&ancestor, metadata);
+        }
+    }
+}
 
 // pub fn root_node() -> Node {
 //     let mut root = Node::Folder(Key::new("/"), HashMap::new());
@@ -33,3 +636,140 @@ impl FsStore {}
 
 //     root
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::super::MAX_KEY_LENGTH;
+    use super::*;
+
+    fn store(key: &str, content: &[u8]) {
+        FsStore::store(
+            StoreArg {
+                key: key.to_string(),
+                content_type: "text/plain".to_string(),
+                content_encoding: "identity".to_string(),
+                content: ByteBuf::from(content.to_vec()),
+                sha256: None,
+                aliased: None,
+            },
+            0,
+        )
+        .unwrap();
+    }
+
+    fn content(key: &str) -> Vec<u8> {
+        FsStore::read_chunk(&Key::new(key), 0).unwrap().1.to_vec()
+    }
+
+    fn names(folder: &str) -> Vec<String> {
+        let mut names: Vec<String> = FsStore::list(&Key::new(folder))
+            .unwrap()
+            .iter()
+            .map(|metadata| metadata.key().name())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn stores_files_and_their_folders() {
+        store("/a/b/c.txt", b"hello");
+
+        assert_eq!(content("/a/b/c.txt"), b"hello");
+        assert!(matches!(
+            FsStore::stat(&Key::new("/a/b/")),
+            Ok(Metadata::Folder(_))
+        ));
+        assert_eq!(names("/a/"), vec!["b"]);
+        assert!(StateStore::contains_asset("/a/b/c.txt"));
+    }
+
+    #[test]
+    fn rejects_invalid_writes() {
+        let arg = |key: &str, content_encoding: &str, sha256: Option<Vec<u8>>| StoreArg {
+            key: key.to_string(),
+            content_type: "text/plain".to_string(),
+            content_encoding: content_encoding.to_string(),
+            content: ByteBuf::from(b"hello".to_vec()),
+            sha256: sha256.map(ByteBuf::from),
+            aliased: None,
+        };
+
+        assert!(FsStore::store(arg("/a.txt", "gzip", None), 0).is_err());
+        assert!(FsStore::store(arg("/a.txt", "identity", Some(vec![0; 32])), 0).is_err());
+        assert!(FsStore::store(arg("/.trash/a.txt", "identity", None), 0).is_err());
+        let long_key = format!("/{}", "a".repeat(MAX_KEY_LENGTH));
+        assert!(FsStore::store(arg(&long_key, "identity", None), 0).is_err());
+        let long_content_type = StoreArg {
+            content_type: "a".repeat(MAX_CONTENT_TYPE_LENGTH + 1),
+            ..arg("/a.txt", "identity", None)
+        };
+        assert!(FsStore::store(long_content_type, 0).is_err());
+        assert!(FsStore::stat(&Key::new("/a.txt")).is_err());
+
+        // The longest accepted key and content type fit the metadata bound
+        let longest = StoreArg {
+            content_type: "a".repeat(MAX_CONTENT_TYPE_LENGTH),
+            ..arg(
+                &format!("/.{}", "b".repeat(MAX_KEY_LENGTH - 2)),
+                "identity",
+                None,
+            )
+        };
+        FsStore::store(longest, 0).unwrap();
+    }
+
+    #[test]
+    fn renames_folders_recursively() {
+        store("/a/b.txt", b"b");
+        store("/a/c/d.txt", b"d");
+
+        FsStore::rename(&Key::new("/a/"), &Key::new("/x/"), 1).unwrap();
+
+        assert!(FsStore::stat(&Key::new("/a/")).is_err());
+        assert_eq!(names("/x/"), vec!["b.txt", "c"]);
+        assert_eq!(content("/x/c/d.txt"), b"d");
+        assert!(StateStore::contains_asset("/x/b.txt"));
+        assert!(!StateStore::contains_asset("/a/b.txt"));
+        assert_eq!(FsStore::stored_bytes(), 2);
+
+        assert!(FsStore::rename(&Key::new("/x/b.txt"), &Key::new("/.trash/b.txt"), 2).is_err());
+    }
+
+    #[test]
+    fn copies_share_their_content() {
+        store("/a/b.txt", b"hello");
+
+        FsStore::copy(&Key::new("/a/"), &Key::new("/c/"), 1).unwrap();
+
+        assert_eq!(content("/c/b.txt"), b"hello");
+        assert_eq!(content("/a/b.txt"), b"hello");
+        assert_eq!(FsStore::used_bytes(), 10);
+        assert_eq!(FsStore::stored_bytes(), 5);
+
+        let usage = FsStore::storage_usage();
+        assert_eq!(usage.logical_bytes, Nat::from(10_u8));
+        assert_eq!(usage.physical_bytes, Nat::from(5_u8));
+
+        // Deleting the original keeps the copy readable
+        FsStore::delete_permanently(&Key::new("/a/")).unwrap();
+        assert_eq!(content("/c/b.txt"), b"hello");
+        assert_eq!(FsStore::stored_bytes(), 5);
+    }
+
+    #[test]
+    fn deletes_into_the_trash() {
+        store("/a/b.txt", b"hello");
+
+        assert!(FsStore::delete(&Key::new("/"), 1).is_err());
+        FsStore::delete(&Key::new("/a/"), 1).unwrap();
+
+        assert!(FsStore::stat(&Key::new("/a/b.txt")).is_err());
+        assert!(!StateStore::contains_asset("/a/b.txt"));
+        assert_eq!(Trash::list().len(), 1);
+
+        let id = Trash::list()[0].id;
+        assert!(FsStore::stat(&Key::new(&format!("/.trash/{}/a/b.txt", id))).is_ok());
+        assert!(FsStore::delete(&Key::new(&format!("/.trash/{}/a/", id)), 2).is_err());
+    }
+}
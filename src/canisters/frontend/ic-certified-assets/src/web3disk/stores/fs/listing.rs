@@ -0,0 +1,221 @@
+use super::{api::FsStore, store::MetadataStore, Key, Metadata};
+use crate::web3disk::stores::{config::ConfigStore, heap::StateStore, sharing::SharingStore};
+use serde::Serialize;
+use time::OffsetDateTime;
+
+const INDEX_FILE: &str = "index.html";
+
+/*
+Generated index pages for public folders without an index.html. When enabled, a request for
+a folder key is answered with an HTML page, or with JSON for `Accept: application/json`.
+*/
+pub struct DirectoryListing;
+
+impl DirectoryListing {
+    /*
+    Regenerate the listings affected by a change at `key`: every folder containing it and,
+    for a folder, the folder itself and all folders below it.
+    */
+    pub fn refresh(key: &Key) {
+        if !ConfigStore::directory_listing() {
+            return;
+        }
+
+        if key.is_dir() {
+            // Drops the listings of folders that were deleted or moved away
+            StateStore::remove_directory_listings(&key.to_string());
+
+            Self::update(key);
+            for metadata in MetadataStore::list_descendants(key) {
+                if let Metadata::Folder(folder) = metadata {
+                    Self::update(&folder.key);
+                }
+            }
+        }
+
+        for ancestor in key.ancestors() {
+            Self::update(&ancestor);
+        }
+    }
+
+    /*
+    Regenerate all listings, or remove them when the setting is disabled.
+    Called after an upgrade and when the setting changes.
+    */
+    pub fn refresh_all() {
+        let root = Key::new("/");
+
+        StateStore::remove_directory_listings(&root.to_string());
+        Self::refresh(&root);
+    }
+
+    fn update(folder: &Key) {
+        let children = match FsStore::list(folder) {
+            Ok(children) if SharingStore::is_public(folder) => children
+                .into_iter()
+                .filter(|metadata| SharingStore::is_public(metadata.key()))
+                .collect::<Vec<Metadata>>(),
+            _ => {
+                StateStore::remove_directory_listing(&folder.to_string());
+                return;
+            }
+        };
+
+        let has_index = children.iter().any(|metadata| match metadata {
+            Metadata::File(file) => file.name == INDEX_FILE,
+            Metadata::Folder(_) => false,
+        });
+        if has_index {
+            StateStore::remove_directory_listing(&folder.to_string());
+            return;
+        }
+
+        let entries = Self::entries(children);
+        let html = Self::html(folder, &entries);
+        let json = serde_json::to_vec(&Listing {
+            key: folder.to_string(),
+            entries,
+        })
+        .expect("Failed to encode directory listing");
+
+        // Fails only when an asset is served at the folder key, which then takes precedence
+        let _ = StateStore::certify_directory_listing(&folder.to_string(), html.into_bytes(), json);
+    }
+
+    // Folders first, then files, each sorted by name
+    fn entries(children: Vec<Metadata>) -> Vec<Entry> {
+        let mut entries: Vec<Entry> = children
+            .into_iter()
+            .map(|metadata| match metadata {
+                Metadata::File(file) => Entry {
+                    name: file.name,
+                    key: file.key.to_string(),
+                    kind: "file",
+                    size: Some(file.size),
+                    content_type: Some(file.content_type),
+                    last_modified: file.last_modified,
+                },
+                Metadata::Folder(folder) => Entry {
+                    name: folder.name,
+                    key: folder.key.to_string(),
+                    kind: "folder",
+                    size: None,
+                    content_type: None,
+                    last_modified: folder.last_modified,
+                },
+            })
+            .collect();
+
+        entries.sort_by(|a, b| {
+            a.size
+                .is_some()
+                .cmp(&b.size.is_some())
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        entries
+    }
+
+    fn html(folder: &Key, entries: &[Entry]) -> String {
+        let title = format!("Index of {}", escape_html(&folder.to_string()));
+
+        let mut rows = String::new();
+        if !folder.is_root() {
+            rows.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td><td></td></tr>\n");
+        }
+        for entry in entries {
+            let (href, name) = match entry.size {
+                Some(_) => (encode_uri_component(&entry.name), escape_html(&entry.name)),
+                None => (
+                    format!("{}/", encode_uri_component(&entry.name)),
+                    format!("{}/", escape_html(&entry.name)),
+                ),
+            };
+
+            rows.push_str(&format!(
+                "<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                href,
+                name,
+                entry.size.map(format_size).unwrap_or_default(),
+                escape_html(entry.content_type.as_deref().unwrap_or_default()),
+                format_time(entry.last_modified),
+            ));
+        }
+
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n</head>\n<body>\n<h1>{title}</h1>\n<table>\n<tr><th>Name</th><th>Size</th><th>Type</th><th>Modified</th></tr>\n{rows}</table>\n</body>\n</html>\n"
+        )
+    }
+}
+
+#[derive(Serialize)]
+struct Listing {
+    key: String,
+    entries: Vec<Entry>,
+}
+
+#[derive(Serialize)]
+struct Entry {
+    name: String,
+    key: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    size: Option<u64>,
+    content_type: Option<String>,
+    // Nanoseconds since the epoch
+    last_modified: u64,
+}
+
+fn escape_html(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '&' => "&amp;".to_string(),
+            '<' => "&lt;".to_string(),
+            '>' => "&gt;".to_string(),
+            '"' => "&quot;".to_string(),
+            '\'' => "&#39;".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+// Percent-encode everything but unreserved characters, names may contain '#', '?' or '%'
+fn encode_uri_component(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", size, UNITS[unit]),
+    }
+}
+
+fn format_time(nanos: u64) -> String {
+    match OffsetDateTime::from_unix_timestamp_nanos(nanos as i128) {
+        Ok(time) => format!(
+            "{}-{:02}-{:02} {:02}:{:02} UTC",
+            time.year(),
+            time.month() as u8,
+            time.day(),
+            time.hour(),
+            time.minute()
+        ),
+        Err(_) => String::new(),
+    }
+}
@@ -1,12 +1,26 @@
 pub mod api;
+pub mod listing;
+pub mod search;
 mod store;
+pub mod thumbnails;
+pub mod trash;
 
+use self::thumbnails::Thumbnail;
 use crate::asset_certification::types::rc_bytes::RcBytes;
 use candid::{CandidType, Decode, Deserialize, Encode};
-use ic_cdk::api::time;
 use ic_stable_structures::{storable::Bound, Storable};
 use std::{borrow::Cow, collections::HashMap, vec::IntoIter};
 
+// Keys are stored in bounded stable structures, leave room for the Candid header
+const MAX_KEY_LENGTH: usize = 900;
+
+// File metadata holds the key up to three times (key, name and extension) next to the
+// content type, both limits keep it within the bound of `Metadata`
+const MAX_CONTENT_TYPE_LENGTH: usize = 256;
+
+// Deleted files and folders are moved here, see `trash::Trash`
+const TRASH_FOLDER: &str = "/.trash/";
+
 #[derive(CandidType, Deserialize, Clone, Ord, PartialOrd, PartialEq, Eq)]
 pub struct Key(String);
 
@@ -34,6 +48,22 @@ impl Key {
         None
     }
 
+    pub fn extension(&self) -> String {
+        self.file_name()
+            .and_then(|name| name.rsplit_once('.').map(|(_, ext)| ext.to_string()))
+            .unwrap_or_default()
+    }
+
+    // Last path segment, for "/photos/2023/" this is "2023"
+    pub fn name(&self) -> String {
+        self.0
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    }
+
     pub fn iter_dir_names(&self) -> IntoIter<String> {
         let mut path = self
             .0
@@ -47,6 +77,52 @@ impl Key {
 
         path.into_iter()
     }
+
+    /*
+    Keys of all folders containing this key, starting with the root folder.
+    "/a/b/c.txt" -> ["/", "/a/", "/a/b/"]
+    */
+    pub fn ancestors(&self) -> Vec<Key> {
+        let path = self.0.trim_end_matches('/');
+
+        path.match_indices('/')
+            .map(|(i, _)| Key::new(&path[..=i]))
+            .collect()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0 == "/"
+    }
+
+    pub fn in_trash(&self) -> bool {
+        self.0.starts_with(TRASH_FOLDER)
+    }
+
+    // Whether `other` is a strict descendant of this folder
+    pub fn contains(&self, other: &Key) -> bool {
+        self.is_dir() && other.0.len() > self.0.len() && other.0.starts_with(&self.0)
+    }
+
+    // Replace the `from` folder prefix of this key with `to`
+    pub fn rebase(&self, from: &Key, to: &Key) -> Key {
+        Key(format!("{}{}", to.0, &self.0[from.0.len()..]))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.0.starts_with('/') {
+            return Err(format!("Key must start with '/': {}", self.0));
+        }
+
+        if self.0.len() > MAX_KEY_LENGTH {
+            return Err(format!("Key exceeds {} bytes: {}", MAX_KEY_LENGTH, self.0));
+        }
+
+        if self.0.trim_end_matches('/').contains("//") {
+            return Err(format!("Key contains an empty path segment: {}", self.0));
+        }
+
+        Ok(())
+    }
 }
 
 impl Storable for Key {
@@ -68,20 +144,32 @@ pub type File = RcBytes;
 pub type Name = String;
 
 #[derive(CandidType, Deserialize, Clone)]
-enum Metadata {
+pub enum Metadata {
     File(FileMetadata),
     Folder(FolderMetadata),
 }
 
 impl Metadata {
-    pub fn new_root_folder_metadata() -> Self {
+    pub fn new_folder_metadata(key: &Key, time: u64) -> Self {
+        let name = match key.0.as_str() {
+            "/" => "__root__".to_string(),
+            _ => key.name(),
+        };
+
         Metadata::Folder(FolderMetadata {
-            key: Key::new("/"),
-            name: "__root__".to_string(),
-            created: time(),
-            last_modified: time(),
+            key: key.clone(),
+            name,
+            created: time,
+            last_modified: time,
         })
     }
+
+    pub fn key(&self) -> &Key {
+        match self {
+            Metadata::File(file) => &file.key,
+            Metadata::Folder(folder) => &folder.key,
+        }
+    }
 }
 
 #[derive(CandidType, Deserialize, Clone)]
@@ -95,10 +183,12 @@ pub struct FileMetadata {
     pub last_modified: u64,
     pub last_accessed: u64,
     pub sha256: Vec<u8>,
+    // Unset for files stored before thumbnails were rendered
+    pub thumbnails: Option<Vec<Thumbnail>>,
 }
 
 #[derive(CandidType, Deserialize, Clone)]
-struct FolderMetadata {
+pub struct FolderMetadata {
     pub key: Key,
     pub name: String,
     pub created: u64,
@@ -109,3 +199,81 @@ enum Node {
     File(Key),
     Folder(Key, HashMap<Name, Node>),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(keys: Vec<Key>) -> Vec<String> {
+        keys.iter().map(Key::to_string).collect()
+    }
+
+    #[test]
+    fn splits_names_and_extensions() {
+        let file = Key::new("/photos/2023/cat.tar.gz");
+        assert!(file.is_file());
+        assert_eq!(file.file_name(), Some("cat.tar.gz".to_string()));
+        assert_eq!(file.extension(), "gz");
+        assert_eq!(file.name(), "cat.tar.gz");
+
+        let folder = Key::new("/photos/2023/");
+        assert!(folder.is_dir());
+        assert_eq!(folder.file_name(), None);
+        assert_eq!(folder.extension(), "");
+        assert_eq!(folder.name(), "2023");
+
+        assert_eq!(Key::new("/README").extension(), "");
+    }
+
+    #[test]
+    fn lists_ancestors_from_the_root() {
+        assert_eq!(
+            keys(Key::new("/a/b/c.txt").ancestors()),
+            vec!["/", "/a/", "/a/b/"]
+        );
+        assert_eq!(keys(Key::new("/a/b/").ancestors()), vec!["/", "/a/"]);
+        assert!(Key::new("/").ancestors().is_empty());
+    }
+
+    #[test]
+    fn contains_strict_descendants_only() {
+        let folder = Key::new("/a/");
+
+        assert!(folder.contains(&Key::new("/a/b.txt")));
+        assert!(folder.contains(&Key::new("/a/b/c/")));
+        assert!(!folder.contains(&folder));
+        assert!(!folder.contains(&Key::new("/ab.txt")));
+        assert!(!Key::new("/a").contains(&Key::new("/a/b.txt")));
+    }
+
+    #[test]
+    fn rebases_keys_onto_a_new_folder() {
+        let key = Key::new("/a/b/c.txt");
+
+        assert_eq!(
+            key.rebase(&Key::new("/a/"), &Key::new("/x/y/")).to_string(),
+            "/x/y/b/c.txt"
+        );
+        assert_eq!(key.rebase(&key, &Key::new("/d.txt")).to_string(), "/d.txt");
+    }
+
+    #[test]
+    fn recognizes_the_root_and_the_trash() {
+        assert!(Key::new("/").is_root());
+        assert!(!Key::new("/a/").is_root());
+        assert!(Key::new("/.trash/1/a.txt").in_trash());
+        assert!(!Key::new("/.trashcan/a.txt").in_trash());
+    }
+
+    #[test]
+    fn validates_keys() {
+        assert!(Key::new("/a/b.txt").validate().is_ok());
+        assert!(Key::new("/a/").validate().is_ok());
+        assert!(Key::new("a/b.txt").validate().is_err());
+        assert!(Key::new("/a//b.txt").validate().is_err());
+
+        let long = format!("/{}", "a".repeat(MAX_KEY_LENGTH));
+        assert!(Key::new(&long).validate().is_err());
+        assert!(Key::new(&long[..MAX_KEY_LENGTH]).validate().is_ok());
+    }
+}
@@ -0,0 +1,541 @@
+use super::{
+    store::{FileDataStore, MetadataStore},
+    File, FileMetadata, Key, Metadata,
+};
+use crate::web3disk::stores::{MemoryManagerStore, MEM_ID_SEARCH_FILES, MEM_ID_SEARCH_TERMS};
+use candid::{CandidType, Decode, Deserialize, Encode};
+use ic_stable_structures::{
+    memory_manager::VirtualMemory, storable::Bound, BTreeMap as StableBTree, DefaultMemoryImpl,
+    Storable,
+};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{BTreeSet, HashSet},
+};
+
+const MAX_SEARCH_PAGE_SIZE: u32 = 100;
+
+// Only the content of small text files is indexed
+const MAX_INDEXED_CONTENT_SIZE: u64 = 64 * 1024;
+const MAX_INDEXED_WORDS: usize = 2_000;
+const MIN_WORD_LENGTH: usize = 2;
+const MAX_WORD_LENGTH: usize = 32;
+
+thread_local! {
+    static TERMS: RefCell<StableBTree<TermKey, (), VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTree::init(MemoryManagerStore::get(MEM_ID_SEARCH_TERMS))
+    );
+
+    // The terms added for each file, to remove them again
+    static INDEXED_FILES: RefCell<StableBTree<Key, IndexedFile, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTree::init(MemoryManagerStore::get(MEM_ID_SEARCH_FILES))
+    );
+}
+
+#[derive(CandidType, Deserialize, Clone, Ord, PartialOrd, PartialEq, Eq)]
+enum Term {
+    // Lowercase file name
+    Name(String),
+    // Lowercase word of the file content
+    Word(String),
+}
+
+impl Term {
+    fn starts_with(&self, prefix: &Term) -> bool {
+        match (self, prefix) {
+            (Term::Name(text), Term::Name(prefix)) | (Term::Word(text), Term::Word(prefix)) => {
+                text.starts_with(prefix.as_str())
+            }
+            _ => false,
+        }
+    }
+}
+
+// Files sharing a term are adjacent in the tree, so prefix lookups are range scans
+#[derive(CandidType, Deserialize, Clone, Ord, PartialOrd, PartialEq, Eq)]
+struct TermKey {
+    term: Term,
+    key: Key,
+}
+
+impl Storable for TermKey {
+    const BOUND: ic_stable_structures::storable::Bound = Bound::Bounded {
+        max_size: 2048,
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self).expect("Failed to encode TermKey"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(&bytes, Self).expect("Failed to decode TermKey")
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+struct IndexedFile {
+    name: String,
+    words: Vec<String>,
+}
+
+impl Storable for IndexedFile {
+    const BOUND: ic_stable_structures::storable::Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self).expect("Failed to encode IndexedFile"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(&bytes, Self).expect("Failed to decode IndexedFile")
+    }
+}
+
+/*
+All criteria are optional and must all match. Names and words are matched case-insensitively.
+*/
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct SearchArgs {
+    // Matched anywhere in the file name, or only at its start with `name_prefix`
+    pub name: Option<String>,
+    pub name_prefix: Option<bool>,
+    // Words that must all occur in the content of an indexed text file, each may be incomplete
+    pub text: Option<String>,
+    // Only files below this folder
+    pub folder: Option<String>,
+    pub extensions: Option<Vec<String>>,
+    // "image/" matches all images
+    pub content_types: Option<Vec<String>>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    // Nanoseconds since the epoch, both inclusive
+    pub modified_after: Option<u64>,
+    pub modified_before: Option<u64>,
+    // Only return files with keys after this one, the first page is returned when unset
+    pub after: Option<String>,
+    pub limit: u32,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct SearchPage {
+    pub files: Vec<FileMetadata>,
+    // Pass as `after` to fetch the next page
+    pub next: Option<String>,
+}
+
+/*
+Index of file names, and of the words in small text files, kept in stable memory.
+Results are ordered by key.
+*/
+pub struct SearchIndex;
+
+impl SearchIndex {
+    pub fn search(args: SearchArgs) -> Result<SearchPage, String> {
+        let limit = args.limit.clamp(1, MAX_SEARCH_PAGE_SIZE) as usize;
+        let name = args.name.as_ref().map(|name| name.to_lowercase());
+        let after = args.after.as_ref().map(|after| Key::new(after));
+
+        if let Some(folder) = &args.folder {
+            if !Key::new(folder).is_dir() {
+                return Err(format!("Folder key must end with '/': {}", folder));
+            }
+        }
+
+        // Use the index where possible, otherwise scan the metadata of all files
+        let keys = match (&args.text, &name) {
+            (Some(text), _) => {
+                let words = words(text);
+                if words.is_empty() {
+                    return Err("Search text contains no words".to_string());
+                }
+                Some(Self::matching_all(&words))
+            }
+            (None, Some(name)) if args.name_prefix.unwrap_or(false) => {
+                Some(Self::matching(&Term::Name(name.clone())))
+            }
+            _ => None,
+        };
+        let candidates: Box<dyn Iterator<Item = FileMetadata>> = match keys {
+            Some(keys) => Box::new(keys.into_iter().filter_map(|key| file_metadata(&key))),
+            None => Box::new(MetadataStore::list_files().into_iter()),
+        };
+
+        let mut files: Vec<FileMetadata> = vec![];
+        let mut next = None;
+        for file in candidates {
+            if after.as_ref().map_or(false, |after| &file.key <= after)
+                || !matches(&args, name.as_deref(), &file)
+            {
+                continue;
+            }
+
+            if files.len() == limit {
+                next = files.last().map(|last| last.key.to_string());
+                break;
+            }
+            files.push(file);
+        }
+
+        Ok(SearchPage { files, next })
+    }
+
+    /*
+    Index a stored file, replacing what was indexed for an earlier version of it.
+    The content is only indexed for small text files.
+    */
+    pub fn update(file: &FileMetadata, chunks: &[File]) {
+        Self::remove(&file.key);
+
+        let words = if is_indexed_content(file) {
+            let content: Vec<u8> = chunks
+                .iter()
+                .flat_map(|chunk| chunk.iter().copied())
+                .collect();
+            let mut words = words(&String::from_utf8_lossy(&content));
+            words.truncate(MAX_INDEXED_WORDS);
+            words
+        } else {
+            vec![]
+        };
+
+        Self::insert(file, words);
+    }
+
+    /*
+    Index a moved file at its new key with the words indexed at `from`, without reading its
+    content. A file that only becomes indexable by its new extension is indexed by name.
+    */
+    pub fn rename(from: &Key, file: &FileMetadata) {
+        let words = INDEXED_FILES
+            .with(|tree| tree.borrow().get(from))
+            .filter(|_| is_indexed_content(file))
+            .map_or(vec![], |indexed| indexed.words);
+
+        Self::remove(from);
+        Self::remove(&file.key);
+        Self::insert(file, words);
+    }
+
+    pub fn remove(key: &Key) {
+        let Some(indexed) = INDEXED_FILES.with(|tree| tree.borrow_mut().remove(key)) else {
+            return;
+        };
+
+        TERMS.with(|tree| {
+            let mut tree = tree.borrow_mut();
+            for term in indexed.terms() {
+                tree.remove(&TermKey {
+                    term,
+                    key: key.clone(),
+                });
+            }
+        });
+    }
+
+    /*
+    Index all files if the index is empty, after an upgrade from a version without it.
+    */
+    pub fn restore() {
+        if INDEXED_FILES.with(|tree| !tree.borrow().is_empty()) {
+            return;
+        }
+
+        for file in MetadataStore::list_files() {
+            let chunks = if is_indexed_content(&file) {
+                FileDataStore::get(&file).unwrap_or_default()
+            } else {
+                vec![]
+            };
+            Self::update(&file, &chunks);
+        }
+    }
+
+    fn insert(file: &FileMetadata, words: Vec<String>) {
+        let indexed = IndexedFile {
+            name: file.name.to_lowercase(),
+            words,
+        };
+
+        TERMS.with(|tree| {
+            let mut tree = tree.borrow_mut();
+            for term in indexed.terms() {
+                tree.insert(
+                    TermKey {
+                        term,
+                        key: file.key.clone(),
+                    },
+                    (),
+                );
+            }
+        });
+        INDEXED_FILES.with(|tree| tree.borrow_mut().insert(file.key.clone(), indexed));
+    }
+
+    // Keys of files with a term starting with the text of `prefix`
+    fn matching(prefix: &Term) -> BTreeSet<Key> {
+        let start = TermKey {
+            term: prefix.clone(),
+            key: Key::new(""),
+        };
+
+        TERMS.with(|tree| {
+            tree.borrow()
+                .range(start..)
+                .take_while(|(term_key, _)| term_key.term.starts_with(prefix))
+                .map(|(term_key, _)| term_key.key)
+                .collect()
+        })
+    }
+
+    fn matching_all(words: &[String]) -> BTreeSet<Key> {
+        let mut keys: Option<BTreeSet<Key>> = None;
+        for word in words {
+            let matching = Self::matching(&Term::Word(word.clone()));
+            keys = Some(match keys {
+                Some(keys) => keys.intersection(&matching).cloned().collect(),
+                None => matching,
+            });
+        }
+        keys.unwrap_or_default()
+    }
+}
+
+impl IndexedFile {
+    fn terms(&self) -> Vec<Term> {
+        let mut terms = vec![Term::Name(self.name.clone())];
+        terms.extend(self.words.iter().map(|word| Term::Word(word.clone())));
+        terms
+    }
+}
+
+fn file_metadata(key: &Key) -> Option<FileMetadata> {
+    match MetadataStore::get(key) {
+        Some(Metadata::File(file)) => Some(file),
+        _ => None,
+    }
+}
+
+fn matches(args: &SearchArgs, name: Option<&str>, file: &FileMetadata) -> bool {
+    if file.key.in_trash() {
+        return false;
+    }
+    if let Some(name) = name {
+        if !file.name.to_lowercase().contains(name) {
+            return false;
+        }
+    }
+    if let Some(folder) = &args.folder {
+        if !Key::new(folder).contains(&file.key) {
+            return false;
+        }
+    }
+    if let Some(extensions) = &args.extensions {
+        if !extensions
+            .iter()
+            .any(|extension| extension.eq_ignore_ascii_case(&file.extension))
+        {
+            return false;
+        }
+    }
+    if let Some(content_types) = &args.content_types {
+        if !content_types
+            .iter()
+            .any(|content_type| file.content_type.starts_with(content_type.as_str()))
+        {
+            return false;
+        }
+    }
+
+    args.min_size.map_or(true, |min| file.size >= min)
+        && args.max_size.map_or(true, |max| file.size <= max)
+        && args
+            .modified_after
+            .map_or(true, |after| file.last_modified >= after)
+        && args
+            .modified_before
+            .map_or(true, |before| file.last_modified <= before)
+}
+
+fn is_indexed_content(file: &FileMetadata) -> bool {
+    let is_text = file.content_type.starts_with("text/")
+        || ["txt", "md", "markdown"].contains(&file.extension.to_lowercase().as_str());
+
+    is_text && file.size <= MAX_INDEXED_CONTENT_SIZE
+}
+
+// Distinct lowercase words in order of first occurrence
+fn words(text: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| (MIN_WORD_LENGTH..=MAX_WORD_LENGTH).contains(&word.chars().count()))
+        .map(|word| word.to_lowercase())
+        .filter(|word| seen.insert(word.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{types::StoreArg, web3disk::stores::fs::api::FsStore};
+    use serde_bytes::ByteBuf;
+
+    fn store(key: &str, content_type: &str, content: &str) {
+        FsStore::store(
+            StoreArg {
+                key: key.to_string(),
+                content_type: content_type.to_string(),
+                content_encoding: "identity".to_string(),
+                content: ByteBuf::from(content.as_bytes().to_vec()),
+                sha256: None,
+                aliased: None,
+            },
+            0,
+        )
+        .unwrap();
+    }
+
+    fn args() -> SearchArgs {
+        SearchArgs {
+            name: None,
+            name_prefix: None,
+            text: None,
+            folder: None,
+            extensions: None,
+            content_types: None,
+            min_size: None,
+            max_size: None,
+            modified_after: None,
+            modified_before: None,
+            after: None,
+            limit: MAX_SEARCH_PAGE_SIZE,
+        }
+    }
+
+    fn keys(page: &SearchPage) -> Vec<String> {
+        page.files.iter().map(|file| file.key.to_string()).collect()
+    }
+
+    #[test]
+    fn pages_results_by_key() {
+        for name in ["a", "b", "c", "d", "e"] {
+            store(&format!("/notes/{}.txt", name), "text/plain", name);
+        }
+
+        let page = SearchIndex::search(SearchArgs {
+            name: Some(".TXT".to_string()),
+            limit: 2,
+            ..args()
+        })
+        .unwrap();
+        assert_eq!(keys(&page), vec!["/notes/a.txt", "/notes/b.txt"]);
+        assert_eq!(page.next, Some("/notes/b.txt".to_string()));
+
+        let page = SearchIndex::search(SearchArgs {
+            after: page.next,
+            limit: 3,
+            ..args()
+        })
+        .unwrap();
+        assert_eq!(
+            keys(&page),
+            vec!["/notes/c.txt", "/notes/d.txt", "/notes/e.txt"]
+        );
+        assert_eq!(page.next, None);
+    }
+
+    #[test]
+    fn matches_words_and_name_prefixes() {
+        store("/todo.md", "text/markdown", "Buy Milk and bread");
+        store("/shopping.txt", "text/plain", "milk, eggs");
+        store("/milk.png", "image/png", "milk");
+
+        let page = SearchIndex::search(SearchArgs {
+            text: Some("MIL".to_string()),
+            ..args()
+        })
+        .unwrap();
+        assert_eq!(keys(&page), vec!["/shopping.txt", "/todo.md"]);
+
+        let page = SearchIndex::search(SearchArgs {
+            text: Some("milk bread".to_string()),
+            ..args()
+        })
+        .unwrap();
+        assert_eq!(keys(&page), vec!["/todo.md"]);
+
+        let page = SearchIndex::search(SearchArgs {
+            name: Some("MI".to_string()),
+            name_prefix: Some(true),
+            ..args()
+        })
+        .unwrap();
+        assert_eq!(keys(&page), vec!["/milk.png"]);
+
+        assert!(SearchIndex::search(SearchArgs {
+            text: Some("a !".to_string()),
+            ..args()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn filters_by_folder_type_and_size() {
+        store("/docs/a.txt", "text/plain", "a");
+        store("/docs/b.png", "image/png", "bb");
+        store("/docs/deep/c.jpg", "image/jpeg", "ccc");
+        store("/d.png", "image/png", "dddd");
+
+        let page = SearchIndex::search(SearchArgs {
+            folder: Some("/docs/".to_string()),
+            content_types: Some(vec!["image/".to_string()]),
+            ..args()
+        })
+        .unwrap();
+        assert_eq!(keys(&page), vec!["/docs/b.png", "/docs/deep/c.jpg"]);
+
+        let page = SearchIndex::search(SearchArgs {
+            extensions: Some(vec!["PNG".to_string()]),
+            min_size: Some(3),
+            ..args()
+        })
+        .unwrap();
+        assert_eq!(keys(&page), vec!["/d.png"]);
+
+        assert!(SearchIndex::search(SearchArgs {
+            folder: Some("/docs".to_string()),
+            ..args()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn follows_renames_and_removals() {
+        store("/a.txt", "text/plain", "unique words");
+
+        FsStore::rename(&Key::new("/a.txt"), &Key::new("/b.txt"), 1).unwrap();
+        let page = SearchIndex::search(SearchArgs {
+            text: Some("unique".to_string()),
+            ..args()
+        })
+        .unwrap();
+        assert_eq!(keys(&page), vec!["/b.txt"]);
+
+        // Files in the trash are not found
+        FsStore::delete(&Key::new("/b.txt"), 2).unwrap();
+        let page = SearchIndex::search(SearchArgs {
+            text: Some("unique".to_string()),
+            ..args()
+        })
+        .unwrap();
+        assert!(page.files.is_empty());
+    }
+
+    #[test]
+    fn splits_distinct_words() {
+        assert_eq!(
+            words("Hello, hello world! a Größe"),
+            vec!["hello", "world", "größe"]
+        );
+    }
+}
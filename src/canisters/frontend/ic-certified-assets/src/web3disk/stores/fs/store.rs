@@ -1,53 +1,117 @@
-use super::{File, Key, Metadata};
-use crate::web3disk::stores::{MemoryManagerStore, MEM_ID_FILE_DATA, MEM_ID_METADATA};
+use super::{File, FileMetadata, Key, Metadata};
+use crate::web3disk::stores::{heap::StateStore, MemoryManagerStore, MEM_ID_METADATA};
 use candid::{Decode, Encode};
 use ic_stable_structures::{
     memory_manager::VirtualMemory, storable::Bound, BTreeMap as StableBTree, DefaultMemoryImpl,
     Storable,
 };
-use std::{borrow::Cow, cell::RefCell};
+use std::{borrow::Cow, cell::RefCell, collections::HashSet};
 
 thread_local! {
-    static FILE_DATA: RefCell<StableBTree<Key, File, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
-        StableBTree::init(MemoryManagerStore::get(MEM_ID_FILE_DATA))
-    );
-
     static METADATA: RefCell<StableBTree<Key, Metadata, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
         StableBTree::init(MemoryManagerStore::get(MEM_ID_METADATA))
     );
 }
 
-struct FileDataStore;
+/*
+File content is stored once per distinct SHA-256 and shared by all files with that content.
+It is kept together with the content of the certified assets, so a public file and its asset
+share the same chunks too.
+*/
+pub(super) struct FileDataStore;
 
 impl FileDataStore {
-    pub fn get(key: &Key) -> Option<File> {
-        FILE_DATA.with(|refcell| refcell.borrow().get(key))
+    pub fn get(file: &FileMetadata) -> Option<Vec<File>> {
+        Self::get_content(&file.sha256)
     }
 
-    pub fn insert_new(key: &Key, file: File) -> Result<(), String> {
-        FILE_DATA.with(|tree| {
-            let mut tree = tree.borrow_mut();
+    pub fn get_chunk(file: &FileMetadata, index: u32) -> Option<File> {
+        let sha256 = sha256_key(&file.sha256)?;
+        StateStore::stored_chunk(&sha256, index as usize)
+    }
 
-            if tree.contains_key(&key) {
-                return Err(format!("File already exists: {}", key.0));
-            }
+    // Content by hash, for data stored alongside files such as thumbnails
+    pub fn get_content(sha256: &[u8]) -> Option<Vec<File>> {
+        let sha256 = sha256_key(sha256)?;
+        let chunk_count = StateStore::stored_chunk_lengths(&sha256)?.len();
 
-            if let None = tree.insert(key.clone(), file) {
-                return Ok(());
-            }
+        (0..chunk_count)
+            .map(|index| StateStore::stored_chunk(&sha256, index))
+            .collect()
+    }
 
-            Err("Failed to insert file".to_string())
-        })
+    pub fn chunk_count(file: &FileMetadata) -> u32 {
+        sha256_key(&file.sha256)
+            .and_then(|sha256| StateStore::stored_chunk_lengths(&sha256))
+            .map_or(0, |lengths| lengths.len() as u32)
+    }
+
+    /*
+    Add a reference to the content with the given hash, writing the chunks only if the
+    content is not stored yet. Every call must be matched by a `remove` of a file with that hash,
+    or a `release` of the hash.
+    */
+    pub fn insert(sha256: &[u8], chunks: Vec<File>) {
+        if let Some(sha256) = sha256_key(sha256) {
+            StateStore::insert_content(sha256, chunks);
+        }
+    }
+
+    // Add a reference to content that is already stored
+    pub fn retain(sha256: &[u8]) {
+        if let Some(sha256) = sha256_key(sha256) {
+            StateStore::retain_content(&sha256);
+        }
+    }
+
+    // Drop the data of a file, the content itself goes with the last file referencing it
+    pub fn remove(file: &FileMetadata) {
+        Self::release(&file.sha256);
+    }
+
+    // Drop a reference to the content with the given hash
+    pub fn release(sha256: &[u8]) {
+        if let Some(sha256) = sha256_key(sha256) {
+            StateStore::release_content(&sha256);
+        }
+    }
+
+    // Bytes of file and thumbnail content actually stored, shared content counts once
+    pub fn stored_bytes() -> u64 {
+        let mut hashes = HashSet::new();
+        for file in MetadataStore::list_files() {
+            hashes.extend(
+                file.thumbnails
+                    .iter()
+                    .flatten()
+                    .filter_map(|thumbnail| sha256_key(&thumbnail.sha256)),
+            );
+            hashes.extend(sha256_key(&file.sha256));
+        }
+
+        hashes
+            .iter()
+            .filter_map(StateStore::stored_chunk_lengths)
+            .map(|lengths| lengths.iter().sum::<usize>() as u64)
+            .sum()
     }
 }
 
-struct MetadataStore;
+fn sha256_key(sha256: &[u8]) -> Option<[u8; 32]> {
+    sha256.try_into().ok()
+}
+
+pub(super) struct MetadataStore;
 
 impl MetadataStore {
     pub fn get(key: &Key) -> Option<Metadata> {
         METADATA.with(|refcell| refcell.borrow().get(key))
     }
 
+    pub fn contains_key(key: &Key) -> bool {
+        METADATA.with(|refcell| refcell.borrow().contains_key(key))
+    }
+
     pub fn insert_new(key: &Key, metadata: &Metadata) -> Result<(), String> {
         METADATA.with(|tree| {
             let mut tree = tree.borrow_mut();
@@ -64,26 +128,83 @@ impl MetadataStore {
         })
     }
 
+    pub fn insert(key: &Key, metadata: Metadata) {
+        METADATA.with(|tree| tree.borrow_mut().insert(key.clone(), metadata));
+    }
+
+    pub fn remove(key: &Key) -> Option<Metadata> {
+        METADATA.with(|tree| tree.borrow_mut().remove(key))
+    }
+
     pub fn list_keys() -> Vec<Key> {
         METADATA.with(|tree| tree.borrow().iter().map(|(key, _)| key.clone()).collect())
     }
-}
 
-impl Storable for File {
-    const BOUND: ic_stable_structures::storable::Bound = Bound::Unbounded;
+    // All files and folders below `folder`, keys sharing the folder prefix are adjacent in the tree
+    pub fn list_descendants(folder: &Key) -> Vec<Metadata> {
+        METADATA.with(|tree| {
+            tree.borrow()
+                .range(folder.clone()..)
+                .skip_while(|(key, _)| key == folder)
+                .take_while(|(key, _)| folder.contains(key))
+                .map(|(_, metadata)| metadata)
+                .collect()
+        })
+    }
 
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
-        Cow::Owned(Encode!(&self).expect("Failed to encode File"))
+    /*
+    Files and folders directly in `folder`. The contents of a subfolder sort right after it,
+    so the scan resumes past them at the first key after the subfolder prefix.
+    */
+    pub fn list_children(folder: &Key) -> Vec<Metadata> {
+        METADATA.with(|tree| {
+            let tree = tree.borrow();
+            let mut children = vec![];
+            let mut start = folder.clone();
+
+            'scan: loop {
+                for (key, metadata) in tree.range(start.clone()..) {
+                    if key == *folder {
+                        continue;
+                    }
+                    if !folder.contains(&key) {
+                        break 'scan;
+                    }
+
+                    let name = &key.0[folder.0.len()..];
+                    match name.find('/') {
+                        Some(i) if i + 1 < name.len() => {
+                            // '0' follows '/', so this skips everything below the subfolder
+                            start = Key(format!("{}0", &key.0[..folder.0.len() + i]));
+                            continue 'scan;
+                        }
+                        _ => children.push(metadata),
+                    }
+                }
+                break;
+            }
+
+            children
+        })
     }
 
-    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
-        Decode!(&bytes, Self).expect("Failed to decode File")
+    pub fn list_files() -> Vec<FileMetadata> {
+        METADATA.with(|tree| {
+            tree.borrow()
+                .iter()
+                .filter_map(|(_, metadata)| match metadata {
+                    Metadata::File(file) => Some(file),
+                    Metadata::Folder(_) => None,
+                })
+                .collect()
+        })
     }
 }
 
 impl Storable for Metadata {
+    // Holds the key twice (key and name), so allow for two maximum length keys
     const BOUND: ic_stable_structures::storable::Bound = Bound::Bounded {
-        max_size: 1024,
+        max_size: 4096,
         is_fixed_size: false,
     };
 
@@ -95,3 +216,138 @@ impl Storable for Metadata {
         Decode!(&bytes, Self).expect("Failed to decode FileMetadata")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::web3disk::stores::fs::thumbnails::Thumbnail;
+    use serde_bytes::ByteBuf;
+
+    fn chunk(bytes: &[u8]) -> File {
+        File::from(ByteBuf::from(bytes.to_vec()))
+    }
+
+    fn file(key: &str, sha256: u8, thumbnails: Option<Vec<Thumbnail>>) -> FileMetadata {
+        let key = Key::new(key);
+        FileMetadata {
+            name: key.name(),
+            extension: key.extension(),
+            key,
+            content_type: "text/plain".to_string(),
+            size: 0,
+            created: 0,
+            last_modified: 0,
+            last_accessed: 0,
+            sha256: vec![sha256; 32],
+            thumbnails,
+        }
+    }
+
+    fn insert_file(file: &FileMetadata) {
+        MetadataStore::insert(&file.key, Metadata::File(file.clone()));
+    }
+
+    #[test]
+    fn stores_shared_content_once() {
+        let a = file("/a.txt", 1, None);
+        let b = file("/b.txt", 1, None);
+
+        FileDataStore::insert(&a.sha256, vec![chunk(b"hello "), chunk(b"world")]);
+        FileDataStore::insert(&b.sha256, vec![chunk(b"hello "), chunk(b"world")]);
+        insert_file(&a);
+        insert_file(&b);
+
+        assert_eq!(FileDataStore::chunk_count(&a), 2);
+        assert_eq!(FileDataStore::get_chunk(&b, 1).unwrap().as_ref(), b"world");
+        assert_eq!(FileDataStore::stored_bytes(), 11);
+
+        // The content goes with the last file referencing it
+        FileDataStore::remove(&a);
+        assert_eq!(FileDataStore::get(&b).unwrap().len(), 2);
+        FileDataStore::remove(&b);
+        assert!(FileDataStore::get(&b).is_none());
+        assert_eq!(FileDataStore::chunk_count(&b), 0);
+    }
+
+    #[test]
+    fn keeps_retained_content_until_released() {
+        let sha256 = [2; 32];
+
+        FileDataStore::insert(&sha256, vec![chunk(b"thumbnail")]);
+        FileDataStore::retain(&sha256);
+
+        FileDataStore::release(&sha256);
+        assert!(FileDataStore::get_content(&sha256).is_some());
+        FileDataStore::release(&sha256);
+        assert!(FileDataStore::get_content(&sha256).is_none());
+    }
+
+    #[test]
+    fn ignores_malformed_hashes() {
+        FileDataStore::insert(&[3; 8], vec![chunk(b"data")]);
+
+        assert!(FileDataStore::get_content(&[3; 8]).is_none());
+        assert!(FileDataStore::get_chunk(&file("/a.txt", 3, None), 0).is_none());
+    }
+
+    #[test]
+    fn counts_file_and_thumbnail_bytes() {
+        let thumbnail = Thumbnail {
+            size: 128,
+            sha256: vec![5; 32],
+            length: 3,
+        };
+        let a = file("/a.png", 4, Some(vec![thumbnail]));
+
+        FileDataStore::insert(&a.sha256, vec![chunk(b"image")]);
+        FileDataStore::insert(&[5; 32], vec![chunk(b"jpg")]);
+        insert_file(&a);
+
+        assert_eq!(FileDataStore::stored_bytes(), 8);
+    }
+
+    #[test]
+    fn lists_descendants_and_children_of_a_folder() {
+        for key in [
+            "/a/",
+            "/a/b.txt",
+            "/a/c/",
+            "/a/c/d.txt",
+            "/a/c0.txt",
+            "/ab.txt",
+            "/b/",
+        ] {
+            let key = Key::new(key);
+            let metadata = match key.is_dir() {
+                true => Metadata::new_folder_metadata(&key, 0),
+                false => Metadata::File(file(&key.0, 1, None)),
+            };
+            MetadataStore::insert_new(&key, &metadata).unwrap();
+        }
+
+        let keys: Vec<String> = MetadataStore::list_descendants(&Key::new("/a/"))
+            .iter()
+            .map(|metadata| metadata.key().to_string())
+            .collect();
+        assert_eq!(keys, vec!["/a/b.txt", "/a/c/", "/a/c/d.txt", "/a/c0.txt"]);
+
+        let keys: Vec<String> = MetadataStore::list_children(&Key::new("/a/"))
+            .iter()
+            .map(|metadata| metadata.key().to_string())
+            .collect();
+        assert_eq!(keys, vec!["/a/b.txt", "/a/c/", "/a/c0.txt"]);
+
+        let keys: Vec<String> = MetadataStore::list_children(&Key::new("/"))
+            .iter()
+            .map(|metadata| metadata.key().to_string())
+            .collect();
+        assert_eq!(keys, vec!["/a/", "/ab.txt", "/b/"]);
+
+        assert_eq!(MetadataStore::list_files().len(), 4);
+        assert!(MetadataStore::insert_new(
+            &Key::new("/a/"),
+            &Metadata::new_folder_metadata(&Key::new("/a/"), 0)
+        )
+        .is_err());
+    }
+}
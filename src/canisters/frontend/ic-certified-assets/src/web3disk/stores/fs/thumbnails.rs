@@ -0,0 +1,318 @@
+use super::{store::FileDataStore, File, FileMetadata, Key};
+use crate::{
+    types::DeleteAssetArguments,
+    web3disk::stores::{config::ConfigStore, heap::StateStore, sharing::SharingStore},
+};
+use candid::{CandidType, Deserialize};
+use image::io::{Limits, Reader};
+use image::{DynamicImage, ImageFormat, ImageOutputFormat};
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+use std::io::Cursor;
+
+// Longest side of the rendered thumbnails in pixels
+pub const THUMBNAIL_SIZES: [u32; 2] = [128, 512];
+
+const THUMBNAIL_CONTENT_TYPE: &str = "image/jpeg";
+const JPEG_QUALITY: u8 = 80;
+
+// Images are decoded in full and rendered within the call storing them, so larger ones are
+// skipped however small the file. This keeps a render well within the instruction limit.
+const MAX_SOURCE_DIMENSION: u32 = 4_096;
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Thumbnail {
+    pub size: u32,
+    pub sha256: Vec<u8>,
+    pub length: u64,
+}
+
+impl Thumbnail {
+    // "/photos/cat.png" has its 128px thumbnail at "/photos/cat.png.thumb-128.jpg"
+    pub fn key(file_key: &Key, size: u32) -> Key {
+        Key::new(&format!("{}.thumb-{}.jpg", file_key.0, size))
+    }
+
+    // User files cannot be stored at keys where thumbnails are served
+    pub fn is_thumbnail_key(key: &Key) -> bool {
+        THUMBNAIL_SIZES
+            .iter()
+            .any(|size| key.0.ends_with(&format!(".thumb-{}.jpg", size)))
+    }
+}
+
+/*
+JPEG thumbnails of images, rendered when an image is stored and kept with the file data.
+They are linked from the file metadata and go wherever the file goes: copies share them,
+and they are released when the file is overwritten or deleted. Public files have their
+thumbnails certified next to them, see `Thumbnail::key`.
+*/
+pub struct Thumbnails;
+
+impl Thumbnails {
+    /*
+    Render and store the thumbnails of an image within the configured size limit.
+    Files that are no image or fail to decode get none.
+    */
+    pub fn create(file: &FileMetadata, chunks: &[File]) -> Vec<Thumbnail> {
+        render(file, chunks)
+            .into_iter()
+            .map(|(size, content)| {
+                let thumbnail = Thumbnail {
+                    size,
+                    sha256: Sha256::digest(&content).to_vec(),
+                    length: content.len() as u64,
+                };
+                FileDataStore::insert(&thumbnail.sha256, vec![File::from(ByteBuf::from(content))]);
+                thumbnail
+            })
+            .collect()
+    }
+
+    // Reference the thumbnails of a file for a copy of it
+    pub fn retain(thumbnails: &[Thumbnail]) {
+        for thumbnail in thumbnails {
+            FileDataStore::retain(&thumbnail.sha256);
+        }
+    }
+
+    pub fn release(file: &FileMetadata) {
+        for thumbnail in file.thumbnails.iter().flatten() {
+            FileDataStore::release(&thumbnail.sha256);
+        }
+    }
+
+    pub fn get(file: &FileMetadata, size: u32) -> Result<File, String> {
+        let thumbnail = Self::find(file, size)
+            .ok_or_else(|| format!("No {}px thumbnail: {}", size, file.key.0))?;
+
+        content(thumbnail).ok_or_else(|| format!("Thumbnail data missing: {}", file.key.0))
+    }
+
+    /*
+    Bring the certified thumbnails of a file in line with its visibility, like
+    `FsStore::recertify` does for the file. Thumbnails of an earlier version are dropped.
+    Failing to certify a thumbnail never fails the write of the file itself.
+    */
+    pub fn certify(file: &FileMetadata) {
+        let public = SharingStore::is_public(&file.key);
+
+        for size in THUMBNAIL_SIZES {
+            let key = Thumbnail::key(&file.key, size).to_string();
+
+            let certified = Self::find(file, size)
+                .filter(|_| public)
+                .and_then(|thumbnail| Some((thumbnail, content(thumbnail)?)));
+
+            match certified {
+                Some((thumbnail, content)) => {
+                    let _ = StateStore::store_chunks(
+                        key,
+                        THUMBNAIL_CONTENT_TYPE.to_string(),
+                        &[content],
+                        thumbnail.sha256.clone(),
+                        file.last_modified,
+                    );
+                }
+                None => StateStore::delete_asset(DeleteAssetArguments { key }),
+            }
+        }
+    }
+
+    pub fn uncertify(file: &FileMetadata) {
+        for size in THUMBNAIL_SIZES {
+            StateStore::delete_asset(DeleteAssetArguments {
+                key: Thumbnail::key(&file.key, size).to_string(),
+            });
+        }
+    }
+
+    fn find(file: &FileMetadata, size: u32) -> Option<&Thumbnail> {
+        file.thumbnails
+            .iter()
+            .flatten()
+            .find(|thumbnail| thumbnail.size == size)
+    }
+}
+
+fn content(thumbnail: &Thumbnail) -> Option<File> {
+    FileDataStore::get_content(&thumbnail.sha256).and_then(|chunks| chunks.into_iter().next())
+}
+
+fn image_format(content_type: &str) -> Option<ImageFormat> {
+    match content_type {
+        "image/jpeg" => Some(ImageFormat::Jpeg),
+        "image/png" => Some(ImageFormat::Png),
+        "image/webp" => Some(ImageFormat::WebP),
+        _ => None,
+    }
+}
+
+// Encoded thumbnails by size, images smaller than a size are only re-encoded
+fn render(file: &FileMetadata, chunks: &[File]) -> Vec<(u32, Vec<u8>)> {
+    let Some(format) = image_format(&file.content_type) else {
+        return vec![];
+    };
+    if file.size > ConfigStore::thumbnail_max_source_bytes() {
+        return vec![];
+    }
+
+    let content: Vec<u8> = chunks
+        .iter()
+        .flat_map(|chunk| chunk.iter().copied())
+        .collect();
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_SOURCE_DIMENSION);
+    limits.max_image_height = Some(MAX_SOURCE_DIMENSION);
+
+    let mut reader = Reader::with_format(Cursor::new(content), format);
+    reader.limits(limits);
+    let Ok(image) = reader.decode() else {
+        return vec![];
+    };
+
+    // Each size is scaled from the next larger one, so the full image is scaled only once
+    let mut source = image;
+    let mut thumbnails: Vec<(u32, Vec<u8>)> = THUMBNAIL_SIZES
+        .iter()
+        .rev()
+        .filter_map(|&size| {
+            if source.width().max(source.height()) > size {
+                source = source.thumbnail(size, size);
+            }
+
+            // JPEG has no alpha channel
+            let mut bytes = vec![];
+            DynamicImage::ImageRgb8(source.to_rgb8())
+                .write_to(
+                    &mut Cursor::new(&mut bytes),
+                    ImageOutputFormat::Jpeg(JPEG_QUALITY),
+                )
+                .ok()?;
+
+            Some((size, bytes))
+        })
+        .collect();
+    thumbnails.reverse();
+
+    thumbnails
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgb};
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = ImageBuffer::from_pixel(width, height, Rgb([200_u8, 100, 50]));
+        let mut bytes = vec![];
+        DynamicImage::ImageRgb8(image)
+            .write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    fn file(content_type: &str, content: &[u8]) -> FileMetadata {
+        let key = Key::new("/photos/cat.png");
+        FileMetadata {
+            name: key.name(),
+            extension: key.extension(),
+            key,
+            content_type: content_type.to_string(),
+            size: content.len() as u64,
+            created: 0,
+            last_modified: 0,
+            last_accessed: 0,
+            sha256: Sha256::digest(content).to_vec(),
+            thumbnails: None,
+        }
+    }
+
+    fn chunks(content: &[u8]) -> Vec<File> {
+        vec![File::from(ByteBuf::from(content.to_vec()))]
+    }
+
+    fn dimensions(content: &[u8]) -> (u32, u32) {
+        let image = image::load_from_memory_with_format(content, ImageFormat::Jpeg).unwrap();
+        (image.width(), image.height())
+    }
+
+    #[test]
+    fn recognizes_thumbnail_keys() {
+        let key = Thumbnail::key(&Key::new("/photos/cat.png"), 128);
+
+        assert_eq!(key.to_string(), "/photos/cat.png.thumb-128.jpg");
+        assert!(Thumbnail::is_thumbnail_key(&key));
+        assert!(Thumbnail::is_thumbnail_key(&Key::new("/a.thumb-512.jpg")));
+        assert!(!Thumbnail::is_thumbnail_key(&Key::new("/a.thumb-64.jpg")));
+        assert!(!Thumbnail::is_thumbnail_key(&Key::new("/photos/cat.png")));
+    }
+
+    #[test]
+    fn renders_scaled_jpeg_thumbnails() {
+        let content = png(600, 300);
+        let mut file = file("image/png", &content);
+
+        let thumbnails = Thumbnails::create(&file, &chunks(&content));
+        let sizes: Vec<u32> = thumbnails.iter().map(|thumbnail| thumbnail.size).collect();
+        assert_eq!(sizes, THUMBNAIL_SIZES.to_vec());
+        file.thumbnails = Some(thumbnails);
+
+        let small = Thumbnails::get(&file, 128).unwrap();
+        assert_eq!(dimensions(&small), (128, 64));
+        assert_eq!(
+            file.thumbnails.as_ref().unwrap()[0].length,
+            small.len() as u64
+        );
+        assert_eq!(
+            dimensions(&Thumbnails::get(&file, 512).unwrap()),
+            (512, 256)
+        );
+        assert!(Thumbnails::get(&file, 64).is_err());
+    }
+
+    #[test]
+    fn keeps_small_images_at_their_size() {
+        let content = png(100, 50);
+        let mut file = file("image/png", &content);
+        file.thumbnails = Some(Thumbnails::create(&file, &chunks(&content)));
+
+        assert_eq!(dimensions(&Thumbnails::get(&file, 512).unwrap()), (100, 50));
+    }
+
+    #[test]
+    fn skips_files_that_are_no_images() {
+        let content = png(10, 10);
+
+        assert!(Thumbnails::create(&file("text/plain", &content), &chunks(&content)).is_empty());
+        assert!(
+            Thumbnails::create(&file("image/png", b"not a png"), &chunks(b"not a png")).is_empty()
+        );
+    }
+
+    #[test]
+    fn skips_images_above_the_dimension_limit() {
+        let content = png(MAX_SOURCE_DIMENSION + 1, 1);
+
+        assert!(Thumbnails::create(&file("image/png", &content), &chunks(&content)).is_empty());
+    }
+
+    #[test]
+    fn releases_thumbnails_with_the_last_file() {
+        let content = png(200, 200);
+        let mut file = file("image/png", &content);
+        file.thumbnails = Some(Thumbnails::create(&file, &chunks(&content)));
+
+        // A copy holds a second reference
+        Thumbnails::retain(file.thumbnails.as_ref().unwrap());
+        Thumbnails::release(&file);
+        assert!(Thumbnails::get(&file, 128).is_ok());
+
+        Thumbnails::release(&file);
+        assert_eq!(
+            Thumbnails::get(&file, 128).err(),
+            Some("Thumbnail data missing: /photos/cat.png".to_string())
+        );
+    }
+}
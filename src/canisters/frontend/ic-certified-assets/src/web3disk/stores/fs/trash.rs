@@ -0,0 +1,243 @@
+use super::{api::FsStore, Key, TRASH_FOLDER};
+use crate::web3disk::stores::{
+    audit::{AuditLog, Operation},
+    config::ConfigStore,
+    MemoryManagerStore, MEM_ID_TRASH,
+};
+use candid::{CandidType, Decode, Deserialize, Encode};
+use ic_stable_structures::{
+    memory_manager::VirtualMemory, storable::Bound, BTreeMap as StableBTree, DefaultMemoryImpl,
+    Storable,
+};
+use std::{borrow::Cow, cell::RefCell, time::Duration};
+
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+// How often items past the retention period are purged
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+thread_local! {
+    // Items keyed by an increasing id, oldest first
+    static TRASH: RefCell<StableBTree<u64, TrashItem, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTree::init(MemoryManagerStore::get(MEM_ID_TRASH))
+    );
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct TrashItem {
+    pub id: u64,
+    // Where the file or folder was deleted from, and is restored to
+    pub key: Key,
+    pub deleted: u64,
+    // Total size of the files
+    pub size: u64,
+}
+
+impl TrashItem {
+    // Where the item is kept while in the trash, "/photos/" becomes "/.trash/7/photos/"
+    fn trash_key(&self) -> Key {
+        let suffix = if self.key.is_dir() { "/" } else { "" };
+        Key::new(&format!(
+            "{}{}/{}{}",
+            TRASH_FOLDER,
+            self.id,
+            self.key.name(),
+            suffix
+        ))
+    }
+}
+
+impl Storable for TrashItem {
+    const BOUND: ic_stable_structures::storable::Bound = Bound::Bounded {
+        max_size: 1200,
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(&self).expect("Failed to encode TrashItem"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(&bytes, Self).expect("Failed to decode TrashItem")
+    }
+}
+
+/*
+Deleted files and folders are moved below `TRASH_FOLDER`, which is never served and cannot
+be written to directly. They keep their content, visibility, shares and creation times until
+they are restored, or purged by hand or once the retention period has passed.
+*/
+pub struct Trash;
+
+impl Trash {
+    pub fn put(key: &Key, time: u64) -> Result<TrashItem, String> {
+        let item = TrashItem {
+            id: TRASH.with(|tree| tree.borrow().last_key_value().map_or(0, |(id, _)| id + 1)),
+            key: key.clone(),
+            deleted: time,
+            size: FsStore::size(key)?,
+        };
+
+        FsStore::move_recursive(key, &item.trash_key(), time)?;
+        TRASH.with(|tree| tree.borrow_mut().insert(item.id, item.clone()));
+
+        Ok(item)
+    }
+
+    pub fn list() -> Vec<TrashItem> {
+        TRASH.with(|tree| tree.borrow().iter().map(|(_, item)| item).collect())
+    }
+
+    // Move an item back to where it was deleted from, which must not be taken by now
+    pub fn restore(id: u64, time: u64) -> Result<TrashItem, String> {
+        let item = Self::get(id)?;
+
+        FsStore::move_recursive(&item.trash_key(), &item.key, time)?;
+        TRASH.with(|tree| tree.borrow_mut().remove(&id));
+
+        Ok(item)
+    }
+
+    pub fn purge(id: u64) -> Result<TrashItem, String> {
+        let item = Self::get(id)?;
+
+        FsStore::delete_permanently(&item.trash_key())?;
+        TRASH.with(|tree| tree.borrow_mut().remove(&id));
+
+        Ok(item)
+    }
+
+    pub fn empty() -> Result<(), String> {
+        for item in Self::list() {
+            Self::purge(item.id)?;
+        }
+
+        Ok(())
+    }
+
+    // Purge all items deleted longer ago than the retention period
+    pub fn purge_expired(time: u64) -> Vec<TrashItem> {
+        let retention = ConfigStore::trash_retention_days().saturating_mul(NANOS_PER_DAY);
+
+        Self::list()
+            .into_iter()
+            .take_while(|item| item.deleted.saturating_add(retention) <= time)
+            .filter_map(|item| Self::purge(item.id).ok())
+            .collect()
+    }
+
+    fn get(id: u64) -> Result<TrashItem, String> {
+        TRASH
+            .with(|tree| tree.borrow().get(&id))
+            .ok_or_else(|| format!("Not in the trash: {}", id))
+    }
+}
+
+pub fn start_trash_purge_timer() {
+    ic_cdk_timers::set_timer_interval(PURGE_INTERVAL, || {
+        let time = ic_cdk::api::time();
+
+        for item in Trash::purge_expired(time) {
+            AuditLog::record(
+                ic_cdk::api::id(),
+                time,
+                Operation::PurgeFromTrash,
+                Some(item.key.to_string()),
+            );
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{types::StoreArg, web3disk::stores::fs::Metadata};
+    use serde_bytes::ByteBuf;
+
+    fn store(key: &str, content: &[u8]) {
+        FsStore::store(
+            StoreArg {
+                key: key.to_string(),
+                content_type: "text/plain".to_string(),
+                content_encoding: "identity".to_string(),
+                content: ByteBuf::from(content.to_vec()),
+                sha256: None,
+                aliased: None,
+            },
+            0,
+        )
+        .unwrap();
+    }
+
+    fn exists(key: &str) -> bool {
+        FsStore::stat(&Key::new(key)).is_ok()
+    }
+
+    #[test]
+    fn restores_items_to_where_they_were_deleted() {
+        store("/photos/cat.txt", b"meow");
+
+        let item = Trash::put(&Key::new("/photos/"), 5).unwrap();
+        assert_eq!(item.size, 4);
+        assert_eq!(item.trash_key().to_string(), "/.trash/0/photos/");
+        assert!(!exists("/photos/cat.txt"));
+        assert!(exists("/.trash/0/photos/cat.txt"));
+
+        let restored = Trash::restore(item.id, 6).unwrap();
+        assert_eq!(restored.key.to_string(), "/photos/");
+        assert!(Trash::list().is_empty());
+
+        // Creation times survive the round trip
+        match FsStore::stat(&Key::new("/photos/cat.txt")).unwrap() {
+            Metadata::File(file) => assert_eq!(file.created, 0),
+            Metadata::Folder(_) => panic!("Expected a file"),
+        }
+        assert!(Trash::restore(item.id, 7).is_err());
+    }
+
+    #[test]
+    fn does_not_restore_over_newer_files() {
+        store("/a.txt", b"old");
+        let item = Trash::put(&Key::new("/a.txt"), 1).unwrap();
+        store("/a.txt", b"new");
+
+        assert!(Trash::restore(item.id, 2).is_err());
+        assert_eq!(Trash::list().len(), 1);
+    }
+
+    #[test]
+    fn purges_content_for_good() {
+        store("/a.txt", b"hello");
+        store("/b.txt", b"world");
+        Trash::put(&Key::new("/a.txt"), 1).unwrap();
+        Trash::put(&Key::new("/b.txt"), 1).unwrap();
+
+        Trash::purge(0).unwrap();
+        assert!(!exists("/.trash/0/a.txt"));
+        assert_eq!(FsStore::stored_bytes(), 5);
+        assert!(Trash::purge(0).is_err());
+
+        Trash::empty().unwrap();
+        assert!(Trash::list().is_empty());
+        assert_eq!(FsStore::stored_bytes(), 0);
+    }
+
+    #[test]
+    fn purges_items_past_the_retention_period() {
+        ConfigStore::set_trash_retention_days(2);
+        store("/a.txt", b"a");
+        store("/b.txt", b"b");
+        Trash::put(&Key::new("/a.txt"), 0).unwrap();
+        Trash::put(&Key::new("/b.txt"), NANOS_PER_DAY).unwrap();
+
+        assert!(Trash::purge_expired(2 * NANOS_PER_DAY - 1).is_empty());
+
+        let purged = Trash::purge_expired(2 * NANOS_PER_DAY);
+        assert_eq!(purged.len(), 1);
+        assert_eq!(purged[0].key.to_string(), "/a.txt");
+        assert_eq!(Trash::list()[0].key.to_string(), "/b.txt");
+
+        assert_eq!(Trash::purge_expired(3 * NANOS_PER_DAY).len(), 1);
+        assert!(Trash::list().is_empty());
+    }
+}
@@ -1,18 +1,31 @@
+use super::{MemoryManagerStore, MEM_ID_ASSETS, MEM_ID_ASSET_CHUNKS, MEM_ID_ASSET_CONTENTS};
 use crate::{
     asset_certification::types::http::{
         CallbackFunc, HttpRequest, HttpResponse, StreamingCallbackHttpResponse,
         StreamingCallbackToken,
     },
+    asset_certification::types::rc_bytes::RcBytes,
+    asset_store::AssetMemories,
     state_machine::State,
-    types::{Permission, SetAssetPropertiesArguments, StoreArg},
+    types::{
+        BatchId, BatchOperation, ChunkId, CommitBatchArguments, CreateAssetArguments,
+        CreateChunkArg, DeleteAssetArguments, DeleteBatchArguments, Permission,
+        SetAssetContentArguments, SetAssetPropertiesArguments, StoreArg,
+    },
 };
 use candid::Principal;
 use ic_cdk::{caller, trap};
 use ic_certification::Hash;
+use serde_bytes::ByteBuf;
 use std::cell::RefCell;
 
 thread_local! {
-    static STATE: RefCell<State> = RefCell::new(State::default());
+    // Assets are kept in stable memory and survive upgrades, permissions and batches do not
+    static STATE: RefCell<State> = RefCell::new(State::with_stable_memory(AssetMemories {
+        assets: MemoryManagerStore::get(MEM_ID_ASSETS),
+        contents: MemoryManagerStore::get(MEM_ID_ASSET_CONTENTS),
+        chunks: MemoryManagerStore::get(MEM_ID_ASSET_CHUNKS),
+    }));
 }
 
 pub struct StateStore;
@@ -26,14 +39,171 @@ impl StateStore {
         STATE.with(|s| s.borrow_mut().grant_permission(principal, permission));
     }
 
+    pub fn revoke_permission(principal: Principal, permission: &Permission) {
+        STATE.with(|s| s.borrow_mut().revoke_permission(principal, permission));
+    }
+
+    #[cfg(test)]
+    pub fn has_permission(principal: Principal, permission: &Permission) -> bool {
+        STATE.with(|s| s.borrow().has_permission(&principal, permission))
+    }
+
     pub fn store(arg: StoreArg, time: u64) -> Result<(), String> {
         STATE.with(|s| s.borrow_mut().store(arg, time))
     }
 
+    pub fn create_batch(time: u64) -> Result<BatchId, String> {
+        STATE.with(|s| s.borrow_mut().create_batch(time))
+    }
+
+    pub fn create_chunk(arg: CreateChunkArg, time: u64) -> Result<ChunkId, String> {
+        STATE.with(|s| s.borrow_mut().create_chunk(arg, time))
+    }
+
+    pub fn chunk_content(chunk_id: &ChunkId) -> Result<RcBytes, String> {
+        STATE.with(|s| s.borrow().chunk_content(chunk_id))
+    }
+
+    pub fn delete_batch(arg: DeleteBatchArguments) -> Result<(), String> {
+        STATE.with(|s| s.borrow_mut().delete_batch(arg))
+    }
+
+    /*
+    Replace the asset at `key` with the content of already uploaded chunks in one batch commit
+    */
+    pub fn commit_chunks(
+        batch_id: BatchId,
+        key: String,
+        content_type: String,
+        chunk_ids: Vec<ChunkId>,
+        sha256: Vec<u8>,
+        time: u64,
+    ) -> Result<(), String> {
+        let operations = vec![
+            BatchOperation::DeleteAsset(DeleteAssetArguments { key: key.clone() }),
+            BatchOperation::CreateAsset(CreateAssetArguments {
+                key: key.clone(),
+                content_type,
+                max_age: None,
+                headers: None,
+                enable_aliasing: Some(false),
+                allow_raw_access: None,
+                redirect: None,
+                rewrite_from: None,
+            }),
+            BatchOperation::SetAssetContent(SetAssetContentArguments {
+                key,
+                content_encoding: "identity".to_string(),
+                chunk_ids,
+                sha256: Some(ByteBuf::from(sha256)),
+            }),
+        ];
+
+        STATE.with(|s| {
+            s.borrow_mut().commit_batch(
+                CommitBatchArguments {
+                    batch_id,
+                    operations,
+                },
+                time,
+            )
+        })
+    }
+
+    /*
+    Store a multi-chunk asset through a short-lived batch
+    */
+    pub fn store_chunks(
+        key: String,
+        content_type: String,
+        chunks: &[RcBytes],
+        sha256: Vec<u8>,
+        time: u64,
+    ) -> Result<(), String> {
+        let batch_id = Self::create_batch(time)?;
+
+        let mut chunk_ids = vec![];
+        for chunk in chunks {
+            let arg = CreateChunkArg {
+                batch_id: batch_id.clone(),
+                content: ByteBuf::from(chunk.to_vec()),
+            };
+
+            match Self::create_chunk(arg, time) {
+                Ok(chunk_id) => chunk_ids.push(chunk_id),
+                Err(msg) => {
+                    Self::delete_batch(DeleteBatchArguments { batch_id })?;
+                    return Err(msg);
+                }
+            }
+        }
+
+        Self::commit_chunks(batch_id, key, content_type, chunk_ids, sha256, time)
+    }
+
+    pub fn contains_asset(key: &str) -> bool {
+        STATE.with(|s| s.borrow().get_asset_properties(key.to_string()).is_ok())
+    }
+
+    pub fn delete_asset(arg: DeleteAssetArguments) {
+        STATE.with(|s| s.borrow_mut().delete_asset(arg));
+    }
+
+    // Certify a moved file at its new key with the content of its current asset
+    pub fn copy_asset(from: &str, to: &str) -> Result<(), String> {
+        STATE.with(|s| {
+            s.borrow_mut()
+                .copy_asset(&from.to_string(), &to.to_string())
+        })
+    }
+
     pub fn set_asset_properties(arg: SetAssetPropertiesArguments) -> Result<(), String> {
         STATE.with(|s| s.borrow_mut().set_asset_properties(arg))
     }
 
+    pub fn certify_directory_listing(
+        key: &str,
+        html: Vec<u8>,
+        json: Vec<u8>,
+    ) -> Result<(), String> {
+        STATE.with(|s| {
+            s.borrow_mut()
+                .certify_directory_listing(&key.to_string(), html, json)
+        })
+    }
+
+    pub fn remove_directory_listing(key: &str) {
+        STATE.with(|s| s.borrow_mut().remove_directory_listing(&key.to_string()));
+    }
+
+    pub fn remove_directory_listings(key: &str) {
+        STATE.with(|s| s.borrow_mut().remove_directory_listings(&key.to_string()));
+    }
+
+    /*
+    File content is kept with the content of the certified assets, so that public files
+    are stored once. Content is addressed by its SHA-256 and reference counted.
+    */
+    pub fn insert_content(sha256: [u8; 32], chunks: Vec<RcBytes>) {
+        STATE.with(|s| s.borrow_mut().insert_content(sha256, chunks));
+    }
+
+    pub fn retain_content(sha256: &[u8; 32]) -> bool {
+        STATE.with(|s| s.borrow_mut().retain_content(sha256))
+    }
+
+    pub fn release_content(sha256: &[u8; 32]) {
+        STATE.with(|s| s.borrow_mut().release_content(sha256));
+    }
+
+    pub fn stored_chunk_lengths(sha256: &[u8; 32]) -> Option<Vec<usize>> {
+        STATE.with(|s| s.borrow().stored_chunk_lengths(sha256))
+    }
+
+    pub fn stored_chunk(sha256: &[u8; 32], index: usize) -> Option<RcBytes> {
+        STATE.with(|s| s.borrow().stored_chunk(sha256, index))
+    }
+
     pub fn root_hash() -> Hash {
         STATE.with(|s| s.borrow().root_hash())
     }
@@ -0,0 +1,446 @@
+use super::{heap::StateStore, MemoryManagerStore, MEM_ID_INVITES, MEM_ID_MEMBERS};
+use crate::types::Permission;
+use candid::{CandidType, Decode, Deserialize, Encode, Principal};
+use ic_stable_structures::{
+    memory_manager::VirtualMemory, storable::Bound, BTreeMap as StableBTree, DefaultMemoryImpl,
+    Storable,
+};
+use std::{borrow::Cow, cell::RefCell};
+
+// Invites not accepted within a week are dropped
+const INVITE_EXPIRY_NANOS: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+
+thread_local! {
+    static MEMBERS: RefCell<StableBTree<MemberId, Member, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTree::init(MemoryManagerStore::get(MEM_ID_MEMBERS))
+    );
+
+    static INVITES: RefCell<StableBTree<InviteCode, Invite, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTree::init(MemoryManagerStore::get(MEM_ID_INVITES))
+    );
+}
+
+/*
+Roles map onto the permission sets of `State`:
+Owner: Commit and ManagePermissions, manages members and canister settings
+Editor: Commit, reads and writes files
+Viewer: Prepare, reads files only
+*/
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Owner,
+    Editor,
+    Viewer,
+}
+
+impl Role {
+    fn permissions(&self) -> Vec<Permission> {
+        match self {
+            Role::Owner => vec![Permission::Commit, Permission::ManagePermissions],
+            Role::Editor => vec![Permission::Commit],
+            Role::Viewer => vec![Permission::Prepare],
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Ord, PartialOrd, PartialEq, Eq)]
+struct MemberId(Principal);
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Member {
+    pub principal: Principal,
+    pub role: Role,
+    pub joined: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Ord, PartialOrd, PartialEq, Eq)]
+struct InviteCode(String);
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Invite {
+    pub code: String,
+    pub role: Role,
+    pub invited_by: Principal,
+    pub expires_at: u64,
+}
+
+pub struct MemberStore;
+
+impl MemberStore {
+    pub fn members() -> Vec<Member> {
+        MEMBERS.with(|tree| tree.borrow().iter().map(|(_, member)| member).collect())
+    }
+
+    pub fn is_empty() -> bool {
+        MEMBERS.with(|tree| tree.borrow().is_empty())
+    }
+
+    pub fn role(principal: Principal) -> Option<Role> {
+        MEMBERS.with(|tree| {
+            tree.borrow()
+                .get(&MemberId(principal))
+                .map(|member| member.role)
+        })
+    }
+
+    /*
+    Add or replace a member and grant the permissions of its role.
+    Used for the first owner on setup, everyone else joins through an invite.
+    */
+    pub fn add(principal: Principal, role: Role, time: u64) {
+        if principal == Principal::anonymous() {
+            return;
+        }
+
+        Self::revoke_permissions(principal);
+
+        let joined = MEMBERS.with(|tree| {
+            tree.borrow()
+                .get(&MemberId(principal))
+                .map_or(time, |member| member.joined)
+        });
+
+        MEMBERS.with(|tree| {
+            tree.borrow_mut().insert(
+                MemberId(principal),
+                Member {
+                    principal,
+                    role,
+                    joined,
+                },
+            )
+        });
+
+        Self::grant_permissions(principal, role);
+    }
+
+    pub fn invite(invited_by: Principal, role: Role, code: String, time: u64) -> Invite {
+        Self::expire_invites(time);
+
+        let invite = Invite {
+            code: code.clone(),
+            role,
+            invited_by,
+            expires_at: time + INVITE_EXPIRY_NANOS,
+        };
+
+        INVITES.with(|tree| tree.borrow_mut().insert(InviteCode(code), invite.clone()));
+
+        invite
+    }
+
+    // Listed from a query, so expired invites are skipped and left for the next update to remove
+    pub fn invites(time: u64) -> Vec<Invite> {
+        INVITES.with(|tree| {
+            tree.borrow()
+                .iter()
+                .map(|(_, invite)| invite)
+                .filter(|invite| invite.expires_at > time)
+                .collect()
+        })
+    }
+
+    pub fn revoke_invite(code: String) -> Result<(), String> {
+        INVITES
+            .with(|tree| tree.borrow_mut().remove(&InviteCode(code)))
+            .map(|_| ())
+            .ok_or_else(|| "Invite not found".to_string())
+    }
+
+    // An invite can be accepted once, by whoever presents the code first
+    pub fn accept(principal: Principal, code: String, time: u64) -> Result<Member, String> {
+        if principal == Principal::anonymous() {
+            return Err("Anonymous principal not allowed".to_string());
+        }
+
+        Self::expire_invites(time);
+
+        if Self::role(principal).is_some() {
+            return Err("Already a member".to_string());
+        }
+
+        let invite = INVITES
+            .with(|tree| tree.borrow_mut().remove(&InviteCode(code)))
+            .ok_or_else(|| "Invite not found or expired".to_string())?;
+
+        Self::add(principal, invite.role, time);
+
+        Ok(Member {
+            principal,
+            role: invite.role,
+            joined: time,
+        })
+    }
+
+    pub fn remove(principal: Principal) -> Result<(), String> {
+        let role = Self::role(principal).ok_or_else(|| "Not a member".to_string())?;
+
+        if role == Role::Owner && Self::owner_count() == 1 {
+            return Err("Cannot remove the last owner".to_string());
+        }
+
+        MEMBERS.with(|tree| tree.borrow_mut().remove(&MemberId(principal)));
+        Self::revoke_permissions(principal);
+
+        Ok(())
+    }
+
+    // Replace all members and their permissions with an earlier snapshot of `members()`
+    pub fn restore(members: Vec<Member>) {
+        for member in Self::members() {
+            Self::revoke_permissions(member.principal);
+        }
+
+        MEMBERS.with(|tree| {
+            let ids: Vec<MemberId> = tree.borrow().iter().map(|(id, _)| id).collect();
+
+            let mut tree = tree.borrow_mut();
+            for id in ids {
+                tree.remove(&id);
+            }
+            for member in members.iter() {
+                tree.insert(MemberId(member.principal), member.clone());
+            }
+        });
+
+        Self::restore_permissions();
+    }
+
+    // The heap `State` is reset on upgrade, grant all members their permissions again
+    pub fn restore_permissions() {
+        for member in Self::members() {
+            Self::grant_permissions(member.principal, member.role);
+        }
+    }
+
+    fn owner_count() -> usize {
+        Self::members()
+            .iter()
+            .filter(|member| member.role == Role::Owner)
+            .count()
+    }
+
+    fn grant_permissions(principal: Principal, role: Role) {
+        for permission in role.permissions() {
+            StateStore::grant_permission(principal, &permission);
+        }
+    }
+
+    fn revoke_permissions(principal: Principal) {
+        for permission in [
+            Permission::Commit,
+            Permission::Prepare,
+            Permission::ManagePermissions,
+        ] {
+            StateStore::revoke_permission(principal, &permission);
+        }
+    }
+
+    fn expire_invites(time: u64) {
+        INVITES.with(|tree| {
+            let expired: Vec<InviteCode> = tree
+                .borrow()
+                .iter()
+                .filter(|(_, invite)| invite.expires_at <= time)
+                .map(|(code, _)| code)
+                .collect();
+
+            let mut tree = tree.borrow_mut();
+            for code in expired {
+                tree.remove(&code);
+            }
+        });
+    }
+}
+
+impl Storable for MemberId {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 64,
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode MemberId"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(&bytes, Self).expect("Failed to decode MemberId")
+    }
+}
+
+impl Storable for Member {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 128,
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode Member"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(&bytes, Self).expect("Failed to decode Member")
+    }
+}
+
+impl Storable for InviteCode {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 128,
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode InviteCode"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(&bytes, Self).expect("Failed to decode InviteCode")
+    }
+}
+
+impl Storable for Invite {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 256,
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode Invite"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(&bytes, Self).expect("Failed to decode Invite")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+    fn principal(id: u8) -> Principal {
+        Principal::from_slice(&[id])
+    }
+
+    fn permissions(principal: Principal) -> Vec<Permission> {
+        [
+            Permission::Commit,
+            Permission::Prepare,
+            Permission::ManagePermissions,
+        ]
+        .into_iter()
+        .filter(|permission| StateStore::has_permission(principal, permission))
+        .collect()
+    }
+
+    #[test]
+    fn grants_the_permissions_of_the_role() {
+        let member = principal(1);
+
+        MemberStore::add(member, Role::Owner, 10);
+        assert_eq!(
+            permissions(member),
+            vec![Permission::Commit, Permission::ManagePermissions]
+        );
+
+        MemberStore::add(member, Role::Editor, 20);
+        assert_eq!(permissions(member), vec![Permission::Commit]);
+
+        MemberStore::add(member, Role::Viewer, 30);
+        assert_eq!(permissions(member), vec![Permission::Prepare]);
+
+        let members = MemberStore::members();
+        assert_eq!(members.len(), 1);
+        assert_eq!(members[0].role, Role::Viewer);
+        assert_eq!(members[0].joined, 10, "a role change keeps the join time");
+    }
+
+    #[test]
+    fn ignores_the_anonymous_principal() {
+        MemberStore::add(Principal::anonymous(), Role::Owner, 0);
+        assert!(MemberStore::is_empty());
+
+        MemberStore::invite(principal(1), Role::Editor, "code".to_string(), 0);
+        assert_eq!(
+            MemberStore::accept(Principal::anonymous(), "code".to_string(), 0).err(),
+            Some("Anonymous principal not allowed".to_string())
+        );
+    }
+
+    #[test]
+    fn accepts_an_invite_once() {
+        MemberStore::add(principal(1), Role::Owner, 0);
+        MemberStore::invite(principal(1), Role::Editor, "code".to_string(), 0);
+
+        let member = MemberStore::accept(principal(2), "code".to_string(), 1).unwrap();
+        assert_eq!(member.role, Role::Editor);
+        assert_eq!(permissions(principal(2)), vec![Permission::Commit]);
+
+        assert_eq!(
+            MemberStore::accept(principal(3), "code".to_string(), 2).err(),
+            Some("Invite not found or expired".to_string())
+        );
+        assert_eq!(MemberStore::role(principal(3)), None);
+    }
+
+    #[test]
+    fn drops_invites_after_a_week() {
+        MemberStore::invite(principal(1), Role::Viewer, "old".to_string(), 0);
+        MemberStore::invite(principal(1), Role::Viewer, "new".to_string(), DAY_NANOS);
+        assert_eq!(MemberStore::invites(7 * DAY_NANOS - 1).len(), 2);
+
+        let codes: Vec<String> = MemberStore::invites(7 * DAY_NANOS)
+            .into_iter()
+            .map(|invite| invite.code)
+            .collect();
+        assert_eq!(codes, vec!["new".to_string()]);
+
+        // Listing leaves expired invites in place, the next update removes them
+        assert_eq!(MemberStore::invites(7 * DAY_NANOS - 1).len(), 2);
+        assert!(MemberStore::accept(principal(2), "old".to_string(), 7 * DAY_NANOS).is_err());
+        assert_eq!(MemberStore::invites(7 * DAY_NANOS - 1).len(), 1);
+    }
+
+    #[test]
+    fn keeps_the_last_owner() {
+        MemberStore::add(principal(1), Role::Owner, 0);
+        MemberStore::add(principal(2), Role::Owner, 0);
+        MemberStore::add(principal(3), Role::Editor, 0);
+
+        MemberStore::remove(principal(3)).unwrap();
+        assert!(permissions(principal(3)).is_empty());
+
+        MemberStore::remove(principal(1)).unwrap();
+        assert_eq!(
+            MemberStore::remove(principal(2)),
+            Err("Cannot remove the last owner".to_string())
+        );
+        assert_eq!(
+            MemberStore::remove(principal(3)),
+            Err("Not a member".to_string())
+        );
+    }
+
+    #[test]
+    fn restores_members_and_their_permissions() {
+        MemberStore::add(principal(1), Role::Owner, 0);
+        MemberStore::add(principal(2), Role::Viewer, 0);
+        let snapshot = MemberStore::members();
+
+        MemberStore::add(principal(2), Role::Owner, 1);
+        MemberStore::add(principal(3), Role::Editor, 1);
+        MemberStore::remove(principal(1)).unwrap();
+
+        MemberStore::restore(snapshot);
+        assert_eq!(MemberStore::role(principal(1)), Some(Role::Owner));
+        assert_eq!(MemberStore::role(principal(2)), Some(Role::Viewer));
+        assert_eq!(MemberStore::role(principal(3)), None);
+        assert_eq!(
+            permissions(principal(1)),
+            vec![Permission::Commit, Permission::ManagePermissions]
+        );
+        assert_eq!(permissions(principal(2)), vec![Permission::Prepare]);
+        assert!(permissions(principal(3)).is_empty());
+    }
+}
@@ -1,6 +1,11 @@
+pub mod audit;
 pub mod config;
 pub mod fs;
 pub mod heap;
+pub mod members;
+pub mod sharing;
+pub mod top_ups;
+pub mod uploads;
 
 use ic_stable_structures::{
     memory_manager::{MemoryId, MemoryManager, VirtualMemory},
@@ -9,8 +14,21 @@ use ic_stable_structures::{
 use std::cell::RefCell;
 
 static MEM_ID_CONFIG: MemoryId = MemoryId::new(0);
-static MEM_ID_FILE_DATA: MemoryId = MemoryId::new(1);
+// Memories 1, 12 and 13 held file data before it moved to the asset content, do not reuse
 static MEM_ID_METADATA: MemoryId = MemoryId::new(2);
+static MEM_ID_VISIBILITY: MemoryId = MemoryId::new(3);
+static MEM_ID_SHARES: MemoryId = MemoryId::new(4);
+static MEM_ID_TOP_UPS: MemoryId = MemoryId::new(5);
+static MEM_ID_MEMBERS: MemoryId = MemoryId::new(6);
+static MEM_ID_INVITES: MemoryId = MemoryId::new(7);
+static MEM_ID_AUDIT_LOG: MemoryId = MemoryId::new(8);
+static MEM_ID_ASSETS: MemoryId = MemoryId::new(9);
+static MEM_ID_ASSET_CHUNKS: MemoryId = MemoryId::new(10);
+static MEM_ID_ASSET_CONTENTS: MemoryId = MemoryId::new(11);
+static MEM_ID_SEARCH_TERMS: MemoryId = MemoryId::new(14);
+static MEM_ID_SEARCH_FILES: MemoryId = MemoryId::new(15);
+static MEM_ID_TRASH: MemoryId = MemoryId::new(16);
+static MEM_ID_PENDING_TOP_UPS: MemoryId = MemoryId::new(17);
 
 thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
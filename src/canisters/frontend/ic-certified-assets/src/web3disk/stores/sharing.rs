@@ -0,0 +1,656 @@
+use super::{
+    fs::{api::FsStore, Key},
+    MemoryManagerStore, MEM_ID_SHARES, MEM_ID_VISIBILITY,
+};
+use crate::{
+    asset_certification::types::{
+        http::{
+            CallbackFunc, HttpRequest, HttpResponse, StreamingCallbackHttpResponse,
+            StreamingCallbackToken, StreamingStrategy,
+        },
+        rc_bytes::RcBytes,
+    },
+    url_decode::url_decode,
+};
+use candid::{CandidType, Decode, Deserialize, Encode, Nat};
+use ic_stable_structures::{
+    memory_manager::VirtualMemory, storable::Bound, BTreeMap as StableBTree, DefaultMemoryImpl,
+    Storable,
+};
+use num_traits::ToPrimitive;
+use serde_bytes::ByteBuf;
+use std::{borrow::Cow, cell::RefCell};
+
+const SHARE_QUERY_PARAM: &str = "share=";
+
+thread_local! {
+    // Only explicit settings are stored, everything else inherits from its parent folder
+    static VISIBILITY: RefCell<StableBTree<Key, Visibility, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTree::init(MemoryManagerStore::get(MEM_ID_VISIBILITY))
+    );
+
+    static SHARES: RefCell<StableBTree<ShareToken, Share, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTree::init(MemoryManagerStore::get(MEM_ID_SHARES))
+    );
+}
+
+/*
+Public files are part of the certified http view.
+Private files are only reachable through the Candid endpoints.
+Shared files are only served to requests carrying a valid share token.
+*/
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Visibility {
+    Public,
+    Private,
+    Shared,
+}
+
+#[derive(CandidType, Deserialize, Clone, Ord, PartialOrd, PartialEq, Eq)]
+struct ShareToken(String);
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Share {
+    pub token: String,
+    pub key: Key,
+    pub created: u64,
+    pub expires_at: Option<u64>,
+    pub max_downloads: Option<u64>,
+    pub downloads: u64,
+}
+
+impl Share {
+    fn is_active(&self, time: u64) -> bool {
+        let expired = self.expires_at.is_some_and(|expires_at| expires_at <= time);
+        let exhausted = self
+            .max_downloads
+            .is_some_and(|max_downloads| self.downloads >= max_downloads);
+
+        !expired && !exhausted
+    }
+
+    // A folder share covers everything inside the folder
+    fn covers(&self, key: &Key) -> bool {
+        &self.key == key || self.key.contains(key)
+    }
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct SetVisibilityArgs {
+    pub key: String,
+    pub visibility: Visibility,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CreateShareArgs {
+    pub key: String,
+    pub expires_at: Option<u64>,
+    pub max_downloads: Option<u64>,
+}
+
+pub struct SharingStore;
+
+impl SharingStore {
+    /*
+    The visibility of a key is its own explicit setting or else the one of its nearest folder.
+    Anything without a setting up to the root is public, except for the trash.
+    */
+    pub fn visibility(key: &Key) -> Visibility {
+        if key.in_trash() {
+            return Visibility::Private;
+        }
+
+        Self::stored_visibility(key)
+    }
+
+    pub fn is_public(key: &Key) -> bool {
+        Self::visibility(key) == Visibility::Public
+    }
+
+    // The visibility set on a key or its folders, also for keys in the trash
+    fn stored_visibility(key: &Key) -> Visibility {
+        VISIBILITY.with(|tree| {
+            let tree = tree.borrow();
+
+            std::iter::once(key.clone())
+                .chain(key.ancestors().into_iter().rev())
+                .find_map(|key| tree.get(&key))
+                .unwrap_or(Visibility::Public)
+        })
+    }
+
+    pub fn set_visibility(arg: SetVisibilityArgs) -> Result<(), String> {
+        let key = Key::new(&arg.key);
+        FsStore::stat(&key)?;
+
+        VISIBILITY.with(|tree| tree.borrow_mut().insert(key.clone(), arg.visibility));
+
+        // Make sure public files are certified and everything else is removed from the http view
+        FsStore::recertify(&key)
+    }
+
+    pub fn create_share(arg: CreateShareArgs, token: String, time: u64) -> Result<Share, String> {
+        let key = Key::new(&arg.key);
+        FsStore::stat(&key)?;
+
+        if Self::visibility(&key) != Visibility::Shared {
+            return Err(format!("Visibility is not set to shared: {}", arg.key));
+        }
+
+        if arg.expires_at.is_some_and(|expires_at| expires_at <= time) {
+            return Err("Expiry time lies in the past".to_string());
+        }
+
+        if arg.max_downloads == Some(0) {
+            return Err("Download limit must be at least 1".to_string());
+        }
+
+        let share = Share {
+            token: token.clone(),
+            key,
+            created: time,
+            expires_at: arg.expires_at,
+            max_downloads: arg.max_downloads,
+            downloads: 0,
+        };
+
+        SHARES.with(|tree| tree.borrow_mut().insert(ShareToken(token), share.clone()));
+
+        Ok(share)
+    }
+
+    // Expired and exhausted shares are kept until revoked but not listed
+    pub fn list_shares(time: u64) -> Vec<Share> {
+        SHARES.with(|tree| {
+            tree.borrow()
+                .iter()
+                .map(|(_, share)| share)
+                .filter(|share| share.is_active(time))
+                .collect()
+        })
+    }
+
+    pub fn revoke_share(token: String) -> Result<(), String> {
+        SHARES
+            .with(|tree| tree.borrow_mut().remove(&ShareToken(token)))
+            .map(|_| ())
+            .ok_or_else(|| "Share not found".to_string())
+    }
+
+    /*
+    Copy the visibility settings of `from` and everything inside it onto `to`.
+    A visibility `from` inherits from its folders is set on `to` explicitly
+    unless `to` inherits the same, so moving an item never changes who can see it.
+    */
+    pub fn copy_visibility(from: &Key, to: &Key) {
+        let visibility = Self::stored_visibility(from);
+        let inherited = Self::stored_visibility(to);
+
+        VISIBILITY.with(|tree| {
+            let entries: Vec<(Key, Visibility)> = tree
+                .borrow()
+                .range(from.clone()..)
+                .take_while(|(key, _)| key == from || from.contains(key))
+                .collect();
+
+            let mut tree = tree.borrow_mut();
+            if visibility != inherited {
+                tree.insert(to.clone(), visibility);
+            }
+            for (key, visibility) in entries {
+                tree.insert(key.rebase(from, to), visibility);
+            }
+        });
+    }
+
+    // Point the shares of a moved file or folder, or of anything inside it, to the new key
+    pub fn move_shares(from: &Key, to: &Key) {
+        SHARES.with(|tree| {
+            let moved: Vec<(ShareToken, Share)> = tree
+                .borrow()
+                .iter()
+                .filter(|(_, share)| &share.key == from || from.contains(&share.key))
+                .collect();
+
+            let mut tree = tree.borrow_mut();
+            for (token, mut share) in moved {
+                share.key = share.key.rebase(from, to);
+                tree.insert(token, share);
+            }
+        });
+    }
+
+    // Forget visibility settings and shares of a deleted file or folder
+    pub fn remove_subtree(key: &Key) {
+        let in_subtree = |other: &Key| other == key || key.contains(other);
+
+        VISIBILITY.with(|tree| {
+            let keys: Vec<Key> = tree
+                .borrow()
+                .range(key.clone()..)
+                .take_while(|(other, _)| in_subtree(other))
+                .map(|(other, _)| other)
+                .collect();
+
+            let mut tree = tree.borrow_mut();
+            for key in keys {
+                tree.remove(&key);
+            }
+        });
+
+        SHARES.with(|tree| {
+            let tokens: Vec<ShareToken> = tree
+                .borrow()
+                .iter()
+                .filter(|(_, share)| in_subtree(&share.key))
+                .map(|(token, _)| token)
+                .collect();
+
+            let mut tree = tree.borrow_mut();
+            for token in tokens {
+                tree.remove(&token);
+            }
+        });
+    }
+
+    // Shared files are served through `http_request_update` so downloads can be counted
+    pub fn is_share_request(req: &HttpRequest) -> bool {
+        Self::share_token(&req.url).is_some()
+    }
+
+    /*
+    Serve the first chunk of a shared file and count the download.
+    Remaining chunks are streamed through `streaming_callback`.
+    */
+    pub fn http_request(req: HttpRequest, callback: CallbackFunc, time: u64) -> HttpResponse {
+        let token = match Self::share_token(&req.url) {
+            Some(token) => token,
+            None => return Self::build_response(403, "Missing share token"),
+        };
+
+        let key = match url_decode(req.get_path()) {
+            Ok(path) => Key::new(&path),
+            Err(err) => {
+                return Self::build_response(400, &format!("Failed to decode path: {}", err))
+            }
+        };
+
+        if let Err(msg) = Self::check_share(&token, &key, time) {
+            return Self::build_response(403, &msg);
+        }
+
+        let (metadata, chunk, chunk_count) = match FsStore::read_chunk(&key, 0) {
+            Ok(read) => read,
+            Err(msg) => return Self::build_response(404, &msg),
+        };
+
+        SHARES.with(|tree| {
+            let mut tree = tree.borrow_mut();
+            if let Some(mut share) = tree.get(&ShareToken(token.clone())) {
+                share.downloads += 1;
+                tree.insert(ShareToken(token.clone()), share);
+            }
+        });
+
+        let streaming_strategy = (chunk_count > 1).then(|| StreamingStrategy::Callback {
+            callback,
+            token: StreamingCallbackToken {
+                key: format!("{}?{}{}", key.to_string(), SHARE_QUERY_PARAM, token),
+                content_encoding: "identity".to_string(),
+                index: Nat::from(1u32),
+                sha256: Some(ByteBuf::from(metadata.sha256.clone())),
+            },
+        });
+
+        HttpResponse {
+            status_code: 200,
+            headers: vec![
+                ("content-type".to_string(), metadata.content_type),
+                ("cache-control".to_string(), "private, no-store".to_string()),
+            ],
+            body: chunk,
+            upgrade: None,
+            streaming_strategy,
+        }
+    }
+
+    pub fn is_share_token(token: &StreamingCallbackToken) -> bool {
+        Self::share_token(&token.key).is_some()
+    }
+
+    // The share is checked again for every chunk so revoking stops running downloads
+    pub fn streaming_callback(
+        token: StreamingCallbackToken,
+        time: u64,
+    ) -> Result<StreamingCallbackHttpResponse, String> {
+        let share_token =
+            Self::share_token(&token.key).ok_or_else(|| "Missing share token".to_string())?;
+        let (path, _) = token.key.split_once('?').unwrap_or((&token.key, ""));
+        let key = Key::new(path);
+
+        Self::check_streaming_share(&share_token, &key, time)?;
+
+        let index = token
+            .index
+            .0
+            .to_u32()
+            .ok_or_else(|| "Invalid chunk index".to_string())?;
+        let (metadata, chunk, chunk_count) = FsStore::read_chunk(&key, index)?;
+
+        if token.sha256.as_deref().map(Vec::as_slice) != Some(metadata.sha256.as_slice()) {
+            return Err("sha256 mismatch".to_string());
+        }
+
+        let next = (index + 1 < chunk_count).then(|| StreamingCallbackToken {
+            index: Nat::from(index + 1),
+            ..token
+        });
+
+        Ok(StreamingCallbackHttpResponse {
+            body: chunk,
+            token: next,
+        })
+    }
+
+    fn check_share(token: &str, key: &Key, time: u64) -> Result<(), String> {
+        let share = SHARES
+            .with(|tree| tree.borrow().get(&ShareToken(token.to_string())))
+            .ok_or_else(|| "Invalid share link".to_string())?;
+
+        if !share.covers(key) || Self::visibility(key) != Visibility::Shared {
+            return Err("Invalid share link".to_string());
+        }
+
+        if !share.is_active(time) {
+            return Err("Share link expired".to_string());
+        }
+
+        Ok(())
+    }
+
+    // The download was already counted when the first chunk was served
+    fn check_streaming_share(token: &str, key: &Key, time: u64) -> Result<(), String> {
+        let share = SHARES
+            .with(|tree| tree.borrow().get(&ShareToken(token.to_string())))
+            .ok_or_else(|| "Invalid share link".to_string())?;
+
+        let expired = share
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= time);
+        if expired || !share.covers(key) || Self::visibility(key) != Visibility::Shared {
+            return Err("Invalid share link".to_string());
+        }
+
+        Ok(())
+    }
+
+    fn share_token(url: &str) -> Option<String> {
+        let (_, query) = url.split_once('?')?;
+
+        query
+            .split('&')
+            .find_map(|param| param.strip_prefix(SHARE_QUERY_PARAM))
+            .filter(|token| !token.is_empty())
+            .map(|token| token.to_string())
+    }
+
+    fn build_response(status_code: u16, message: &str) -> HttpResponse {
+        HttpResponse {
+            status_code,
+            headers: vec![],
+            body: RcBytes::from(ByteBuf::from(message.as_bytes())),
+            upgrade: None,
+            streaming_strategy: None,
+        }
+    }
+}
+
+impl Storable for Visibility {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 64,
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode Visibility"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(&bytes, Self).expect("Failed to decode Visibility")
+    }
+}
+
+impl Storable for ShareToken {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 128,
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode ShareToken"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(&bytes, Self).expect("Failed to decode ShareToken")
+    }
+}
+
+impl Storable for Share {
+    // Holds a maximum length key plus the token and counters
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1536,
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode Share"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(&bytes, Self).expect("Failed to decode Share")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        types::StoreArg,
+        web3disk::stores::{fs::trash::Trash, heap::StateStore},
+    };
+    use candid::Principal;
+
+    const CONTENT: &[u8] = b"shared content";
+
+    fn store(key: &str) {
+        FsStore::store(
+            StoreArg {
+                key: key.to_string(),
+                content_type: "text/plain".to_string(),
+                content_encoding: "identity".to_string(),
+                content: ByteBuf::from(CONTENT.to_vec()),
+                sha256: None,
+                aliased: None,
+            },
+            0,
+        )
+        .unwrap();
+    }
+
+    fn set_visibility(key: &str, visibility: Visibility) {
+        SharingStore::set_visibility(SetVisibilityArgs {
+            key: key.to_string(),
+            visibility,
+        })
+        .unwrap();
+    }
+
+    fn share(key: &str, token: &str, expires_at: Option<u64>, max_downloads: Option<u64>) {
+        SharingStore::create_share(
+            CreateShareArgs {
+                key: key.to_string(),
+                expires_at,
+                max_downloads,
+            },
+            token.to_string(),
+            0,
+        )
+        .unwrap();
+    }
+
+    fn download(url: &str, time: u64) -> HttpResponse {
+        let req = HttpRequest {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers: vec![],
+            body: ByteBuf::new(),
+            certificate_version: None,
+        };
+        let callback = CallbackFunc::new(Principal::anonymous(), "callback".to_string());
+
+        SharingStore::http_request(req, callback, time)
+    }
+
+    #[test]
+    fn serves_shared_files_with_a_valid_token_only() {
+        store("/docs/notes.txt");
+        assert!(StateStore::contains_asset("/docs/notes.txt"));
+
+        set_visibility("/docs/", Visibility::Shared);
+        assert!(!StateStore::contains_asset("/docs/notes.txt"));
+
+        // A folder share covers the files inside
+        share("/docs/", "token", None, None);
+
+        let response = download("/docs/notes.txt?share=token", 0);
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body.as_ref(), CONTENT);
+        assert!(response.streaming_strategy.is_none());
+
+        assert_eq!(download("/docs/notes.txt", 0).status_code, 403);
+        assert_eq!(download("/docs/notes.txt?share=other", 0).status_code, 403);
+        assert_eq!(
+            download("/docs/missing.txt?share=token", 0).status_code,
+            404
+        );
+
+        // Making the folder public again does not leave the link working
+        set_visibility("/docs/", Visibility::Public);
+        assert_eq!(download("/docs/notes.txt?share=token", 0).status_code, 403);
+    }
+
+    #[test]
+    fn validates_new_shares() {
+        store("/notes.txt");
+        let create = |expires_at, max_downloads| {
+            SharingStore::create_share(
+                CreateShareArgs {
+                    key: "/notes.txt".to_string(),
+                    expires_at,
+                    max_downloads,
+                },
+                "token".to_string(),
+                10,
+            )
+            .map(|_| ())
+        };
+
+        assert_eq!(
+            create(None, None),
+            Err("Visibility is not set to shared: /notes.txt".to_string())
+        );
+
+        set_visibility("/notes.txt", Visibility::Shared);
+        assert_eq!(
+            create(Some(10), None),
+            Err("Expiry time lies in the past".to_string())
+        );
+        assert_eq!(
+            create(None, Some(0)),
+            Err("Download limit must be at least 1".to_string())
+        );
+        assert_eq!(create(Some(11), Some(1)), Ok(()));
+    }
+
+    #[test]
+    fn counts_downloads_up_to_the_limit() {
+        store("/notes.txt");
+        set_visibility("/notes.txt", Visibility::Shared);
+        share("/notes.txt", "twice", None, Some(2));
+
+        assert_eq!(download("/notes.txt?share=twice", 0).status_code, 200);
+        assert_eq!(SharingStore::list_shares(0)[0].downloads, 1);
+        assert_eq!(download("/notes.txt?share=twice", 0).status_code, 200);
+
+        let response = download("/notes.txt?share=twice", 0);
+        assert_eq!(response.status_code, 403);
+        assert_eq!(response.body.as_ref(), b"Share link expired");
+        assert!(SharingStore::list_shares(0).is_empty());
+    }
+
+    #[test]
+    fn expires_shares() {
+        store("/notes.txt");
+        set_visibility("/notes.txt", Visibility::Shared);
+        share("/notes.txt", "token", Some(100), None);
+
+        assert_eq!(download("/notes.txt?share=token", 99).status_code, 200);
+        assert_eq!(download("/notes.txt?share=token", 100).status_code, 403);
+        assert_eq!(SharingStore::list_shares(99).len(), 1);
+        assert!(SharingStore::list_shares(100).is_empty());
+
+        let token = StreamingCallbackToken {
+            key: "/notes.txt?share=token".to_string(),
+            content_encoding: "identity".to_string(),
+            index: Nat::from(0_u8),
+            sha256: Some(ByteBuf::from(
+                FsStore::read_chunk(&Key::new("/notes.txt"), 0)
+                    .unwrap()
+                    .0
+                    .sha256,
+            )),
+        };
+        assert!(SharingStore::streaming_callback(token.clone(), 99).is_ok());
+        assert_eq!(
+            SharingStore::streaming_callback(token.clone(), 100).err(),
+            Some("Invalid share link".to_string())
+        );
+
+        SharingStore::revoke_share("token".to_string()).unwrap();
+        assert!(SharingStore::streaming_callback(token, 99).is_err());
+    }
+
+    #[test]
+    fn moves_visibility_and_shares_along() {
+        store("/private/notes.txt");
+        set_visibility("/private/", Visibility::Shared);
+        share("/private/notes.txt", "token", None, None);
+
+        FsStore::rename(&Key::new("/private/"), &Key::new("/public/"), 1).unwrap();
+
+        assert_eq!(
+            SharingStore::visibility(&Key::new("/public/notes.txt")),
+            Visibility::Shared
+        );
+        assert!(!StateStore::contains_asset("/public/notes.txt"));
+        assert_eq!(
+            download("/public/notes.txt?share=token", 1).status_code,
+            200
+        );
+        assert_eq!(
+            SharingStore::list_shares(1)[0].key.to_string(),
+            "/public/notes.txt"
+        );
+
+        // Shares follow the folder into the trash and are dropped once it is purged
+        let item = Trash::put(&Key::new("/public/"), 2).unwrap();
+        assert_eq!(
+            download("/public/notes.txt?share=token", 2).status_code,
+            403
+        );
+        assert_eq!(SharingStore::list_shares(2).len(), 1);
+
+        Trash::purge(item.id).unwrap();
+        assert!(SharingStore::list_shares(2).is_empty());
+    }
+}
@@ -0,0 +1,220 @@
+use super::{MemoryManagerStore, MEM_ID_PENDING_TOP_UPS, MEM_ID_TOP_UPS};
+use candid::{CandidType, Decode, Deserialize, Encode, Nat};
+use ic_ledger_types::BlockIndex;
+use ic_stable_structures::{
+    memory_manager::VirtualMemory, storable::Bound, BTreeMap as StableBTree, DefaultMemoryImpl,
+    Storable,
+};
+use std::{borrow::Cow, cell::RefCell};
+
+// Errors are cut to keep history entries bounded
+const MAX_ERROR_LENGTH: usize = 256;
+
+thread_local! {
+    // Top ups in the order they were minted
+    static TOP_UPS: RefCell<StableBTree<u64, TopUp, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTree::init(MemoryManagerStore::get(MEM_ID_TOP_UPS))
+    );
+
+    // Transfers to the CMC that still have to be notified, by block index
+    static PENDING_TOP_UPS: RefCell<StableBTree<BlockIndex, PendingTopUp, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTree::init(MemoryManagerStore::get(MEM_ID_PENDING_TOP_UPS))
+    );
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct TopUp {
+    pub block_index: BlockIndex,
+    pub icp_e8s: u64,
+    pub cycles: Nat,
+    pub time: u64,
+    // Whether the top up was triggered by the auto top up policy
+    pub automatic: bool,
+    // Why no cycles were minted for the transfer
+    pub error: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct PendingTopUp {
+    pub block_index: BlockIndex,
+    pub icp_e8s: u64,
+    // When the ICP was transferred
+    pub time: u64,
+    pub automatic: bool,
+    // Failed notifications so far
+    pub attempts: u32,
+}
+
+pub struct TopUpStore;
+
+impl TopUpStore {
+    pub fn history() -> Vec<TopUp> {
+        TOP_UPS.with(|tree| tree.borrow().iter().map(|(_, top_up)| top_up).collect())
+    }
+
+    pub fn add_pending(pending: PendingTopUp) {
+        PENDING_TOP_UPS.with(|tree| tree.borrow_mut().insert(pending.block_index, pending));
+    }
+
+    pub fn pending() -> Vec<PendingTopUp> {
+        PENDING_TOP_UPS.with(|tree| tree.borrow().iter().map(|(_, pending)| pending).collect())
+    }
+
+    pub fn record_attempt(block_index: BlockIndex) {
+        PENDING_TOP_UPS.with(|tree| {
+            let mut tree = tree.borrow_mut();
+            if let Some(mut pending) = tree.get(&block_index) {
+                pending.attempts += 1;
+                tree.insert(block_index, pending);
+            }
+        });
+    }
+
+    /*
+    Move a pending top up to the history, with the minted cycles or the reason it failed.
+    A block is only recorded once, even if a retry and another notification overlap.
+    */
+    pub fn complete(block_index: BlockIndex, result: Result<Nat, String>, time: u64) {
+        let pending = match PENDING_TOP_UPS.with(|tree| tree.borrow_mut().remove(&block_index)) {
+            Some(pending) => pending,
+            None => return,
+        };
+
+        let (cycles, error) = match result {
+            Ok(cycles) => (cycles, None),
+            Err(mut error) => {
+                if error.len() > MAX_ERROR_LENGTH {
+                    let mut end = MAX_ERROR_LENGTH;
+                    while !error.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    error.truncate(end);
+                }
+                (Nat::from(0u32), Some(error))
+            }
+        };
+
+        Self::record(TopUp {
+            block_index,
+            icp_e8s: pending.icp_e8s,
+            cycles,
+            time,
+            automatic: pending.automatic,
+            error,
+        });
+    }
+
+    fn record(top_up: TopUp) {
+        TOP_UPS.with(|tree| {
+            let mut tree = tree.borrow_mut();
+            let index = tree.len();
+
+            tree.insert(index, top_up);
+        });
+    }
+}
+
+impl Storable for TopUp {
+    // Holds the counters plus an error of at most `MAX_ERROR_LENGTH` bytes
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 512,
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode TopUp"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(&bytes, Self).expect("Failed to decode TopUp")
+    }
+}
+
+impl Storable for PendingTopUp {
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 128,
+        is_fixed_size: false,
+    };
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode PendingTopUp"))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(&bytes, Self).expect("Failed to decode PendingTopUp")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending(block_index: BlockIndex) -> PendingTopUp {
+        PendingTopUp {
+            block_index,
+            icp_e8s: 100_000_000,
+            time: 10,
+            automatic: true,
+            attempts: 0,
+        }
+    }
+
+    #[test]
+    fn completes_a_pending_top_up_once() {
+        TopUpStore::add_pending(pending(7));
+        TopUpStore::record_attempt(7);
+        TopUpStore::record_attempt(7);
+        assert_eq!(TopUpStore::pending()[0].attempts, 2);
+
+        TopUpStore::complete(7, Ok(Nat::from(5_000_000_000_u64)), 20);
+        TopUpStore::complete(7, Ok(Nat::from(5_000_000_000_u64)), 30);
+
+        assert!(TopUpStore::pending().is_empty());
+        let history = TopUpStore::history();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].block_index, 7);
+        assert_eq!(history[0].cycles, Nat::from(5_000_000_000_u64));
+        assert_eq!(history[0].time, 20);
+        assert!(history[0].automatic);
+        assert!(history[0].error.is_none());
+    }
+
+    #[test]
+    fn keeps_history_in_completion_order() {
+        TopUpStore::add_pending(pending(9));
+        TopUpStore::add_pending(pending(3));
+
+        TopUpStore::complete(9, Ok(Nat::from(1_u8)), 1);
+        TopUpStore::complete(3, Ok(Nat::from(2_u8)), 2);
+
+        let blocks: Vec<BlockIndex> = TopUpStore::history()
+            .iter()
+            .map(|top_up| top_up.block_index)
+            .collect();
+        assert_eq!(blocks, vec![9, 3]);
+    }
+
+    #[test]
+    fn cuts_long_errors_at_a_char_boundary() {
+        TopUpStore::add_pending(pending(1));
+
+        // 3 byte characters, the limit falls inside one
+        let error = "€".repeat(MAX_ERROR_LENGTH);
+        TopUpStore::complete(1, Err(error), 2);
+
+        let top_up = &TopUpStore::history()[0];
+        assert_eq!(top_up.cycles, Nat::from(0_u8));
+        let error = top_up.error.as_ref().unwrap();
+        assert_eq!(error.len(), MAX_ERROR_LENGTH - MAX_ERROR_LENGTH % 3);
+        assert!(error.chars().all(|c| c == '€'));
+    }
+
+    #[test]
+    fn ignores_attempts_on_unknown_blocks() {
+        TopUpStore::record_attempt(1);
+        TopUpStore::complete(1, Ok(Nat::from(1_u8)), 1);
+
+        assert!(TopUpStore::pending().is_empty());
+        assert!(TopUpStore::history().is_empty());
+    }
+}
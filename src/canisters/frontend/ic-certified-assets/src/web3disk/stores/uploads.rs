@@ -0,0 +1,547 @@
+use super::{
+    config::ConfigStore,
+    fs::{api::FsStore, thumbnails::Thumbnail, Key},
+    heap::StateStore,
+};
+use crate::{
+    state_machine::BATCH_EXPIRY_NANOS,
+    types::{BatchId, ChunkId, CreateChunkArg, DeleteBatchArguments},
+};
+use candid::{CandidType, Deserialize, Principal};
+use serde_bytes::ByteBuf;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+};
+
+// Stay below the 2 MiB ingress message limit including Candid overhead
+const MAX_UPLOAD_CHUNK_BYTES: usize = 1_900_000;
+
+const MAX_UPLOAD_SESSIONS: usize = 8;
+
+/*
+Upload sessions are kept on the heap next to the batch holding their chunks.
+A session expires together with its batch: `BATCH_EXPIRY_NANOS` after the last chunk was received.
+
+Neither sessions nor batches survive an upgrade. Uploads in progress during an upgrade
+are lost and have to be started again.
+
+The declared size of every open session is reserved up front: it counts against the storage
+quota and against `ConfigStore::max_upload_bytes`, which bounds the chunks held on the heap.
+*/
+thread_local! {
+    static UPLOADS: RefCell<HashMap<BatchId, UploadSession>> = RefCell::new(HashMap::new());
+}
+
+struct UploadSession {
+    owner: Principal,
+    key: Key,
+    content_type: String,
+    size: u64,
+    sha256: Vec<u8>,
+    // chunk index -> (chunk id in the batch, chunk length)
+    chunks: BTreeMap<u32, (ChunkId, u64)>,
+    expires_at: u64,
+}
+
+impl UploadSession {
+    fn received_bytes(&self) -> u64 {
+        self.chunks.values().map(|(_, length)| length).sum()
+    }
+
+    fn status(&self, session_id: &BatchId) -> UploadStatus {
+        UploadStatus {
+            session_id: session_id.clone(),
+            key: self.key.to_string(),
+            size: self.size,
+            received_bytes: self.received_bytes(),
+            received_chunks: self.chunks.keys().copied().collect(),
+            expires_at: self.expires_at,
+        }
+    }
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct StartUploadArgs {
+    pub key: String,
+    pub content_type: String,
+    pub size: u64,
+    pub sha256: ByteBuf,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct UploadChunkArgs {
+    pub session_id: BatchId,
+    pub index: u32,
+    pub content: ByteBuf,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct UploadStatus {
+    pub session_id: BatchId,
+    pub key: String,
+    pub size: u64,
+    pub received_bytes: u64,
+    pub received_chunks: Vec<u32>,
+    pub expires_at: u64,
+}
+
+pub struct UploadStore;
+
+impl UploadStore {
+    pub fn start(caller: Principal, arg: StartUploadArgs, time: u64) -> Result<BatchId, String> {
+        Self::expire(time);
+
+        let key = Key::new(&arg.key);
+        key.validate()?;
+        FsStore::check_outside_trash(&key)?;
+        FsStore::check_content_type(&arg.content_type)?;
+
+        if key.is_dir() {
+            return Err(format!("Cannot upload to a folder key: {}", arg.key));
+        }
+
+        if Thumbnail::is_thumbnail_key(&key) {
+            return Err(format!("Key is reserved for thumbnails: {}", arg.key));
+        }
+
+        if arg.size == 0 {
+            return Err("Empty files must be stored directly".to_string());
+        }
+
+        if arg.sha256.len() != 32 {
+            return Err("invalid SHA-256".to_string());
+        }
+
+        // Checked again on commit, other writes may have happened in between
+        FsStore::check_quota(&key, arg.size)?;
+
+        UPLOADS.with(|uploads| {
+            let mut uploads = uploads.borrow_mut();
+
+            if uploads.len() >= MAX_UPLOAD_SESSIONS {
+                return Err("Too many uploads in progress".to_string());
+            }
+
+            if uploads.values().any(|session| session.key == key) {
+                return Err(format!("Upload already in progress: {}", arg.key));
+            }
+
+            let max_upload_bytes = ConfigStore::max_upload_bytes();
+            if Self::reserved_bytes(&uploads) + arg.size > max_upload_bytes {
+                return Err(format!(
+                    "Uploads in progress would exceed the upload limit of {} bytes",
+                    max_upload_bytes
+                ));
+            }
+
+            let session_id = StateStore::create_batch(time)?;
+
+            uploads.insert(
+                session_id.clone(),
+                UploadSession {
+                    owner: caller,
+                    key,
+                    content_type: arg.content_type,
+                    size: arg.size,
+                    sha256: arg.sha256.into_vec(),
+                    chunks: BTreeMap::new(),
+                    expires_at: time + BATCH_EXPIRY_NANOS,
+                },
+            );
+
+            Ok(session_id)
+        })
+    }
+
+    /*
+    Upload one chunk of the file. Uploading an index again replaces the previous chunk,
+    so a client can simply resend everything not listed in `status` after a dropped connection.
+    */
+    pub fn upload_chunk(caller: Principal, arg: UploadChunkArgs, time: u64) -> Result<(), String> {
+        Self::expire(time);
+
+        if arg.content.is_empty() {
+            return Err("Chunk is empty".to_string());
+        }
+
+        if arg.content.len() > MAX_UPLOAD_CHUNK_BYTES {
+            return Err(format!("Chunk exceeds {} bytes", MAX_UPLOAD_CHUNK_BYTES));
+        }
+
+        UPLOADS.with(|uploads| {
+            let mut uploads = uploads.borrow_mut();
+            let session = Self::session_mut(&mut uploads, &arg.session_id, caller)?;
+
+            let replaced = session
+                .chunks
+                .get(&arg.index)
+                .map(|(_, length)| *length)
+                .unwrap_or(0);
+            let length = arg.content.len() as u64;
+
+            if session.received_bytes() - replaced + length > session.size {
+                return Err("Chunk exceeds the declared file size".to_string());
+            }
+
+            let chunk_id = StateStore::create_chunk(
+                CreateChunkArg {
+                    batch_id: arg.session_id.clone(),
+                    content: arg.content,
+                },
+                time,
+            )?;
+
+            session.chunks.insert(arg.index, (chunk_id, length));
+            session.expires_at = time + BATCH_EXPIRY_NANOS;
+
+            Ok(())
+        })
+    }
+
+    pub fn status(
+        caller: Principal,
+        session_id: &BatchId,
+        time: u64,
+    ) -> Result<UploadStatus, String> {
+        UPLOADS.with(|uploads| {
+            let uploads = uploads.borrow();
+            let session = uploads
+                .get(session_id)
+                .filter(|session| session.expires_at > time)
+                .ok_or_else(|| "Upload session not found".to_string())?;
+
+            if session.owner != caller {
+                return Err("Upload session belongs to another principal".to_string());
+            }
+
+            Ok(session.status(session_id))
+        })
+    }
+
+    /*
+    Verify the upload is complete and commit it as a user file, returning its key.
+    A session whose content does not match the expected SHA-256 is discarded.
+    */
+    pub fn commit(caller: Principal, session_id: &BatchId, time: u64) -> Result<String, String> {
+        Self::expire(time);
+
+        let session = UPLOADS.with(|uploads| {
+            let mut uploads = uploads.borrow_mut();
+            let session = Self::session_mut(&mut uploads, session_id, caller)?;
+
+            if session.received_bytes() != session.size {
+                return Err(format!(
+                    "Upload incomplete: received {} of {} bytes",
+                    session.received_bytes(),
+                    session.size
+                ));
+            }
+
+            let contiguous = session
+                .chunks
+                .keys()
+                .enumerate()
+                .all(|(position, index)| position as u32 == *index);
+            if !contiguous {
+                return Err("Upload incomplete: missing chunks".to_string());
+            }
+
+            Ok(uploads.remove(session_id).unwrap())
+        })?;
+
+        let chunk_ids = session
+            .chunks
+            .into_values()
+            .map(|(chunk_id, _)| chunk_id)
+            .collect();
+
+        let result = FsStore::commit_chunks(
+            &session.key,
+            session.content_type,
+            session_id.clone(),
+            chunk_ids,
+            &session.sha256,
+            time,
+        );
+
+        if result.is_err() {
+            Self::delete_batch(session_id);
+        }
+
+        result.map(|_| session.key.to_string())
+    }
+
+    pub fn cancel(caller: Principal, session_id: &BatchId) -> Result<(), String> {
+        UPLOADS.with(|uploads| {
+            let mut uploads = uploads.borrow_mut();
+
+            match uploads.get(session_id) {
+                Some(session) if session.owner == caller => {
+                    uploads.remove(session_id);
+                    Ok(())
+                }
+                Some(_) => Err("Upload session belongs to another principal".to_string()),
+                None => Err("Upload session not found".to_string()),
+            }
+        })?;
+
+        Self::delete_batch(session_id);
+
+        Ok(())
+    }
+
+    // Bytes reserved by open sessions, not yet part of the stored files
+    pub fn pending_bytes() -> u64 {
+        UPLOADS.with(|uploads| Self::reserved_bytes(&uploads.borrow()))
+    }
+
+    fn reserved_bytes(uploads: &HashMap<BatchId, UploadSession>) -> u64 {
+        uploads.values().map(|session| session.size).sum()
+    }
+
+    // Drop stale sessions together with their chunks
+    fn expire(time: u64) {
+        let expired: Vec<BatchId> = UPLOADS.with(|uploads| {
+            let mut uploads = uploads.borrow_mut();
+            let expired: Vec<BatchId> = uploads
+                .iter()
+                .filter(|(_, session)| session.expires_at <= time)
+                .map(|(session_id, _)| session_id.clone())
+                .collect();
+
+            for session_id in expired.iter() {
+                uploads.remove(session_id);
+            }
+
+            expired
+        });
+
+        for session_id in expired.iter() {
+            Self::delete_batch(session_id);
+        }
+    }
+
+    fn session_mut<'a>(
+        uploads: &'a mut HashMap<BatchId, UploadSession>,
+        session_id: &BatchId,
+        caller: Principal,
+    ) -> Result<&'a mut UploadSession, String> {
+        let session = uploads
+            .get_mut(session_id)
+            .ok_or_else(|| "Upload session not found".to_string())?;
+
+        if session.owner != caller {
+            return Err("Upload session belongs to another principal".to_string());
+        }
+
+        Ok(session)
+    }
+
+    // The batch may already have been pruned by `create_batch` after expiry
+    fn delete_batch(session_id: &BatchId) {
+        let _ = StateStore::delete_batch(DeleteBatchArguments {
+            batch_id: session_id.clone(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::web3disk::stores::config::Quota;
+    use sha2::{Digest, Sha256};
+
+    const CONTENT: &[u8] = b"first chunk, second chunk";
+
+    fn owner() -> Principal {
+        Principal::from_slice(&[1])
+    }
+
+    fn start(key: &str, content: &[u8]) -> Result<BatchId, String> {
+        start_at(key, content, 0)
+    }
+
+    fn start_at(key: &str, content: &[u8], time: u64) -> Result<BatchId, String> {
+        UploadStore::start(
+            owner(),
+            StartUploadArgs {
+                key: key.to_string(),
+                content_type: "text/plain".to_string(),
+                size: content.len() as u64,
+                sha256: ByteBuf::from(Sha256::digest(content).to_vec()),
+            },
+            time,
+        )
+    }
+
+    fn upload(session_id: &BatchId, index: u32, content: &[u8]) -> Result<(), String> {
+        UploadStore::upload_chunk(
+            owner(),
+            UploadChunkArgs {
+                session_id: session_id.clone(),
+                index,
+                content: ByteBuf::from(content.to_vec()),
+            },
+            0,
+        )
+    }
+
+    #[test]
+    fn resumes_and_commits_an_upload() {
+        let session_id = start("/docs/notes.txt", CONTENT).unwrap();
+
+        // Chunks may arrive in any order and be sent again
+        upload(&session_id, 1, &CONTENT[12..]).unwrap();
+        upload(&session_id, 0, b"lost").unwrap();
+        assert_eq!(
+            UploadStore::commit(owner(), &session_id, 0),
+            Err("Upload incomplete: received 17 of 25 bytes".to_string())
+        );
+        upload(&session_id, 0, &CONTENT[..12]).unwrap();
+
+        let status = UploadStore::status(owner(), &session_id, 0).unwrap();
+        assert_eq!(status.received_chunks, vec![0, 1]);
+        assert_eq!(status.received_bytes, CONTENT.len() as u64);
+
+        assert_eq!(
+            UploadStore::commit(owner(), &session_id, 0),
+            Ok("/docs/notes.txt".to_string())
+        );
+        assert_eq!(UploadStore::pending_bytes(), 0);
+
+        let key = Key::new("/docs/notes.txt");
+        let (file, chunk, chunk_count) = FsStore::read_chunk(&key, 0).unwrap();
+        assert_eq!(file.size, CONTENT.len() as u64);
+        assert_eq!(chunk_count, 2);
+        assert_eq!(chunk.as_ref(), &CONTENT[..12]);
+        assert_eq!(
+            FsStore::read_chunk(&key, 1).unwrap().1.as_ref(),
+            &CONTENT[12..]
+        );
+    }
+
+    #[test]
+    fn requires_contiguous_chunks_within_the_declared_size() {
+        let session_id = start("/file.txt", CONTENT).unwrap();
+
+        assert_eq!(
+            upload(&session_id, 0, &[0; 26]),
+            Err("Chunk exceeds the declared file size".to_string())
+        );
+
+        upload(&session_id, 0, &CONTENT[..12]).unwrap();
+        upload(&session_id, 2, &CONTENT[12..]).unwrap();
+        assert_eq!(
+            UploadStore::commit(owner(), &session_id, 0),
+            Err("Upload incomplete: missing chunks".to_string())
+        );
+    }
+
+    #[test]
+    fn discards_uploads_with_another_sha256() {
+        let session_id = start("/file.txt", CONTENT).unwrap();
+        upload(&session_id, 0, b"a different content of 25").unwrap();
+
+        assert_eq!(
+            UploadStore::commit(owner(), &session_id, 0),
+            Err("sha256 mismatch".to_string())
+        );
+        assert!(UploadStore::status(owner(), &session_id, 0).is_err());
+        assert!(FsStore::stat(&Key::new("/file.txt")).is_err());
+    }
+
+    #[test]
+    fn keeps_sessions_to_their_owner() {
+        let session_id = start("/file.txt", CONTENT).unwrap();
+        let other = Principal::from_slice(&[2]);
+        let error = Err("Upload session belongs to another principal".to_string());
+
+        assert_eq!(
+            UploadStore::status(other, &session_id, 0).map(|_| ()),
+            error
+        );
+        assert_eq!(
+            UploadStore::commit(other, &session_id, 0).map(|_| ()),
+            error
+        );
+        assert_eq!(UploadStore::cancel(other, &session_id), error);
+
+        UploadStore::cancel(owner(), &session_id).unwrap();
+        assert_eq!(
+            UploadStore::cancel(owner(), &session_id),
+            Err("Upload session not found".to_string())
+        );
+    }
+
+    #[test]
+    fn reserves_the_declared_size() {
+        ConfigStore::set_max_upload_bytes(2 * CONTENT.len() as u64);
+
+        start("/a.txt", CONTENT).unwrap();
+        assert_eq!(
+            start("/a.txt", CONTENT),
+            Err("Upload already in progress: /a.txt".to_string())
+        );
+        start("/b.txt", CONTENT).unwrap();
+        assert_eq!(UploadStore::pending_bytes(), 2 * CONTENT.len() as u64);
+        assert_eq!(
+            start("/c.txt", CONTENT),
+            Err("Uploads in progress would exceed the upload limit of 50 bytes".to_string())
+        );
+
+        // Reserved bytes count against the storage quota as well
+        ConfigStore::set_max_upload_bytes(u64::MAX);
+        ConfigStore::set_quota(Quota {
+            max_bytes: Some(2 * CONTENT.len() as u64),
+            low_cycles_threshold: None,
+        });
+        assert!(start("/c.txt", CONTENT)
+            .unwrap_err()
+            .starts_with("Storage quota exceeded"));
+    }
+
+    #[test]
+    fn expires_idle_sessions() {
+        let session_id = start("/file.txt", CONTENT).unwrap();
+        assert!(UploadStore::status(owner(), &session_id, BATCH_EXPIRY_NANOS - 1).is_ok());
+
+        assert_eq!(
+            UploadStore::status(owner(), &session_id, BATCH_EXPIRY_NANOS).map(|_| ()),
+            Err("Upload session not found".to_string())
+        );
+        start_at("/other.txt", CONTENT, BATCH_EXPIRY_NANOS).unwrap();
+        assert_eq!(UploadStore::pending_bytes(), CONTENT.len() as u64);
+    }
+
+    #[test]
+    fn rejects_invalid_uploads() {
+        assert_eq!(
+            start("/.trash/file.txt", CONTENT),
+            Err("Cannot write to the trash: /.trash/file.txt".to_string())
+        );
+        assert_eq!(
+            start("/folder/", CONTENT),
+            Err("Cannot upload to a folder key: /folder/".to_string())
+        );
+        assert_eq!(
+            start("/empty.txt", b""),
+            Err("Empty files must be stored directly".to_string())
+        );
+
+        let long_content_type = UploadStore::start(
+            owner(),
+            StartUploadArgs {
+                key: "/file.txt".to_string(),
+                content_type: "a".repeat(257),
+                size: CONTENT.len() as u64,
+                sha256: ByteBuf::from(Sha256::digest(CONTENT).to_vec()),
+            },
+            0,
+        );
+        assert_eq!(
+            long_content_type,
+            Err("Content type exceeds 256 bytes".to_string())
+        );
+    }
+}
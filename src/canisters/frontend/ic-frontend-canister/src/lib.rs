@@ -6,15 +6,13 @@ fn init(args: Option<AssetCanisterArgs>) {
     ic_certified_assets::init(args);
 }
 
+// Assets are kept in stable memory, `ic_certified_assets` saves the rest of its state there too
 #[pre_upgrade]
 fn pre_upgrade() {
-    ic_cdk::storage::stable_save((ic_certified_assets::pre_upgrade(),))
-        .expect("failed to save stable state");
+    ic_certified_assets::pre_upgrade();
 }
 
 #[post_upgrade]
 fn post_upgrade(args: Option<AssetCanisterArgs>) {
-    let (stable_state,): (ic_certified_assets::StableState,) =
-        ic_cdk::storage::stable_restore().expect("failed to restore stable state");
-    ic_certified_assets::post_upgrade(stable_state, args);
+    ic_certified_assets::post_upgrade(args);
 }
@@ -0,0 +1,21 @@
+use crate::{DownloadOpts, ExportOpts};
+use ic_utils::Canister;
+use slog::Logger;
+
+pub(crate) async fn download(
+    canister: &Canister<'_>,
+    o: &DownloadOpts,
+    logger: &Logger,
+) -> anyhow::Result<()> {
+    ic_asset::download(canister, &o.keys, &o.output_dir, logger).await?;
+    Ok(())
+}
+
+pub(crate) async fn export(
+    canister: &Canister<'_>,
+    o: &ExportOpts,
+    logger: &Logger,
+) -> anyhow::Result<()> {
+    ic_asset::export(canister, &o.directory, logger).await?;
+    Ok(())
+}
@@ -9,6 +9,21 @@ pub(crate) async fn sync(
     logger: &Logger,
 ) -> anyhow::Result<()> {
     let dirs: Vec<&Path> = o.directory.iter().map(|d| d.as_path()).collect();
-    ic_asset::sync(canister, &dirs, logger).await?;
+    if o.dry_run {
+        let diff = ic_asset::compute_sync_diff(canister, &dirs, logger).await?;
+        if o.json {
+            println!("{}", serde_json::to_string_pretty(&diff)?);
+        } else {
+            println!("{}", diff);
+        }
+        return Ok(());
+    }
+
+    if o.watch {
+        ic_asset::watch(canister, &dirs, None, logger).await?;
+        return Ok(());
+    }
+
+    ic_asset::sync(canister, &dirs, None, logger).await?;
     Ok(())
 }
@@ -1,5 +1,6 @@
 mod commands;
 mod support;
+use crate::commands::download::{download, export};
 use crate::commands::list::list;
 use crate::commands::sync::sync;
 use crate::commands::upload::upload;
@@ -48,6 +49,12 @@ enum SubCommand {
     /// Synchronize a directory to the asset canister
     Sync(SyncOpts),
 
+    /// Download assets from the asset canister, verifying their content.
+    Download(DownloadOpts),
+
+    /// Download all assets and an .ic-assets.json with their properties into a directory.
+    Export(ExportOpts),
+
     /// Uploads an asset to an asset canister.
     Upload(UploadOpts),
 }
@@ -65,6 +72,41 @@ struct SyncOpts {
 
     /// The directories to synchronize
     directory: Vec<PathBuf>,
+
+    /// Print the changes a sync would make, without uploading or committing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Print the changes as JSON
+    #[arg(long, requires = "dry_run")]
+    json: bool,
+
+    /// Keep watching the directories after the sync and upload changed files as they change
+    #[arg(long, conflicts_with = "dry_run")]
+    watch: bool,
+}
+
+#[derive(Parser)]
+struct DownloadOpts {
+    /// The canister ID.
+    canister_id: String,
+
+    /// The keys of the assets to download.
+    #[arg(required = true)]
+    keys: Vec<String>,
+
+    /// The directory to write the assets to, each at the path of its key.
+    #[arg(long, default_value = ".")]
+    output_dir: PathBuf,
+}
+
+#[derive(Parser)]
+struct ExportOpts {
+    /// The canister ID.
+    canister_id: String,
+
+    /// The directory to export to.
+    directory: PathBuf,
 }
 
 #[derive(Parser)]
@@ -136,6 +178,20 @@ async fn main() -> anyhow::Result<()> {
                 .build()?;
             sync(&canister, o, &logger).await?;
         }
+        SubCommand::Download(o) => {
+            let canister = ic_utils::Canister::builder()
+                .with_agent(&agent)
+                .with_canister_id(Principal::from_text(&o.canister_id)?)
+                .build()?;
+            download(&canister, o, &logger).await?;
+        }
+        SubCommand::Export(o) => {
+            let canister = ic_utils::Canister::builder()
+                .with_agent(&agent)
+                .with_canister_id(Principal::from_text(&o.canister_id)?)
+                .build()?;
+            export(&canister, o, &logger).await?;
+        }
         SubCommand::Upload(o) => {
             let canister = ic_utils::Canister::builder()
                 .with_agent(&agent)
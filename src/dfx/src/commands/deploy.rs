@@ -1,6 +1,7 @@
 use crate::lib::agent::create_agent_environment;
 use crate::lib::canister_info::CanisterInfo;
 use crate::lib::error::DfxResult;
+use crate::lib::installers::assets::watch_assets;
 use crate::lib::network::network_opt::NetworkOpt;
 use crate::lib::operations::canister::deploy_canisters::deploy_canisters;
 use crate::lib::operations::canister::deploy_canisters::DeployMode::{
@@ -103,6 +104,15 @@ pub struct DeployOpts {
     #[arg(long, conflicts_with("by_proposal"))]
     compute_evidence: bool,
 
+    /// After deploying an asset canister, keep watching its source directories
+    /// and upload changed files as they change.
+    #[arg(
+        long,
+        requires("canister_name"),
+        conflicts_with_all(["by_proposal", "compute_evidence"])
+    )]
+    watch: bool,
+
     /// Transaction timestamp, in nanoseconds, for use in controlling transaction deduplication, default is system time.
     /// https://internetcomputer.org/docs/current/developer-docs/integrations/icrc-1/#transaction-deduplication-
     //TODO(SDK-1331): unhide
@@ -197,6 +207,22 @@ pub fn exec(env: &dyn Environment, opts: DeployOpts) -> DfxResult {
     if matches!(deploy_mode, NormalDeploy | ForceReinstallSingleCanister(_)) {
         display_urls(&env)?;
     }
+
+    if let (true, Some(canister_name)) = (opts.watch, canister_name) {
+        let canister_id = env
+            .get_canister_id_store()?
+            .find(canister_name)
+            .with_context(|| format!("Cannot find canister id for '{}'.", canister_name))?;
+        let canister_info = CanisterInfo::load(&config, canister_name, Some(canister_id))?;
+        if !canister_info.is_assets() {
+            bail!("The --watch flag is only valid for asset canisters.");
+        }
+        runtime.block_on(watch_assets(
+            &canister_info,
+            env.get_agent(),
+            env.get_logger(),
+        ))?;
+    }
     Ok(())
 }
 
@@ -1,9 +1,12 @@
 use crate::lib::canister_info::assets::AssetsCanisterInfo;
 use crate::lib::canister_info::CanisterInfo;
 use crate::lib::error::DfxResult;
+use crate::lib::progress_bar::ProgressBar;
 use anyhow::Context;
 use fn_error_context::context;
 use ic_agent::Agent;
+use ic_asset::{UploadProgress, UploadProgressReporter};
+use indicatif::{HumanBytes, HumanDuration};
 use slog::Logger;
 use std::path::Path;
 
@@ -11,6 +14,7 @@ use std::path::Path;
 pub async fn post_install_store_assets(
     info: &CanisterInfo,
     agent: &Agent,
+    progress: &ProgressBar,
     logger: &Logger,
 ) -> DfxResult {
     let assets_canister_info = info.as_info::<AssetsCanisterInfo>()?;
@@ -27,7 +31,7 @@ pub async fn post_install_store_assets(
         .build()
         .context("Failed to build asset canister caller.")?;
 
-    ic_asset::sync(&canister, &source_paths, logger)
+    ic_asset::sync(&canister, &source_paths, Some(progress), logger)
         .await
         .with_context(|| {
             format!(
@@ -59,7 +63,7 @@ pub async fn prepare_assets_for_proposal(
         .build()
         .context("Failed to build asset canister caller.")?;
 
-    ic_asset::prepare_sync_for_proposal(&canister, &source_paths, logger)
+    ic_asset::prepare_sync_for_proposal(&canister, &source_paths, None, logger)
         .await
         .with_context(|| {
             format!(
@@ -70,3 +74,51 @@ pub async fn prepare_assets_for_proposal(
 
     Ok(())
 }
+
+#[context("Failed to watch assets of canister '{}'.", info.get_name())]
+pub async fn watch_assets(info: &CanisterInfo, agent: &Agent, logger: &Logger) -> DfxResult {
+    let assets_canister_info = info.as_info::<AssetsCanisterInfo>()?;
+    let source_paths = assets_canister_info.get_source_paths();
+    let source_paths: Vec<&Path> = source_paths.iter().map(|p| p.as_path()).collect::<_>();
+
+    let canister_id = info
+        .get_canister_id()
+        .context("Could not find canister ID.")?;
+
+    let canister = ic_utils::Canister::builder()
+        .with_agent(agent)
+        .with_canister_id(canister_id)
+        .build()
+        .context("Failed to build asset canister caller.")?;
+
+    ic_asset::watch(&canister, &source_paths, None, logger)
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to watch assets of canister {}.",
+                canister.canister_id_()
+            )
+        })?;
+
+    Ok(())
+}
+
+impl UploadProgressReporter for ProgressBar {
+    fn report(&self, progress: &UploadProgress) {
+        let eta = progress
+            .eta
+            .map(|eta| format!(", {} left", HumanDuration(eta)))
+            .unwrap_or_default();
+        self.set_message(
+            format!(
+                "Uploading assets: {}/{} files, {}/{}{}",
+                progress.processed_files,
+                progress.total_files,
+                HumanBytes(progress.uploaded_bytes as u64),
+                HumanBytes(progress.total_bytes as u64),
+                eta
+            )
+            .into(),
+        );
+    }
+}
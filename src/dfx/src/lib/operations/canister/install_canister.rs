@@ -237,7 +237,9 @@ The command line value will be used.",
         };
 
         info!(log, "Uploading assets to asset canister...");
-        post_install_store_assets(canister_info, agent, log).await?;
+        let progress = env.new_spinner("Uploading assets".into());
+        post_install_store_assets(canister_info, agent, &progress, log).await?;
+        progress.finish_and_clear();
     }
     if !canister_info.get_post_install().is_empty() {
         let config = env.get_config();